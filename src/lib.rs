@@ -5,26 +5,337 @@
 
 use jieba_rs::Jieba;
 use pyo3::prelude::*;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::{BinaryHeap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::sync::LazyLock;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 
-/// 全局 Jieba 实例（线程安全，延迟初始化）
-static JIEBA: LazyLock<Jieba> = LazyLock::new(Jieba::new);
+/// 全局 Jieba 实例（线程安全，延迟初始化；可通过 configure_global_dict 预先加载自定义词典）
+static JIEBA: OnceLock<Jieba> = OnceLock::new();
+
+/// 获取全局 Jieba 实例，首次调用时使用默认词典初始化
+fn jieba() -> &'static Jieba {
+    JIEBA.get_or_init(Jieba::new)
+}
+
+/// 并行方法使用的线程池 (None 表示使用 rayon 全局线程池)，由 set_num_threads 配置
+static THREAD_POOL: RwLock<Option<Arc<rayon::ThreadPool>>> = RwLock::new(None);
+
+/// 在配置的线程池中执行并行任务
+fn run_parallel<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    let pool = THREAD_POOL
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// 游标跨越 Block 边界时是否预取下一个 Block，由 set_block_prefetch 配置
+static BLOCK_PREFETCH: AtomicBool = AtomicBool::new(false);
+
+/// 预取 Block 各数组的首个缓存行，降低长倒排列表在块边界处的缓存缺失
+///
+/// 只是提示，不影响结果；非 x86_64 平台上为空操作
+#[inline]
+fn prefetch_block(block: &Block) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        // SAFETY: 预取指令不会解引用指针，对任意地址都是安全的
+        unsafe {
+            _mm_prefetch::<_MM_HINT_T0>(block.doc_ids.as_ptr() as *const i8);
+            _mm_prefetch::<_MM_HINT_T0>(block.freqs.as_ptr() as *const i8);
+            if !block.weighted_freqs.is_empty() {
+                _mm_prefetch::<_MM_HINT_T0>(block.weighted_freqs.as_ptr() as *const i8);
+            }
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = block;
+}
+
+/// 文档元数据 (键值对)
+type Metadata = HashMap<String, String>;
+
+/// 单个文档的统计结果: (文档长度, [(词, Posting)])
+type DocPostings<'a> = (u32, Vec<(Cow<'a, str>, Posting)>);
+
+/// export_term 导出的 Block: (last_doc_id, max_score, [(doc_id, freq, doc_len)])
+type ExportedBlock = (u32, f64, Vec<(u32, u32, u32)>);
+
+/// 打分时各命中词所在的 Block: [(词, Block 下标)]
+type BlockHits = Vec<(String, usize)>;
+
+/// 调试信息: 内部 doc_id -> 该文档的 BlockHits
+type BlockTrace = HashMap<u32, BlockHits>;
 
 /// 常量定义
-const BLOCK_SIZE: usize = 128; // BMW 算法块大小
+const BLOCK_SIZE: usize = 128; // 默认 BMW 算法块大小
+const FORMAT_VERSION: u32 = 2; // 索引文件格式版本 (格式不兼容的改动时递增)
+const UNK_TOKEN: &str = "<UNK>"; // 低频单字合并后的共享词
+const FIT_BATCH_SIZE: usize = 4096; // fit 并行分词的批大小 (限制同时驻留的中间结果)
+const SCORE_STRIPE_SIZE: usize = 16384; // get_scores 并行累加时每个线程负责的文档区间大小
+
+/// 停用词处理方式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopwordMode {
+    /// 分词时直接移除停用词
+    #[default]
+    Remove,
+    /// 停用词保留在索引中 (保留位置与文档长度)，但查询时贡献为 0
+    ZeroWeight,
+}
+
+/// 空文档 (全部为空白或控制字符) 的处理方式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmptyDocPolicy {
+    /// 作为长度为 0 的文档保留 (会拉低 avgdl)
+    #[default]
+    Keep,
+    /// fit 时报错并列出空文档的下标
+    Reject,
+    /// 长度为 0 的文档按长度 1 参与归一化
+    MinLength,
+}
+
+/// 查询词全部不在词表中时的兜底排序
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FallbackMode {
+    /// 不兜底，返回空结果
+    #[default]
+    Disabled,
+    /// 按文档长度升序返回 (分数为 0)
+    LengthSorted,
+    /// 按外部 ID 升序返回 (分数为 0)
+    IdSorted,
+}
+
+/// 分词器 (可组合为集成分词，索引与查询使用各分词结果的并集)
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Segmenter {
+    /// jieba 精确模式分词
+    Jieba,
+    /// 汉字重叠二元组 (单个汉字成词时保留单字)，非汉字部分按连续字母数字切分
+    Bigram,
+    /// 按空白切分 (英文等以空格分词的语料，或已预先分好词的文本)
+    Whitespace,
+}
+
+/// jieba 分词模式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CutMode {
+    /// 精确模式
+    #[default]
+    Default,
+    /// 搜索引擎模式: 在精确模式基础上对长词再切分出重叠的短词，召回更高
+    Search,
+    /// 全模式: 输出所有可能成词的片段
+    All,
+}
+
+/// 词的权重方案
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weighting {
+    /// 逆文档频率 (经典 BM25)
+    #[default]
+    Idf,
+    /// 逆集合频率: ln(语料总词数 / 该词总出现次数)
+    Icf,
+}
+
+/// 搜索结果排序方式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// 按分数降序
+    #[default]
+    Relevance,
+    /// 按外部 ID 升序 (插入顺序)
+    AscId,
+    /// 按外部 ID 降序
+    DescId,
+}
+
+/// 次要排序键的使用方式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecondaryMode {
+    /// 按 BM25 分数排序，分数相同时按次要值降序
+    #[default]
+    TieBreak,
+    /// 按 BM25 分数 + weight * 次要值 排序
+    Blend,
+}
+
+/// Block 最大得分
+///
+/// 以原子变量存储，使搜索 (只读借用) 时也能惰性重建过期的上界；
+/// 序列化格式与普通 f64 相同
+#[derive(Debug, Default)]
+struct MaxScore(AtomicU64);
+
+impl MaxScore {
+    fn new(score: f64) -> Self {
+        MaxScore(AtomicU64::new(score.to_bits()))
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(AtomicOrdering::Relaxed))
+    }
+
+    fn set(&self, score: f64) {
+        self.0.store(score.to_bits(), AtomicOrdering::Relaxed);
+    }
+}
+
+impl Serialize for MaxScore {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // f32 可精确表示的值 (f32_scores 模式) 按 f32 写出，减小索引体积
+        let score = self.get();
+        let narrow = score as f32;
+        if narrow as f64 == score {
+            serializer.serialize_f32(narrow)
+        } else {
+            serializer.serialize_f64(score)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MaxScore {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer).map(MaxScore::new)
+    }
+}
 
 /// 倒排索引块
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// 序列化时 doc_ids 按差值编码 (见 StoredBlock)，内存中始终为绝对 ID
+#[derive(Debug, Deserialize)]
+#[serde(from = "StoredBlock<'static>")]
 struct Block {
-    max_score: f64,     // 块内最大可能得分 (BMW 优化核心)
-    last_doc_id: u32,   // 块内最后一个文档ID (Skip List)
-    doc_ids: Vec<u32>,  // 文档ID列表
-    freqs: Vec<u32>,    // 词频列表
-    doc_lens: Vec<u32>, // 文档长度列表 (用于计算 BM25)
+    max_score: MaxScore, // 块内最大可能得分 (BMW 优化核心)
+    last_doc_id: u32,    // 块内最后一个文档ID (Skip List)
+    doc_ids: Vec<u32>,   // 文档ID列表
+    freqs: Vec<u32>,     // 词频列表
+    doc_lens: Vec<u32>, // 已废弃: 文档长度改为查全局 doc_lengths，仅为兼容旧索引文件保留 (始终为空)
+    #[serde(default)]
+    positions: Vec<Vec<u32>>, // 词位置列表 (仅 store_positions 时填充)
+    #[serde(default)]
+    weighted_freqs: Vec<f64>, // 加权词频 (仅 fit_weighted 构建的索引填充，替代 freqs 参与打分)
+}
+
+/// Block 的序列化形式
+///
+/// 块内 doc_id 递增，按与前一个 ID 的差值 (第一个为 ID 本身) 存入 doc_gaps，
+/// 小整数在 MessagePack 中只占 1 字节；doc_ids 位置写空数组。
+/// 旧版本索引文件没有 doc_gaps，doc_ids 为绝对 ID
+#[derive(Serialize, Deserialize)]
+struct StoredBlock<'a> {
+    max_score: MaxScore,
+    last_doc_id: u32,
+    doc_ids: Cow<'a, [u32]>,
+    freqs: Cow<'a, [u32]>,
+    doc_lens: Cow<'a, [u32]>,
+    #[serde(default)]
+    positions: Cow<'a, [Vec<u32>]>,
+    #[serde(default)]
+    weighted_freqs: Cow<'a, [f64]>,
+    #[serde(default)]
+    doc_gaps: Vec<u32>,
+}
+
+impl Serialize for Block {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut prev = 0;
+        let doc_gaps = self
+            .doc_ids
+            .iter()
+            .map(|&doc_id| {
+                let gap = doc_id.wrapping_sub(prev);
+                prev = doc_id;
+                gap
+            })
+            .collect();
+        StoredBlock {
+            max_score: MaxScore::new(self.max_score.get()),
+            last_doc_id: self.last_doc_id,
+            doc_ids: Cow::Borrowed(&[]),
+            freqs: Cow::Borrowed(&self.freqs),
+            doc_lens: Cow::Borrowed(&self.doc_lens),
+            positions: Cow::Borrowed(&self.positions),
+            weighted_freqs: Cow::Borrowed(&self.weighted_freqs),
+            doc_gaps,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<StoredBlock<'_>> for Block {
+    fn from(stored: StoredBlock<'_>) -> Self {
+        let doc_ids = if stored.doc_gaps.is_empty() {
+            stored.doc_ids.into_owned()
+        } else {
+            let mut prev = 0u32;
+            stored
+                .doc_gaps
+                .iter()
+                .map(|&gap| {
+                    prev = prev.wrapping_add(gap);
+                    prev
+                })
+                .collect()
+        };
+        Block {
+            max_score: stored.max_score,
+            last_doc_id: stored.last_doc_id,
+            doc_ids,
+            freqs: stored.freqs.into_owned(),
+            doc_lens: stored.doc_lens.into_owned(),
+            positions: stored.positions.into_owned(),
+            weighted_freqs: stored.weighted_freqs.into_owned(),
+        }
+    }
+}
+
+impl Block {
+    /// 参与打分的词频 (加权索引使用加权词频)
+    fn tf(&self, i: usize) -> f64 {
+        match self.weighted_freqs.get(i) {
+            Some(&weighted) => weighted,
+            None => self.freqs[i] as f64,
+        }
+    }
+
+    /// 把 next 的前 n 个 Posting 移到本块末尾 (next 紧跟在本块之后)
+    ///
+    /// 两块 max_score 的较大值仍是合并后的有效上界，无需重建
+    fn take_front(&mut self, next: &mut Block, n: usize) {
+        self.doc_ids.extend(next.doc_ids.drain(..n));
+        self.freqs.extend(next.freqs.drain(..n));
+        if !next.positions.is_empty() {
+            self.positions.extend(next.positions.drain(..n));
+        }
+        if !next.weighted_freqs.is_empty() {
+            self.weighted_freqs.extend(next.weighted_freqs.drain(..n));
+        }
+        self.last_doc_id = *self.doc_ids.last().unwrap_or(&self.last_doc_id);
+        self.max_score
+            .set(self.max_score.get().max(next.max_score.get()));
+    }
 }
 
 /// 倒排列表
@@ -32,6 +343,226 @@ struct Block {
 struct InvertedList {
     blocks: Vec<Block>,
     doc_count: usize, // 包含该词的文档总数
+    #[serde(default)]
+    collection_freq: u64, // 该词在语料中的总出现次数 (各 Posting 词频之和)
+}
+
+impl InvertedList {
+    /// 结构是否自洽: 各数组长度一致、doc_id 严格递增且小于 corpus_size、doc_count 与 Posting 数相符
+    ///
+    /// 用于 load_lenient 识别格式完好但内容已损坏的倒排列表
+    fn is_consistent(&self, corpus_size: usize) -> bool {
+        let mut prev: Option<u32> = None;
+        let mut postings = 0;
+        for block in &self.blocks {
+            let n = block.doc_ids.len();
+            let parallel = |len: usize| len == 0 || len == n;
+            if n == 0
+                || block.freqs.len() != n
+                || !parallel(block.doc_lens.len())
+                || !parallel(block.positions.len())
+                || !parallel(block.weighted_freqs.len())
+                || block.weighted_freqs.iter().any(|w| !w.is_finite())
+                || block.doc_ids[n - 1] != block.last_doc_id
+            {
+                return false;
+            }
+            for &doc_id in &block.doc_ids {
+                if prev.is_some_and(|p| p >= doc_id) || doc_id as usize >= corpus_size {
+                    return false;
+                }
+                prev = Some(doc_id);
+            }
+            postings += n;
+        }
+        postings == self.doc_count
+    }
+
+    /// 倒排列表占用的堆内存 (字节)
+    fn memory_usage(&self) -> usize {
+        self.blocks.capacity() * size_of::<Block>()
+            + self
+                .blocks
+                .iter()
+                .map(|b| {
+                    (b.doc_ids.capacity() + b.freqs.capacity() + b.doc_lens.capacity())
+                        * size_of::<u32>()
+                        + b.weighted_freqs.capacity() * size_of::<f64>()
+                        + b.positions.capacity() * size_of::<Vec<u32>>()
+                        + b.positions
+                            .iter()
+                            .map(|p| p.capacity() * size_of::<u32>())
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    /// 释放各数组的预留容量
+    fn shrink_to_fit(&mut self) {
+        self.blocks.shrink_to_fit();
+        for block in &mut self.blocks {
+            block.doc_ids.shrink_to_fit();
+            block.freqs.shrink_to_fit();
+            block.doc_lens = Vec::new();
+            block.weighted_freqs.shrink_to_fit();
+            block.positions.shrink_to_fit();
+            block.positions.iter_mut().for_each(Vec::shrink_to_fit);
+        }
+    }
+
+    /// 整个倒排列表的得分上界 (各 Block max_score 的最大值)
+    fn max_score(&self) -> f64 {
+        self.blocks
+            .iter()
+            .map(|b| b.max_score.get())
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    /// 查找文档的 Posting 位置: (Block 下标, 块内下标)
+    fn locate(&self, doc_id: u32) -> Option<(usize, usize)> {
+        let block_idx = self.blocks.partition_point(|b| b.last_doc_id < doc_id);
+        let i = self
+            .blocks
+            .get(block_idx)?
+            .doc_ids
+            .binary_search(&doc_id)
+            .ok()?;
+        Some((block_idx, i))
+    }
+
+    /// 删除指定文档的 Posting，返回是否存在
+    fn remove_doc(&mut self, doc_id: u32) -> bool {
+        let Some((block_idx, i)) = self.locate(doc_id) else {
+            return false;
+        };
+        let block = &mut self.blocks[block_idx];
+        block.doc_ids.remove(i);
+        self.collection_freq -= block.freqs.remove(i) as u64;
+        if !block.positions.is_empty() {
+            block.positions.remove(i);
+        }
+        if !block.weighted_freqs.is_empty() {
+            block.weighted_freqs.remove(i);
+        }
+        match block.doc_ids.last() {
+            Some(&last) => block.last_doc_id = last,
+            None => {
+                self.blocks.remove(block_idx);
+            }
+        }
+        self.doc_count -= 1;
+        true
+    }
+
+    /// 按 doc_id 顺序插入 Posting (文档不能已存在)，块超过 block_size 时对半拆分；
+    /// 追加到列表末尾且最后一个块已满时新建块 (与 fit 的分块一致)
+    ///
+    /// 新块的 max_score 为 0，调用方需要标记 max_score 过期
+    fn insert_posting(
+        &mut self,
+        posting: Posting,
+        block_size: usize,
+        store_positions: bool,
+        weighted_tf: bool,
+    ) {
+        let doc_id = posting.doc_id;
+        let append_full = self
+            .blocks
+            .last()
+            .is_some_and(|b| b.last_doc_id < doc_id && b.doc_ids.len() >= block_size);
+        if self.blocks.is_empty() || append_full {
+            self.blocks.push(Block {
+                max_score: MaxScore::default(),
+                last_doc_id: doc_id,
+                doc_ids: Vec::new(),
+                freqs: Vec::new(),
+                doc_lens: Vec::new(),
+                positions: Vec::new(),
+                weighted_freqs: Vec::new(),
+            });
+        }
+
+        let block_idx = self
+            .blocks
+            .partition_point(|b| b.last_doc_id < doc_id)
+            .min(self.blocks.len() - 1);
+        let block = &mut self.blocks[block_idx];
+        let i = block.doc_ids.partition_point(|&d| d < doc_id);
+        block.doc_ids.insert(i, doc_id);
+        block.freqs.insert(i, posting.freq);
+        if store_positions {
+            block.positions.insert(i, posting.positions);
+        }
+        if weighted_tf {
+            block.weighted_freqs.insert(i, posting.weight);
+        }
+        block.last_doc_id = block.last_doc_id.max(doc_id);
+        self.doc_count += 1;
+        self.collection_freq += posting.freq as u64;
+
+        if block.doc_ids.len() > block_size {
+            let mid = block.doc_ids.len() / 2;
+            let tail = Block {
+                max_score: MaxScore::new(block.max_score.get()),
+                last_doc_id: block.last_doc_id,
+                doc_ids: block.doc_ids.split_off(mid),
+                freqs: block.freqs.split_off(mid),
+                doc_lens: Vec::new(),
+                positions: if block.positions.is_empty() {
+                    Vec::new()
+                } else {
+                    block.positions.split_off(mid)
+                },
+                weighted_freqs: if block.weighted_freqs.is_empty() {
+                    Vec::new()
+                } else {
+                    block.weighted_freqs.split_off(mid)
+                },
+            };
+            block.last_doc_id = block.doc_ids[mid - 1];
+            self.blocks.insert(block_idx + 1, tail);
+        }
+    }
+
+    /// 合并 Posting 数少于 min_len 的 Block: 不足的块从后一个块取 Posting 补满 (至多
+    /// block_size 个)，两侧都接近满的块保持不变。返回减少的 Block 数
+    fn compact(&mut self, min_len: usize, block_size: usize) -> usize {
+        let before = self.blocks.len();
+        let mut compacted: Vec<Block> = Vec::with_capacity(before);
+        for mut block in std::mem::take(&mut self.blocks) {
+            if let Some(prev) = compacted.last_mut() {
+                if prev.doc_ids.len() < min_len || block.doc_ids.len() < min_len {
+                    let n = block_size
+                        .saturating_sub(prev.doc_ids.len())
+                        .min(block.doc_ids.len());
+                    if n > 0 {
+                        prev.take_front(&mut block, n);
+                    }
+                }
+            }
+            if !block.doc_ids.is_empty() {
+                compacted.push(block);
+            }
+        }
+        self.blocks = compacted;
+        before - self.blocks.len()
+    }
+}
+
+/// 构建索引时的临时 Posting
+struct Posting {
+    doc_id: u32,
+    freq: u32,
+    weight: f64, // 加权词频 (未加权时等于 freq)
+    positions: Vec<u32>,
+}
+
+/// 外部注入的全局统计量 (多个索引联合检索时使 idf 与 avgdl 可比)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GlobalStats {
+    corpus_size: usize,
+    avgdl: f64,
+    doc_freqs: HashMap<String, usize>, // 词 -> 全局文档频率
 }
 
 /// 候选文档得分（用于 Top-K 堆）
@@ -45,7 +576,7 @@ struct ScoredDoc {
 impl Eq for ScoredDoc {}
 impl PartialOrd for ScoredDoc {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        other.score.partial_cmp(&self.score)
+        Some(self.cmp(other))
     }
 }
 impl Ord for ScoredDoc {
@@ -60,6 +591,337 @@ impl Ord for ScoredDoc {
     }
 }
 
+/// 搜索选项
+struct SearchOptions {
+    k: usize,                           // 返回结果数
+    require_all_terms: bool,            // 是否要求包含全部查询词
+    phrase_boost: f64,                  // 完整短语命中时的额外加分 (0 表示关闭)
+    max_query_terms: Option<usize>,     // 最多使用的查询词数 (保留 idf 最高的词)
+    max_blocks_scanned: Option<usize>,  // 最多扫描的 Block 数 (超出后提前停止，结果为近似)
+    min_coverage: Option<f64>,          // 命中词 idf 占查询 idf 总和的最低比例
+    length_prior: Option<f64>,          // 文档长度先验权重: 分数加上 ln(doc_len + 1) * 权重
+    term_weights: HashMap<String, f64>, // 查询词权重乘数 (未列出的词为 1.0)
+    query_terms: Option<Vec<String>>,   // 调用方分好的查询词 (给出时不再对 query 分词)
+}
+
+impl SearchOptions {
+    fn new(k: usize) -> Self {
+        SearchOptions {
+            k,
+            require_all_terms: false,
+            phrase_boost: 0.0,
+            max_query_terms: None,
+            max_blocks_scanned: None,
+            min_coverage: None,
+            length_prior: None,
+            term_weights: HashMap::new(),
+            query_terms: None,
+        }
+    }
+}
+
+/// 文本是否只包含空白或控制字符
+fn is_blank(text: &str) -> bool {
+    text.chars().all(|c| c.is_whitespace() || c.is_control())
+}
+
+/// 单次搜索的诊断信息
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Default)]
+pub struct SearchStats {
+    /// 扫描的 Block 数 (被 max_score 剪枝整块跳过的不计)
+    blocks_scanned: usize,
+    /// 完整打分的文档数
+    docs_scored: usize,
+    /// 是否因 max_blocks_scanned 提前停止 (此时结果为近似)
+    truncated: bool,
+}
+
+#[pymethods]
+impl SearchStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchStats(blocks_scanned={}, docs_scored={}, truncated={})",
+            self.blocks_scanned,
+            self.docs_scored,
+            if self.truncated { "True" } else { "False" }
+        )
+    }
+}
+
+/// 单次搜索各阶段耗时 (微秒)
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Default)]
+pub struct SearchTiming {
+    /// 查询分词
+    tokenize_us: f64,
+    /// 查找倒排列表、计算 idf 并建立游标
+    setup_us: f64,
+    /// 遍历倒排列表与打分 (含 Top-K 维护)
+    traversal_us: f64,
+    /// 内部 ID 映射为外部 ID 等结果整理
+    assembly_us: f64,
+    /// 总耗时
+    total_us: f64,
+}
+
+#[pymethods]
+impl SearchTiming {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchTiming(tokenize_us={:.1}, setup_us={:.1}, traversal_us={:.1}, assembly_us={:.1}, total_us={:.1})",
+            self.tokenize_us, self.setup_us, self.traversal_us, self.assembly_us, self.total_us
+        )
+    }
+}
+
+/// load_lenient 的恢复报告
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    /// 完整恢复的词数
+    recovered_terms: usize,
+    /// 词本身可读、但倒排列表已损坏而被丢弃的词
+    dropped_terms: Vec<String>,
+    /// 连词本身也无法读取而被丢弃的词条数
+    unreadable_terms: usize,
+}
+
+#[pymethods]
+impl LoadReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "LoadReport(recovered_terms={}, dropped_terms={}, unreadable_terms={})",
+            self.recovered_terms,
+            self.dropped_terms.len(),
+            self.unreadable_terms
+        )
+    }
+}
+
+/// search_json 输出的单条结果
+#[derive(Serialize)]
+struct JsonHit {
+    id: u64,
+    score: f64,
+}
+
+/// 按小数位数四舍五入 (仅用于序列化输出)
+fn round_score(score: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (score * factor).round() / factor
+}
+
+/// 是否为汉字 (CJK 统一表意文字及扩展 A 区)
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}')
+}
+
+/// 将单个文档的 Postings 按文档顺序追加到临时索引 (仅在首次出现该词时分配 String 键)
+fn merge_postings(
+    temp_index: &mut HashMap<String, Vec<Posting>>,
+    postings: Vec<(Cow<'_, str>, Posting)>,
+) {
+    for (term, posting) in postings {
+        match temp_index.get_mut(term.as_ref()) {
+            Some(postings) => postings.push(posting),
+            None => {
+                temp_index.insert(term.into_owned(), vec![posting]);
+            }
+        }
+    }
+}
+
+/// 分词器: 将文本切分为原文本的切片
+///
+/// 返回切片而非新字符串，调用方可借用原文本，集成分词时也可由指针偏移得到词的起始位置。
+/// 新的分词方式实现该 trait 并在 Segmenter 中增加对应的选项
+trait Tokenizer: Send + Sync {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str>;
+}
+
+/// jieba 分词 (jieba 为 None 时使用全局实例，否则为由 user_dict 构建的实例)
+struct JiebaTokenizer {
+    jieba: Option<Arc<Jieba>>,
+    cut_mode: CutMode,
+    use_hmm: bool,
+}
+
+impl Tokenizer for JiebaTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let jieba = self.jieba.as_deref().unwrap_or_else(|| jieba());
+        match self.cut_mode {
+            CutMode::Default => jieba.cut(text, self.use_hmm),
+            CutMode::Search => jieba.cut_for_search(text, self.use_hmm),
+            CutMode::All => jieba.cut_all(text),
+        }
+    }
+}
+
+/// 空白分词
+struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        text.split_whitespace().collect()
+    }
+}
+
+/// 二元组分词 (见 bigrams)
+struct BigramTokenizer;
+
+impl Tokenizer for BigramTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        bigrams(text)
+    }
+}
+
+/// 二元组切分: 连续汉字切为重叠二元组 (单个汉字保留单字)，连续字母数字整体成词
+fn bigrams(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if is_cjk(c) {
+            // 收集连续汉字的字节边界
+            let mut bounds = vec![start];
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_cjk(c) {
+                    break;
+                }
+                chars.next();
+                bounds.push(i + c.len_utf8());
+            }
+            if bounds.len() == 2 {
+                tokens.push(&text[bounds[0]..bounds[1]]);
+            } else {
+                tokens.extend(bounds.windows(3).map(|w| &text[w[0]..w[2]]));
+            }
+        } else if c.is_alphanumeric() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if is_cjk(c) || !c.is_alphanumeric() {
+                    break;
+                }
+                chars.next();
+                end = i + c.len_utf8();
+            }
+            tokens.push(&text[start..end]);
+        } else {
+            chars.next();
+        }
+    }
+    tokens
+}
+
+/// 判断各词的位置列表 (按查询顺序) 能否组成连续短语
+fn is_phrase(positions: &[&[u32]]) -> bool {
+    let Some((first, rest)) = positions.split_first() else {
+        return false;
+    };
+    first.iter().any(|&start| {
+        rest.iter()
+            .enumerate()
+            .all(|(i, p)| p.binary_search(&(start + i as u32 + 1)).is_ok())
+    })
+}
+
+/// 去除重复的查询词，保留首次出现的顺序 (重复的查询词只计分一次)
+fn distinct_terms<'a>(tokens: Vec<Cow<'a, str>>) -> Vec<Cow<'a, str>> {
+    let mut seen = HashSet::new();
+    tokens
+        .into_iter()
+        .filter(|token| seen.insert(token.clone()))
+        .collect()
+}
+
+/// 判断各词的位置列表 (按词顺序) 能否落在 slop 窗口内
+///
+/// 窗口跨度按 Lucene 的定义: 最后位置 - 最先位置 - (词数 - 1) <= slop，slop 为 0 时即相邻。
+/// ordered 为 true 时要求各词按给定顺序出现
+fn within_window(positions: &[&[u32]], slop: u32, ordered: bool) -> bool {
+    if positions.iter().any(|p| p.is_empty()) {
+        return false;
+    }
+    let extra = positions.len() as u32 - 1;
+    if ordered {
+        // 以第一个词的每个位置为起点，贪心选择后续各词中最近的后继位置
+        let Some((first, rest)) = positions.split_first() else {
+            return false;
+        };
+        return first.iter().any(|&start| {
+            let mut prev = start;
+            for p in rest {
+                let i = p.partition_point(|&x| x <= prev);
+                match p.get(i) {
+                    Some(&next) => prev = next,
+                    None => return false,
+                }
+            }
+            prev - start - extra <= slop
+        });
+    }
+
+    // 无序: 多路归并求覆盖所有词的最小区间
+    let mut idx = vec![0; positions.len()];
+    loop {
+        let mut min = (u32::MAX, 0);
+        let mut max = 0;
+        for (i, p) in positions.iter().enumerate() {
+            let pos = p[idx[i]];
+            if pos < min.0 {
+                min = (pos, i);
+            }
+            max = max.max(pos);
+        }
+        if max - min.0 <= slop + extra {
+            return true;
+        }
+        idx[min.1] += 1;
+        if idx[min.1] >= positions[min.1].len() {
+            return false;
+        }
+    }
+}
+
+/// Top-K 收集器（内部为最小堆）
+struct TopK {
+    heap: BinaryHeap<ScoredDoc>,
+    k: usize,
+}
+
+impl TopK {
+    /// max_len: 候选文档数上限 (通常为 corpus_size)，预分配不超过它，避免过大的 k 申请巨量内存
+    fn new(k: usize, max_len: usize) -> Self {
+        TopK {
+            heap: BinaryHeap::with_capacity(k.min(max_len)),
+            k,
+        }
+    }
+
+    /// 该分数能否进入当前 Top-K
+    fn accepts(&self, score: f64) -> bool {
+        if self.heap.len() < self.k {
+            return true;
+        }
+        matches!(self.heap.peek(), Some(min_node) if score > min_node.score)
+    }
+
+    fn push(&mut self, score: f64, doc_id: u32) {
+        if !self.accepts(score) {
+            return;
+        }
+        if self.heap.len() >= self.k {
+            self.heap.pop();
+        }
+        self.heap.push(ScoredDoc { score, doc_id });
+    }
+
+    /// 按分数降序输出
+    fn into_sorted_vec(self) -> Vec<ScoredDoc> {
+        self.heap.into_sorted_vec()
+    }
+}
+
 /// BM25 中文文本搜索算法
 #[pyclass]
 #[derive(Serialize, Deserialize)]
@@ -72,15 +934,166 @@ pub struct BM25 {
     index: HashMap<String, InvertedList>,
     doc_lengths: Vec<u32>, // 全局文档长度
     doc_ids: Vec<u64>,     // 映射: 内部ID(usize) -> 外部ID(u64)
+    #[serde(default)]
+    metadata: Vec<Metadata>, // 文档元数据 (与内部ID平行，未提供时为空)
+    #[serde(default)]
+    store_positions: bool, // 是否存储词位置 (短语匹配需要)
+    #[serde(default)]
+    stopwords: HashSet<String>, // 停用词表
+    #[serde(default)]
+    stopword_mode: StopwordMode,
+    #[serde(default)]
+    unk_df_threshold: Option<usize>, // 文档频率低于该值的单字合并到 <UNK>
+    #[serde(default)]
+    unk_terms: HashSet<String>, // 已合并到 <UNK> 的词 (查询时同样映射)
+    #[serde(default)]
+    doc_weights: Vec<f64>, // 文档分数乘数 (与内部ID平行，为空表示全部为 1.0)
+    #[serde(default)]
+    max_scores_dirty: AtomicBool, // Block max_score 是否已过期 (修改打分相关数据后置位)
+    #[serde(default)]
+    f32_scores: bool, // Block max_score 与返回分数使用 f32 精度
+    #[serde(default)]
+    global_stats: Option<GlobalStats>, // 注入的全局统计量 (为空时使用本索引统计)
+    #[serde(default)]
+    empty_doc_policy: EmptyDocPolicy,
+    #[serde(default)]
+    empty_query_fallback: FallbackMode,
+    #[serde(default)]
+    weighted_tf: bool, // 索引是否由 fit_weighted 构建 (Block 存储加权词频)
+    #[serde(default)]
+    binary_tf: bool, // 词频截断为 1 (按词是否出现打分)
+    #[serde(default)]
+    idf_length_coupling: Option<f64>, // 长度归一化强度随 idf 衰减的系数 (None 为经典 BM25)
+    #[serde(default)]
+    segmenters: Vec<Segmenter>, // 集成分词使用的分词器 (为空表示仅使用 jieba)
+    #[serde(default = "default_use_idf")]
+    use_idf: bool, // 为 false 时 idf 恒为 1.0 (纯词频排序)
+    #[serde(default)]
+    weighting: Weighting,
+    #[serde(default)]
+    chunk_size: Option<usize>, // fit 时的分块大小 (分块索引中多个内部文档共享同一外部 ID)
+    #[serde(default)]
+    query_lowercase: Option<bool>, // 查询侧是否转为小写 (None 表示与 lowercase 一致)
+    #[serde(default)]
+    idf_overrides: HashMap<String, f64>, // 外部注入的 idf (优先于本索引计算的 idf)
+    #[serde(default)]
+    term_boosts: HashMap<String, f64>, // 词级权重乘数 (出现在任何查询中都生效)
+    #[serde(default)]
+    deleted: HashSet<u32>, // 已删除文档的内部ID (墓碑，compact 后清空)
+    #[serde(default)]
+    user_dict: String, // 实例级自定义词典内容 (随索引保存，加载后重建分词器)
+    #[serde(skip)]
+    custom_jieba: Option<Arc<Jieba>>, // 由 user_dict 构建的分词器 (None 表示使用全局实例)
+    #[serde(skip)]
+    tokenizers: Vec<Box<dyn Tokenizer>>, // 按 segmenters 等分词设置构建 (加载后重建)
+    #[serde(skip)]
+    id_map: HashMap<u64, usize>, // 外部ID -> 第一个未删除的内部ID (由 doc_ids 重建)
+    #[serde(skip)]
+    next_id: Option<u64>, // add_document 的默认 ID: 最大外部ID + 1 (None 表示已用到 u64::MAX)
+    #[serde(default)]
+    char_fallback: bool, // 不在词表中的多字查询词拆分为单字检索
+    #[serde(default)]
+    cut_mode: CutMode, // jieba 分词模式 (索引与查询一致)
+    #[serde(default)]
+    use_hmm: bool, // jieba 使用 HMM 识别未登录词
+    #[serde(default)]
+    field_weights: HashMap<String, f64>, // fit_fields 的字段权重 (非空表示 BM25F 索引)
+    #[serde(default = "default_block_size")]
+    block_size: usize, // 每个 Block 的 Posting 数上限 (BMW 跳块粒度)
+    #[serde(default)]
+    version: u32, // 索引格式版本 (0 表示没有版本字段的旧文件，加载时升级)
+}
+
+/// 旧版本索引文件没有 use_idf 字段，按开启处理
+fn default_use_idf() -> bool {
+    true
+}
+
+/// 旧版本索引文件没有 block_size 字段，按固定的 128 分块
+fn default_block_size() -> usize {
+    BLOCK_SIZE
 }
 
 #[pymethods]
 impl BM25 {
     /// 创建新的 BM25 实例
+    ///
+    /// store_positions: 是否存储词位置 (短语加权需要，会增大索引)
+    /// stopwords: 可选的停用词列表
+    /// stopword_mode: 停用词处理方式 (Remove: 分词时移除; ZeroWeight: 保留在索引中但不参与打分)
+    /// unk_df_threshold: 文档频率低于该值的单字在 fit 时合并为共享的 <UNK> 词，用于压缩词表
+    /// f32_scores: Block max_score 与返回分数使用 f32 精度 (内部仍以 f64 计算)，减小索引体积
+    /// empty_doc_policy: 空文档 (全部为空白或控制字符) 的处理方式 (Keep / Reject / MinLength)
+    /// empty_query_fallback: 查询词全部不在词表中时的兜底排序 (Disabled / LengthSorted / IdSorted)
+    /// binary_tf: 每个词在文档中只计一次 (按是否出现打分)，适合短文本或标签类字段
+    /// idf_length_coupling: 实验选项，b 随词的 idf 调整: b * (1 - coupling * idf / max_idf)，
+    /// 稀有词受文档长度惩罚更小；None 为经典 BM25
+    /// segmenters: 集成分词，如 [Segmenter.Jieba, Segmenter.Bigram] 同时索引词语与二元组
+    /// (召回更高，索引更大)；默认仅使用 jieba
+    /// use_idf: 为 False 时不使用 idf 加权 (idf 恒为 1.0)，常见词与稀有词每次出现贡献相同
+    /// weighting: 词权重方案，Idf (逆文档频率) 或 Icf (逆集合频率，按词的总出现次数衡量稀有度)
+    /// query_lowercase: 单独设置查询侧是否转为小写 (默认与 lowercase 一致)。用于索引已预先
+    /// 规范化、查询为原始输入的场景；两侧不一致时可能出现查询词无法匹配索引词的情况
+    /// char_fallback: 不在词表中的多字查询词拆分为单字参与检索 (各字之间为 OR)，
+    /// 词表中已有的词不受影响，用于提高未登录词的召回
+    /// cut_mode: jieba 分词模式 (Default 精确模式 / Search 搜索引擎模式 / All 全模式)。
+    /// 改变分词边界，切换模式后需要重新 fit
+    /// use_hmm: jieba 使用 HMM 识别词典外的新词 (否则未登录词多被切为单字)，
+    /// 适合用户生成内容等新词较多的语料；全模式下不使用 HMM
+    /// block_size: 每个 Block 的 Posting 数上限。块越小 BMW 跳块越精细，但 max_score
+    /// 的存储与计算开销越大；随索引保存
     #[new]
-    #[pyo3(signature = (k1=1.5, b=0.75, lowercase=false))]
-    pub fn new(k1: f64, b: f64, lowercase: bool) -> Self {
-        BM25 {
+    #[pyo3(signature = (
+        k1=1.5,
+        b=0.75,
+        lowercase=false,
+        store_positions=false,
+        stopwords=None,
+        stopword_mode=StopwordMode::Remove,
+        unk_df_threshold=None,
+        f32_scores=false,
+        empty_doc_policy=EmptyDocPolicy::Keep,
+        empty_query_fallback=FallbackMode::Disabled,
+        binary_tf=false,
+        idf_length_coupling=None,
+        segmenters=None,
+        use_idf=true,
+        weighting=Weighting::Idf,
+        query_lowercase=None,
+        char_fallback=false,
+        cut_mode=CutMode::Default,
+        use_hmm=false,
+        block_size=BLOCK_SIZE,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        k1: f64,
+        b: f64,
+        lowercase: bool,
+        store_positions: bool,
+        stopwords: Option<Vec<String>>,
+        stopword_mode: StopwordMode,
+        unk_df_threshold: Option<usize>,
+        f32_scores: bool,
+        empty_doc_policy: EmptyDocPolicy,
+        empty_query_fallback: FallbackMode,
+        binary_tf: bool,
+        idf_length_coupling: Option<f64>,
+        segmenters: Option<Vec<Segmenter>>,
+        use_idf: bool,
+        weighting: Weighting,
+        query_lowercase: Option<bool>,
+        char_fallback: bool,
+        cut_mode: CutMode,
+        use_hmm: bool,
+        block_size: usize,
+    ) -> PyResult<Self> {
+        if block_size == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "block_size must be positive",
+            ));
+        }
+        let mut bm25 = BM25 {
             k1,
             b,
             lowercase,
@@ -89,6 +1102,87 @@ impl BM25 {
             index: HashMap::new(),
             doc_lengths: Vec::new(),
             doc_ids: Vec::new(),
+            metadata: Vec::new(),
+            store_positions,
+            stopwords: HashSet::new(),
+            stopword_mode,
+            unk_df_threshold,
+            unk_terms: HashSet::new(),
+            doc_weights: Vec::new(),
+            max_scores_dirty: AtomicBool::new(false),
+            f32_scores,
+            global_stats: None,
+            empty_doc_policy,
+            empty_query_fallback,
+            weighted_tf: false,
+            binary_tf,
+            idf_length_coupling,
+            segmenters: segmenters.unwrap_or_default(),
+            use_idf,
+            weighting,
+            chunk_size: None,
+            query_lowercase,
+            idf_overrides: HashMap::new(),
+            term_boosts: HashMap::new(),
+            deleted: HashSet::new(),
+            user_dict: String::new(),
+            custom_jieba: None,
+            tokenizers: Vec::new(),
+            id_map: HashMap::new(),
+            next_id: Some(0),
+            char_fallback,
+            cut_mode,
+            use_hmm,
+            field_weights: HashMap::new(),
+            block_size,
+            version: FORMAT_VERSION,
+        };
+        bm25.set_stopwords(stopwords.unwrap_or_default());
+        bm25.rebuild_tokenizers();
+        Ok(bm25)
+    }
+
+    /// 为本实例加载自定义词典 (jieba 词典格式，在默认词典基础上追加，可多次调用)
+    ///
+    /// 与 configure_global_dict 不同，只影响本实例的 fit 与 search 分词，
+    /// 词典内容随索引保存，加载后分词保持一致。修改后需要重新 fit
+    pub fn load_user_dict(&mut self, path: &str) -> PyResult<()> {
+        let mut user_dict = self.user_dict.clone();
+        user_dict.push_str(&std::fs::read_to_string(path)?);
+        if !user_dict.ends_with('\n') {
+            user_dict.push('\n');
+        }
+        self.custom_jieba = Some(Arc::new(build_jieba(&user_dict)?));
+        self.user_dict = user_dict;
+        self.rebuild_tokenizers();
+        Ok(())
+    }
+
+    /// 设置停用词表 (开启 lowercase 时停用词同样转为小写)
+    ///
+    /// 注意: Remove 模式下停用词在分词阶段生效，修改后需要重新 fit
+    pub fn set_stopwords(&mut self, words: Vec<String>) {
+        self.stopwords = words
+            .into_iter()
+            .map(|w| if self.lowercase { w.to_lowercase() } else { w })
+            .collect();
+    }
+
+    /// 返回文本的分词结果 (用于调试分词、停用词与自定义词典)
+    ///
+    /// 与 fit 使用的分词完全相同 (含 lowercase 与 Remove 模式的停用词过滤)；
+    /// query=True 时返回 search 使用的查询分词 (按 query_lowercase，并把已合并的词映射为 <UNK>)
+    #[pyo3(signature = (text, query=false))]
+    pub fn analyze(&self, text: &str, query: bool) -> Vec<String> {
+        if query {
+            self.query_tokens(text)
+                .into_iter()
+                .map(Cow::into_owned)
+                .collect()
+        } else {
+            self.tokenize(text, self.lowercase)
+                .map(Cow::into_owned)
+                .collect()
         }
     }
 
@@ -96,271 +1190,3777 @@ impl BM25 {
     ///
     /// documents: 文档内容列表
     /// ids: 可选的文档 ID 列表 (必须与 documents 长度一致)
-    #[pyo3(signature = (documents, ids=None))]
-    pub fn fit(&mut self, documents: Vec<String>, ids: Option<Vec<u64>>) -> PyResult<()> {
-        if let Some(ref external_ids) = ids {
-            if external_ids.len() != documents.len() {
-                return Err(pyo3::exceptions::PyValueError::new_err(
-                    "documents and ids must have the same length",
-                ));
-            }
-        }
+    /// metadata: 可选的文档元数据列表 (必须与 documents 长度一致)
+    /// chunk_size: 按词数切分长文档，每个分块作为独立的内部文档索引 (段落检索)，
+    /// 各分块沿用原文档的外部 ID 与元数据，搜索结果中同一 ID 可能出现多次
+    ///
+    /// 分词与建索引期间释放 GIL，其他 Python 线程可以同时运行
+    #[pyo3(signature = (documents, ids=None, metadata=None, chunk_size=None))]
+    pub fn fit(
+        &mut self,
+        py: Python<'_>,
+        documents: Vec<String>,
+        ids: Option<Vec<u64>>,
+        metadata: Option<Vec<Metadata>>,
+        chunk_size: Option<usize>,
+    ) -> PyResult<()> {
+        py.detach(|| self.fit_documents(documents, ids, metadata, chunk_size))
+    }
 
-        self.corpus_size = documents.len();
-        self.index.clear();
-        self.doc_lengths.clear();
-        self.doc_ids.clear();
+    /// 从文本文件训练模型: 每行一个文档，按行号顺序分配 ID (从 0 开始)
+    ///
+    /// 逐批读取并分词，不在内存中保留全部原文，峰值内存接近索引本身的大小。
+    /// 空行按 empty_doc_policy 处理。读取与建索引期间释放 GIL
+    pub fn fit_from_file(&mut self, py: Python<'_>, path: &str) -> PyResult<()> {
+        py.detach(|| {
+            let mut lines = BufReader::new(File::open(path)?).lines();
+            self.fit_streaming(|| {
+                lines
+                    .by_ref()
+                    .take(FIT_BATCH_SIZE)
+                    .map(|line| Ok((None, line?)))
+                    .collect()
+            })
+        })
+    }
+
+    /// 同 fit_from_file，每行为 "ID<TAB>文本" (文本中可以再包含制表符)
+    pub fn fit_from_file_with_ids(&mut self, py: Python<'_>, path: &str) -> PyResult<()> {
+        py.detach(|| {
+            let mut lines = BufReader::new(File::open(path)?).lines().enumerate();
+            self.fit_streaming(|| {
+                lines
+                    .by_ref()
+                    .take(FIT_BATCH_SIZE)
+                    .map(|(line_no, line)| {
+                        let line = line?;
+                        let parsed = line
+                            .split_once('\t')
+                            .and_then(|(id, text)| Some((id.trim().parse::<u64>().ok()?, text)));
+                        match parsed {
+                            Some((id, text)) => Ok((Some(id), text.to_string())),
+                            None => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                                "line {}: expected <id>\\t<text> with an unsigned integer id",
+                                line_no + 1
+                            ))),
+                        }
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// 从任意 Python 可迭代对象 (如生成器、数据库游标) 逐个读取文档训练模型
+    ///
+    /// 每次取至多一批文档，分词统计后即丢弃原文，内存与索引大小成正比而非语料大小；
+    /// 结果与对同样的文档调用 fit 一致。ids 须与文档数一致。分词期间释放 GIL
+    #[pyo3(signature = (iterator, ids=None))]
+    pub fn fit_iter(
+        &mut self,
+        py: Python<'_>,
+        iterator: &Bound<'_, PyAny>,
+        ids: Option<Vec<u64>>,
+    ) -> PyResult<()> {
+        let iterator = iterator.try_iter()?.unbind();
+        let mut consumed = 0;
+        py.detach(|| {
+            self.fit_streaming(|| {
+                Python::attach(|py| {
+                    let mut batch = Vec::with_capacity(FIT_BATCH_SIZE);
+                    for document in iterator.bind(py).clone().take(FIT_BATCH_SIZE) {
+                        let id = match &ids {
+                            Some(ids) => Some(*ids.get(consumed).ok_or_else(|| {
+                                pyo3::exceptions::PyValueError::new_err(
+                                    "documents and ids must have the same length",
+                                )
+                            })?),
+                            None => None,
+                        };
+                        batch.push((id, document?.extract::<String>()?));
+                        consumed += 1;
+                    }
+                    if batch.len() < FIT_BATCH_SIZE
+                        && ids.as_ref().is_some_and(|ids| ids.len() != consumed)
+                    {
+                        return Err(pyo3::exceptions::PyValueError::new_err(
+                            "documents and ids must have the same length",
+                        ));
+                    }
+                    Ok(batch)
+                })
+            })
+        })
+    }
+
+    /// 使用预先加权的词列表训练模型 (不经过分词)
+    ///
+    /// docs_with_weights: 每个文档为 [(词, 权重)] 列表。同一词的权重累加为加权词频，
+    /// 替代原始词频参与 BM25 (饱和函数作用于加权词频)；文档长度为条目数。
+    /// 词不做分词与停用词过滤，开启 lowercase 时同样转为小写
+    #[pyo3(signature = (docs_with_weights, ids=None, metadata=None))]
+    pub fn fit_weighted(
+        &mut self,
+        docs_with_weights: Vec<Vec<(String, f64)>>,
+        ids: Option<Vec<u64>>,
+        metadata: Option<Vec<Metadata>>,
+    ) -> PyResult<()> {
+        if let Some(ref external_ids) = ids {
+            if external_ids.len() != docs_with_weights.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "docs_with_weights and ids must have the same length",
+                ));
+            }
+        }
+        if let Some(ref metadata) = metadata {
+            if metadata.len() != docs_with_weights.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "docs_with_weights and metadata must have the same length",
+                ));
+            }
+        }
+        if docs_with_weights
+            .iter()
+            .flatten()
+            .any(|&(_, weight)| !weight.is_finite() || weight < 0.0)
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "term weights must be finite non-negative numbers",
+            ));
+        }
+        let empty: Vec<usize> = docs_with_weights
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| doc.iter().all(|(term, _)| is_blank(term)))
+            .map(|(i, _)| i)
+            .collect();
+        self.check_empty_documents(&empty)?;
+
+        self.reset_for_fit(docs_with_weights.len(), ids, metadata);
+        self.weighted_tf = true;
+
+        let mut temp_index: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut total_length: u64 = 0;
+
+        for (doc_id, doc) in docs_with_weights.iter().enumerate() {
+            let tokens = doc
+                .iter()
+                .filter(|(term, _)| !is_blank(term))
+                .map(|(term, weight)| {
+                    let term = if self.lowercase && term.chars().any(char::is_uppercase) {
+                        Cow::Owned(term.to_lowercase())
+                    } else {
+                        Cow::Borrowed(term.as_str())
+                    };
+                    (term, *weight)
+                });
+            let (doc_len, postings) = self.doc_postings(doc_id as u32, tokens);
+
+            self.doc_lengths.push(doc_len);
+            total_length += doc_len as u64;
+            merge_postings(&mut temp_index, postings);
+        }
+
+        self.finish_fit(temp_index, total_length);
+        Ok(())
+    }
+
+    /// 按字段训练 BM25F 模型 (如标题与正文使用不同权重)
+    ///
+    /// documents: 每个文档为 {字段名: 文本}，缺失的字段视为空
+    /// field_weights: 字段权重，不在其中的字段不参与索引
+    /// 每个字段按自身的平均长度做长度归一化 (共用 b)，词频按
+    /// sum(weight * tf / (1 - b + b * len / avg_len)) 合并后再经过 k1 饱和；
+    /// 文档长度为各索引字段的词数之和。BM25F 索引不支持 add_document 与 update_document，
+    /// idf_length_coupling 不生效
+    #[pyo3(signature = (documents, field_weights, ids=None, metadata=None))]
+    pub fn fit_fields(
+        &mut self,
+        documents: Vec<HashMap<String, String>>,
+        field_weights: HashMap<String, f64>,
+        ids: Option<Vec<u64>>,
+        metadata: Option<Vec<Metadata>>,
+    ) -> PyResult<()> {
+        if field_weights.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "field_weights must not be empty",
+            ));
+        }
+        if field_weights
+            .values()
+            .any(|&weight| !weight.is_finite() || weight < 0.0)
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "field weights must be finite non-negative numbers",
+            ));
+        }
+        if let Some(ref external_ids) = ids {
+            if external_ids.len() != documents.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "documents and ids must have the same length",
+                ));
+            }
+        }
+        if let Some(ref metadata) = metadata {
+            if metadata.len() != documents.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "documents and metadata must have the same length",
+                ));
+            }
+        }
+        // 字段按名称排序，保证位置编号与结果稳定
+        let mut fields: Vec<(&str, f64)> = field_weights
+            .iter()
+            .map(|(field, &weight)| (field.as_str(), weight))
+            .collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+
+        let empty: Vec<usize> = documents
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| fields.iter().all(|&(f, _)| is_blank(field_text(doc, f))))
+            .map(|(i, _)| i)
+            .collect();
+        self.check_empty_documents(&empty)?;
+
+        // 1. 分词并统计各字段的平均长度
+        let tokenized: Vec<Vec<Vec<Cow<str>>>> = documents
+            .iter()
+            .map(|doc| {
+                fields
+                    .iter()
+                    .map(|&(f, _)| self.tokenize(field_text(doc, f), self.lowercase).collect())
+                    .collect()
+            })
+            .collect();
+        let avg_lens: Vec<f64> = (0..fields.len())
+            .map(|f| {
+                let total: usize = tokenized.iter().map(|doc| doc[f].len()).sum();
+                total as f64 / documents.len().max(1) as f64
+            })
+            .collect();
+
+        self.reset_for_fit(documents.len(), ids, metadata);
+        self.weighted_tf = true;
+
+        // 2. 各字段的词依次拼接，每次出现的权重为 weight / 字段长度归一化因子，
+        // 累加后即为 BM25F 的合并词频
+        let mut temp_index: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut total_length: u64 = 0;
+        for (doc_id, doc) in tokenized.into_iter().enumerate() {
+            let tokens = doc.into_iter().enumerate().flat_map(|(f, tokens)| {
+                let norm = if avg_lens[f] > 0.0 {
+                    1.0 - self.b + self.b * tokens.len() as f64 / avg_lens[f]
+                } else {
+                    1.0
+                };
+                let weight = fields[f].1 / norm;
+                tokens.into_iter().map(move |t| (t, weight))
+            });
+            let (doc_len, postings) = self.doc_postings(doc_id as u32, tokens);
+
+            self.doc_lengths.push(doc_len);
+            total_length += doc_len as u64;
+            merge_postings(&mut temp_index, postings);
+        }
+
+        self.finish_fit(temp_index, total_length);
+        self.field_weights = field_weights;
+        Ok(())
+    }
+
+    /// 搜索与查询最相关的文档 (Block-Max WAND)
+    /// 返回: List[(doc_id, score)]，其中 doc_id 是外部 ID (u64)
+    /// 检索期间释放 GIL，其他 Python 线程可以同时运行
+    ///
+    /// require_all_terms: 为 True 时只返回包含全部查询词的文档 (合取查询)
+    /// phrase_boost: 文档包含完整查询短语 (查询词连续出现) 时额外加上的分数，
+    ///               需要 store_positions=True，0 表示关闭
+    /// sort_by: 结果排序方式。仍按相关性选出 Top-K (分页时为当前页)，之后再按 ID 重新排序
+    /// max_query_terms: 最多使用的查询词数，只保留 idf 最高的词，用于限制长查询的开销
+    /// max_blocks_scanned: 最多扫描的 Block 数 (所有查询词合计)，超出后返回目前为止的
+    /// Top-K。结果为近似: 后续 Block 中更高分的文档会被遗漏，适合作为延迟上限
+    /// min_coverage: 文档命中的查询词 idf 之和占全部查询词 idf 之和的最低比例 (0~1)，
+    /// 用于过滤只命中常见词的文档；单词查询的覆盖率恒为 1.0
+    /// length_prior: 实验选项，命中文档的分数加上 ln(doc_len + 1) * length_prior，
+    /// 给较长文档少量加分以抑制过短的灌水文档；开启后单词查询不再跳过整块
+    /// min_score: 最终分数低于该值的结果被丢弃 (可能少于 top_k 个)。按完整打分后的分数过滤，
+    /// 不影响 Block 剪枝
+    /// offset: 分页偏移，返回按相关性排序的第 [offset, offset + top_k) 个结果
+    /// (内部保留 offset + top_k 个候选)；超出命中数时返回空列表
+    /// exclude_terms: 排除词 (索引中的词，不再分词，开启 lowercase 时转为小写)，
+    /// 包含任一排除词的文档不出现在结果中
+    #[pyo3(signature = (
+        query,
+        top_k=None,
+        require_all_terms=false,
+        phrase_boost=0.0,
+        sort_by=SortOrder::Relevance,
+        max_query_terms=None,
+        max_blocks_scanned=None,
+        min_coverage=None,
+        length_prior=None,
+        min_score=None,
+        offset=None,
+        exclude_terms=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        top_k: Option<usize>,
+        require_all_terms: bool,
+        phrase_boost: f64,
+        sort_by: SortOrder,
+        max_query_terms: Option<usize>,
+        max_blocks_scanned: Option<usize>,
+        min_coverage: Option<f64>,
+        length_prior: Option<f64>,
+        min_score: Option<f64>,
+        offset: Option<usize>,
+        exclude_terms: Option<Vec<String>>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        if phrase_boost != 0.0 && !self.store_positions {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "phrase_boost requires an index built with store_positions=True",
+            ));
+        }
+        if min_coverage.is_some_and(|c| !(0.0..=1.0).contains(&c)) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "min_coverage must be between 0 and 1",
+            ));
+        }
+        if length_prior.is_some_and(|w| !w.is_finite()) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "length_prior must be a finite number",
+            ));
+        }
+        if min_score.is_some_and(|s| s.is_nan()) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "min_score must not be NaN",
+            ));
+        }
+        let offset = offset.unwrap_or(0);
+        let k = match top_k.unwrap_or(10) {
+            0 => 0,
+            // 结果数不超过文档数，避免过大的 offset 预分配堆空间
+            k => k.saturating_add(offset).min(self.corpus_size),
+        };
+        let options = SearchOptions {
+            require_all_terms,
+            phrase_boost,
+            max_query_terms,
+            max_blocks_scanned,
+            min_coverage,
+            length_prior,
+            ..SearchOptions::new(k)
+        };
+        // 检索期间释放 GIL
+        let mut results: Vec<(u64, f64)> = py.detach(|| {
+            // 预先收集排除词命中的全部文档，候选检查为 O(1)
+            let excluded: HashSet<u32> = exclude_terms
+                .iter()
+                .flatten()
+                .filter_map(|term| self.index.get(&self.index_term(term)))
+                .flat_map(|list| &list.blocks)
+                .flat_map(|block| block.doc_ids.iter().copied())
+                .collect();
+            self.search_internal(query, &options, |doc_id| !excluded.contains(&doc_id))
+                .into_iter()
+                .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                .collect()
+        });
+        if let Some(min_score) = min_score {
+            results.retain(|&(_, score)| score >= min_score);
+        }
+        results.drain(..offset.min(results.len()));
+        match sort_by {
+            SortOrder::Relevance => {}
+            SortOrder::AscId => results.sort_by_key(|&(doc_id, _)| doc_id),
+            SortOrder::DescId => results.sort_by_key(|&(doc_id, _)| std::cmp::Reverse(doc_id)),
+        }
+        Ok(results)
+    }
+
+    /// 使用调用方分好的词搜索，不经过 jieba 分词 (如自定义实体识别、同义词扩展后的词)
+    ///
+    /// 各词按索引规则规范化 (查询小写设置与 <UNK> 映射)，其余打分流程与 search 相同
+    #[pyo3(signature = (tokens, top_k=None))]
+    pub fn search_tokens(
+        &self,
+        py: Python<'_>,
+        tokens: Vec<String>,
+        top_k: Option<usize>,
+    ) -> Vec<(u64, f64)> {
+        let options = SearchOptions {
+            query_terms: Some(tokens.iter().map(|t| self.index_term(t)).collect()),
+            ..SearchOptions::new(top_k.unwrap_or(10))
+        };
+        py.detach(|| {
+            self.search_internal("", &options, |_| true)
+                .into_iter()
+                .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                .collect()
+        })
+    }
+
+    /// 批量搜索，查询之间并行执行 (线程数由 set_num_threads 控制)
+    ///
+    /// 整批查询只跨越一次 Python/Rust 边界，执行期间释放 GIL
+    /// 返回: 与 queries 顺序一致的 List[List[(doc_id, score)]]
+    #[pyo3(signature = (queries, top_k=None))]
+    pub fn search_batch(
+        &self,
+        py: Python<'_>,
+        queries: Vec<String>,
+        top_k: Option<usize>,
+    ) -> Vec<Vec<(u64, f64)>> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        // 并行前重建过期的 max_score，避免多个线程同时重建
+        self.ensure_max_scores();
+        py.detach(|| {
+            run_parallel(|| {
+                queries
+                    .par_iter()
+                    .map(|query| {
+                        self.search_internal(query, &options, |_| true)
+                            .into_iter()
+                            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                            .collect()
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// 搜索并返回诊断信息
+    /// 返回: (List[(doc_id, score)], SearchStats)
+    #[pyo3(signature = (query, top_k=None, max_blocks_scanned=None))]
+    pub fn search_with_stats(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        max_blocks_scanned: Option<usize>,
+    ) -> (Vec<(u64, f64)>, SearchStats) {
+        let options = SearchOptions {
+            max_blocks_scanned,
+            ..SearchOptions::new(top_k.unwrap_or(10))
+        };
+        let (docs, stats) = self.search_internal_with_stats(query, &options, |_| true, None, None);
+        let results = docs
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect();
+        (results, stats)
+    }
+
+    /// 搜索并返回各阶段耗时，用于定位查询耗时主要在分词还是遍历
+    ///
+    /// 只有本方法计时，普通搜索没有额外开销
+    /// 返回: (List[(doc_id, score)], SearchTiming)
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn search_timed(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+    ) -> (Vec<(u64, f64)>, SearchTiming) {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let mut timing = SearchTiming::default();
+        let start = Instant::now();
+        let (docs, _) =
+            self.search_internal_with_stats(query, &options, |_| true, None, Some(&mut timing));
+        let searched = Instant::now();
+        let results = docs
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect();
+        timing.assembly_us = micros(searched.elapsed());
+        timing.traversal_us =
+            (micros(searched - start) - timing.tokenize_us - timing.setup_us).max(0.0);
+        timing.total_us = micros(start.elapsed());
+        (results, timing)
+    }
+
+    /// 调试用搜索: 额外返回每个结果打分时各命中词所在的 Block 下标
+    ///
+    /// 用于排查 Block-Max 剪枝导致的意外结果；普通搜索不记录这些信息
+    /// 返回: List[(doc_id, score, [(term, block_index)])]，term 为索引中的词
+    #[pyo3(signature = (query, top_k=None, require_all_terms=false))]
+    pub fn search_debug(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        require_all_terms: bool,
+    ) -> Vec<(u64, f64, BlockHits)> {
+        let options = SearchOptions {
+            require_all_terms,
+            ..SearchOptions::new(top_k.unwrap_or(10))
+        };
+        let mut trace = BlockTrace::new();
+        let (docs, _) =
+            self.search_internal_with_stats(query, &options, |_| true, Some(&mut trace), None);
+        docs.into_iter()
+            .map(|d| {
+                let blocks = trace.remove(&d.doc_id).unwrap_or_default();
+                (
+                    self.external_id(d.doc_id),
+                    self.output_score(d.score),
+                    blocks,
+                )
+            })
+            .collect()
+    }
+
+    /// 搜索并返回每个结果命中的查询词 (用于结果高亮)
+    ///
+    /// 命中词按查询顺序排列并去重，为索引中的词 (经过小写与 <UNK> 映射)
+    /// 返回: List[(doc_id, score, [term])]
+    #[pyo3(signature = (query, top_k=None, require_all_terms=false))]
+    pub fn search_with_matches(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        require_all_terms: bool,
+    ) -> Vec<(u64, f64, Vec<String>)> {
+        self.search_debug(query, top_k, require_all_terms)
+            .into_iter()
+            .map(|(doc_id, score, blocks)| {
+                let mut matched: Vec<String> = Vec::with_capacity(blocks.len());
+                for (term, _) in blocks {
+                    if !matched.contains(&term) {
+                        matched.push(term);
+                    }
+                }
+                (doc_id, score, matched)
+            })
+            .collect()
+    }
+
+    /// 搜索并返回 JSON 字符串: [{"id": doc_id, "score": score}, ...]
+    ///
+    /// score_decimals: 输出分数保留的小数位数 (只影响序列化结果，内部精度不变)
+    #[pyo3(signature = (query, top_k=None, score_decimals=None))]
+    pub fn search_json(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        score_decimals: Option<u32>,
+    ) -> PyResult<String> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let hits: Vec<JsonHit> = self
+            .search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| {
+                let score = self.output_score(d.score);
+                JsonHit {
+                    id: self.external_id(d.doc_id),
+                    score: score_decimals.map_or(score, |decimals| round_score(score, decimals)),
+                }
+            })
+            .collect();
+        serde_json::to_string(&hits)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    /// 搜索并附带累计分数占比 (用于渐进式渲染的进度条或提前停止渲染)
+    ///
+    /// 返回: List[(doc_id, score, cumulative)]，cumulative 为截至该名次的分数之和占全部
+    /// 返回结果分数之和的比例，单调不减，最后一个结果为 1.0 (分数全为 0 时按名次均分)
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn search_cumulative(&self, query: &str, top_k: Option<usize>) -> Vec<(u64, f64, f64)> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let results: Vec<(u64, f64)> = self
+            .search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect();
+        let total: f64 = results.iter().map(|&(_, score)| score).sum();
+        let count = results.len();
+        let mut running = 0.0;
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (doc_id, score))| {
+                running += score;
+                let cumulative = if rank + 1 == count {
+                    1.0
+                } else if total > 0.0 {
+                    (running / total).min(1.0)
+                } else {
+                    (rank + 1) as f64 / count as f64
+                };
+                (doc_id, score, cumulative)
+            })
+            .collect()
+    }
+
+    /// 搜索并附带每个结果的元数据
+    /// 返回: List[(doc_id, score, metadata)]，未存储元数据时 metadata 为 None
+    #[pyo3(signature = (query, top_k=None, require_all_terms=false))]
+    pub fn search_with_metadata(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        require_all_terms: bool,
+    ) -> Vec<(u64, f64, Option<Metadata>)> {
+        let options = SearchOptions {
+            require_all_terms,
+            ..SearchOptions::new(top_k.unwrap_or(10))
+        };
+        self.search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| {
+                let metadata = self.metadata.get(d.doc_id as usize).cloned();
+                (
+                    self.external_id(d.doc_id),
+                    self.output_score(d.score),
+                    metadata,
+                )
+            })
+            .collect()
+    }
+
+    /// 带元数据过滤的搜索
+    ///
+    /// filter: Python 可调用对象，接收候选文档的元数据 dict (未存储时为空 dict)，
+    /// 返回 True 的文档才会保留。回调在打分过程中调用 (持有 GIL)，
+    /// 且只对有机会进入 Top-K 的候选调用
+    /// include_unmatched: 为 True 时过滤结果即结果全集 (先过滤后排序): 对每个文档调用一次
+    /// filter，未命中查询词的文档以 0 分排在命中文档之后 (按文档顺序)
+    #[pyo3(signature = (query, filter, top_k=None, include_unmatched=false))]
+    pub fn search_with_filter(
+        &self,
+        query: &str,
+        filter: &Bound<'_, PyAny>,
+        top_k: Option<usize>,
+        include_unmatched: bool,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        let empty = Metadata::new();
+        let mut error: Option<PyErr> = None;
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let mut keep = |doc_id: u32| {
+            if error.is_some() {
+                return false;
+            }
+            let metadata = self.metadata.get(doc_id as usize).unwrap_or(&empty);
+            match filter
+                .call1((metadata.clone(),))
+                .and_then(|r| r.is_truthy())
+            {
+                Ok(keep) => keep,
+                Err(e) => {
+                    error = Some(e);
+                    false
+                }
+            }
+        };
+
+        let results: Vec<(u32, f64)> = if include_unmatched {
+            let universe: Vec<u32> = (0..self.corpus_size as u32)
+                .filter(|&doc_id| !self.deleted.contains(&doc_id) && keep(doc_id))
+                .collect();
+            let scores: HashMap<u32, f64> = self.sparse_scores(query).into_iter().collect();
+            let mut ranked: Vec<(u32, f64)> = universe
+                .into_iter()
+                .map(|doc_id| (doc_id, scores.get(&doc_id).copied().unwrap_or(0.0)))
+                .collect();
+            // 稳定排序: 同分 (包括 0 分) 文档保持文档顺序
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+            ranked.truncate(options.k);
+            ranked
+        } else {
+            self.search_internal(query, &options, &mut keep)
+                .into_iter()
+                .map(|d| (d.doc_id, d.score))
+                .collect()
+        };
+
+        if let Some(e) = error {
+            return Err(e);
+        }
+        Ok(results
+            .into_iter()
+            .map(|(doc_id, score)| (self.external_id(doc_id), self.output_score(score)))
+            .collect())
+    }
+
+    /// 按词加权搜索: terms 为 [(查询文本, 权重)]，每个词的 BM25 分量乘以权重后求和
+    ///
+    /// 查询文本按 search 的规则分词，分出的各词使用同一权重；同一个词出现多次时
+    /// 只计一次，使用最后给出的权重。与 set_term_boosts 不同，权重只作用于本次查询
+    #[pyo3(signature = (terms, top_k=None))]
+    pub fn search_boosted(
+        &self,
+        py: Python<'_>,
+        terms: Vec<(String, f64)>,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        if terms.iter().any(|&(_, w)| !w.is_finite() || w < 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "term weights must be finite non-negative numbers",
+            ));
+        }
+        let mut options = SearchOptions::new(top_k.unwrap_or(10));
+        for (text, weight) in &terms {
+            for token in self.query_tokens(text) {
+                options.term_weights.insert(token.into_owned(), *weight);
+            }
+        }
+        let query = terms
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(py.detach(|| {
+            self.search_internal(&query, &options, |_| true)
+                .into_iter()
+                .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                .collect()
+        }))
+    }
+
+    /// 只在指定外部 ID 的文档中搜索 (如多租户场景下只检索当前用户的文档)
+    ///
+    /// allowed_ids 在检索前一次性转换为内部 ID 的位图，遍历倒排列表时只对集合内的
+    /// 文档打分入堆；不在索引中的 ID 被忽略。检索期间释放 GIL
+    #[pyo3(signature = (query, allowed_ids, top_k=None))]
+    pub fn search_filtered(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        allowed_ids: Vec<u64>,
+        top_k: Option<usize>,
+    ) -> Vec<(u64, f64)> {
+        if allowed_ids.is_empty() {
+            return Vec::new();
+        }
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        py.detach(|| {
+            let allowed: HashSet<u64> = allowed_ids.into_iter().collect();
+            let mask: Vec<bool> = self.doc_ids.iter().map(|id| allowed.contains(id)).collect();
+            self.search_internal(query, &options, |doc_id| mask[doc_id as usize])
+                .into_iter()
+                .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                .collect()
+        })
+    }
+
+    /// 两阶段检索: 先用 BM25 召回 retrieve_k 个候选，再交给 Python 重排序函数打分
+    ///
+    /// rerank: 可调用对象 rerank(query, candidate_ids) -> List[float]，candidate_ids 为按 BM25
+    /// 排序的外部 ID (索引不保存原文，调用方按 ID 取文本)，返回与之等长的新分数
+    /// 返回: 按新分数降序的 Top-K [(doc_id, rerank_score)]，同分时保持 BM25 顺序
+    #[pyo3(signature = (query, rerank, retrieve_k=100, top_k=None))]
+    pub fn search_rerank(
+        &self,
+        query: &str,
+        rerank: &Bound<'_, PyAny>,
+        retrieve_k: usize,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        let options = SearchOptions::new(retrieve_k);
+        let candidates: Vec<u64> = self
+            .search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| self.external_id(d.doc_id))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let scores: Vec<f64> = rerank.call1((query, candidates.clone()))?.extract()?;
+        if scores.len() != candidates.len() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "rerank must return one score per candidate",
+            ));
+        }
+        let mut results: Vec<(u64, f64)> = candidates.into_iter().zip(scores).collect();
+        // 稳定排序: 同分时保持 BM25 顺序
+        results.sort_by(|a, b| b.1.total_cmp(&a.1));
+        results.truncate(top_k.unwrap_or(10));
+        Ok(results)
+    }
+
+    /// 折叠近似重复结果的搜索 (如模板化的样板文档)，提高结果多样性
+    ///
+    /// 召回 retrieve_k 个候选 (默认 3 * top_k)，按分数从高到低依次保留，与已保留文档的
+    /// 词集合 Jaccard 相似度达到 similarity_threshold 的候选被折叠 (保留得分最高的代表)。
+    /// 索引不保存原文，词集合由倒排列表反查，需要扫描一遍词表
+    #[pyo3(signature = (query, top_k=None, similarity_threshold=0.8, retrieve_k=None))]
+    pub fn search_dedup(
+        &self,
+        query: &str,
+        top_k: Option<usize>,
+        similarity_threshold: f64,
+        retrieve_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        if !(similarity_threshold > 0.0 && similarity_threshold <= 1.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "similarity_threshold must be in (0, 1]",
+            ));
+        }
+        let k = top_k.unwrap_or(10);
+        let options = SearchOptions::new(retrieve_k.unwrap_or(k.saturating_mul(3)).max(k));
+        let candidates = self.search_internal(query, &options, |_| true);
+        let doc_ids: Vec<u32> = candidates.iter().map(|d| d.doc_id).collect();
+        let term_sets = self.term_sets(&doc_ids);
+
+        let mut kept: Vec<&ScoredDoc> = Vec::new();
+        for candidate in &candidates {
+            if kept.len() >= k {
+                break;
+            }
+            let terms = &term_sets[&candidate.doc_id];
+            let duplicate = kept
+                .iter()
+                .any(|d| jaccard(terms, &term_sets[&d.doc_id]) >= similarity_threshold);
+            if !duplicate {
+                kept.push(candidate);
+            }
+        }
+        Ok(kept
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect())
+    }
+
+    /// 结合次要排序键的搜索 (如文档热度)
+    ///
+    /// secondary: 按内部槽位排列的次要值列表 (与 fit 时文档顺序一致，长度为 num_slots，
+    /// 已删除文档的槽位在 compact 前仍占一位)
+    /// secondary_key: 从元数据中读取次要值的键 (值需可解析为数字，缺失视为 0)
+    /// mode: TieBreak 时返回 BM25 分数，Blend 时返回混合后的分数
+    /// 需要对全部命中文档打分，开销高于 search
+    #[pyo3(signature = (
+        query,
+        secondary=None,
+        secondary_key=None,
+        mode=SecondaryMode::TieBreak,
+        weight=1.0,
+        top_k=None,
+    ))]
+    pub fn search_with_secondary(
+        &self,
+        query: &str,
+        secondary: Option<Vec<f64>>,
+        secondary_key: Option<&str>,
+        mode: SecondaryMode,
+        weight: f64,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        let values = match (secondary, secondary_key) {
+            (Some(values), None) => {
+                if values.len() != self.corpus_size {
+                    return Err(pyo3::exceptions::PyValueError::new_err(
+                        "secondary must have the same length as num_slots (document slots, including deleted documents)",
+                    ));
+                }
+                values
+            }
+            (None, Some(key)) => self.metadata_values(key)?,
+            _ => {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "exactly one of secondary and secondary_key must be provided",
+                ))
+            }
+        };
+
+        let mut candidates: Vec<(u32, f64, f64)> = self
+            .sparse_scores(query)
+            .into_iter()
+            .map(|(doc_id, score)| {
+                let value = values[doc_id as usize];
+                match mode {
+                    SecondaryMode::TieBreak => (doc_id, score, value),
+                    SecondaryMode::Blend => (doc_id, score + weight * value, value),
+                }
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| b.2.total_cmp(&a.2))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        candidates.truncate(top_k.unwrap_or(10));
+        Ok(candidates
+            .into_iter()
+            .map(|(doc_id, score, _)| (self.external_id(doc_id), self.output_score(score)))
+            .collect())
+    }
+
+    /// 获取指定外部 ID 文档的元数据
+    pub fn get_metadata(&self, external_id: u64) -> Option<Metadata> {
+        let internal_id = self.internal_id(external_id)?;
+        self.metadata.get(internal_id).cloned()
+    }
+
+    /// 搜索并将结果编码为紧凑的二进制帧 (用于 IPC / Socket 传输)
+    ///
+    /// 帧格式 (小端): u32 结果数 n | n 个 u64 文档 ID | n 个 f64 分数
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn search_frame(&self, query: &str, top_k: Option<usize>) -> Vec<u8> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let results: Vec<(u64, f64)> = self
+            .search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect();
+        encode_frame(&results)
+    }
+
+    /// 解码 search_frame 生成的二进制帧
+    #[staticmethod]
+    pub fn decode_frame(data: &[u8]) -> PyResult<Vec<(u64, f64)>> {
+        decode_frame(data).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err("invalid or truncated result frame")
+        })
+    }
+
+    /// 对多个词分别查询 Top-K 文档
+    ///
+    /// 每个词独立走单词快速路径，返回 {词: [(doc_id, score)]}
+    /// 开启 lowercase 时词会先转为小写；不在索引中的词对应空列表
+    #[pyo3(signature = (terms, top_k=None))]
+    pub fn top_docs_per_term(
+        &self,
+        terms: Vec<String>,
+        top_k: Option<usize>,
+    ) -> HashMap<String, Vec<(u64, f64)>> {
+        let k = top_k.unwrap_or(10);
+        terms
+            .into_iter()
+            .map(|term| {
+                let key = self.index_term(&term);
+                let results = match self.index.get(&key) {
+                    Some(list) if !self.is_zero_weight(&key) => {
+                        let idf = self.calc_idf(&key, list);
+                        let options = SearchOptions::new(k);
+                        self.single_term_top_k(
+                            list,
+                            idf,
+                            &options,
+                            &mut SearchStats::default(),
+                            |_| true,
+                        )
+                        .into_iter()
+                        .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                        .collect()
+                    }
+                    _ => Vec::new(),
+                };
+                (term, results)
+            })
+            .collect()
+    }
+
+    /// 邻近查询: 返回所有词都出现且位置落在 slop 窗口内的文档
+    ///
+    /// terms: 索引中的词 (不再分词，开启 lowercase 时转为小写)
+    /// slop: 允许的额外间隔，0 表示必须相邻; ordered: 是否要求按给定顺序出现
+    /// 分数为各词 BM25 分数之和，需要 store_positions=True
+    #[pyo3(signature = (terms, slop=0, ordered=true, top_k=None))]
+    pub fn search_near(
+        &self,
+        terms: Vec<String>,
+        slop: u32,
+        ordered: bool,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        if !self.store_positions {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "search_near requires an index built with store_positions=True",
+            ));
+        }
+        let mut keys: Vec<String> = terms.iter().map(|t| self.index_term(t)).collect();
+        if !ordered {
+            // 无序匹配中重复的词没有意义
+            let mut seen = HashSet::new();
+            keys.retain(|k| seen.insert(k.clone()));
+        }
+        Ok(self.near_top_k(&keys, slop, ordered, top_k.unwrap_or(10)))
+    }
+
+    /// 短语查询: 只返回查询分词后的各词按顺序连续出现的文档
+    ///
+    /// 短语与 search 使用相同的查询分词 (Remove 模式的停用词在索引与查询中都被移除，
+    /// 不占位置)；分数为各词 BM25 分数之和，需要 store_positions=True
+    #[pyo3(signature = (phrase, top_k=None))]
+    pub fn search_phrase(&self, phrase: &str, top_k: Option<usize>) -> PyResult<Vec<(u64, f64)>> {
+        if !self.store_positions {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "search_phrase requires an index built with store_positions=True",
+            ));
+        }
+        let keys: Vec<Cow<str>> = self.query_tokens(phrase);
+        Ok(self.near_top_k(&keys, 0, true, top_k.unwrap_or(10)))
+    }
+
+    /// 布尔查询: +词 必须出现，-词 必须不出现，其余词可选 (只参与打分)
+    ///
+    /// 子句以空白分隔，子句内容按查询分词 (一个 + 子句分出的多个词都必须出现)。
+    /// 必选词不在索引中时没有结果；只有排除词的查询返回空列表
+    /// 返回: List[(doc_id, score)]，分数为文档中出现的必选词与可选词的 BM25 分数之和
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn search_boolean(&self, query: &str, top_k: Option<usize>) -> Vec<(u64, f64)> {
+        let k = top_k.unwrap_or(10);
+        let mut required: Vec<Cow<str>> = Vec::new();
+        let mut excluded: Vec<&InvertedList> = Vec::new();
+        let mut optional: Vec<&str> = Vec::new();
+        for clause in query.split_whitespace() {
+            if let Some(text) = clause.strip_prefix('+') {
+                required.extend(self.query_tokens(text));
+            } else if let Some(text) = clause.strip_prefix('-') {
+                let tokens = self.query_tokens(text);
+                excluded.extend(tokens.iter().filter_map(|t| self.index.get(t.as_ref())));
+            } else {
+                optional.push(clause);
+            }
+        }
+        let is_excluded = |doc_id: u32| excluded.iter().any(|list| list.locate(doc_id).is_some());
+        let term_idf = |term: &str, list: &InvertedList| {
+            if self.is_zero_weight(term) {
+                0.0
+            } else {
+                self.calc_idf(term, list)
+            }
+        };
+
+        // 没有必选词: 可选词的普通检索，排除词作为过滤条件
+        if required.is_empty() {
+            if optional.is_empty() {
+                return Vec::new();
+            }
+            let options = SearchOptions::new(k);
+            return self
+                .search_internal(&optional.join(" "), &options, |doc_id| !is_excluded(doc_id))
+                .into_iter()
+                .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+                .collect();
+        }
+
+        // 重复的词 (包括同时作为必选词与可选词出现的词) 只计分一次
+        let required = distinct_terms(required);
+        let mut cursors = Vec::with_capacity(required.len());
+        for token in &required {
+            match self.index.get(token.as_ref()) {
+                Some(list) if !list.blocks.is_empty() => {
+                    cursors.push(BlockCursor::new(list, term_idf(token, list)));
+                }
+                _ => return Vec::new(),
+            }
+        }
+        let optional_tokens = optional
+            .iter()
+            .flat_map(|clause| self.query_tokens(clause))
+            .filter(|token| !required.contains(token))
+            .collect();
+        let mut optional_cursors: Vec<BlockCursor> = distinct_terms(optional_tokens)
+            .into_iter()
+            .filter_map(|token| {
+                let list = self.index.get(token.as_ref())?;
+                Some(BlockCursor::new(list, term_idf(&token, list)))
+            })
+            .collect();
+
+        // 必选词求交，可选词游标跟随交集前进并累加分数
+        let mut top_k = TopK::new(k, self.corpus_size);
+        let mut intersection = Intersection::new(cursors);
+        while let Some(doc_id) = intersection.next_doc() {
+            if !is_excluded(doc_id) {
+                let mut score: f64 = intersection
+                    .cursors()
+                    .iter()
+                    .map(|c| self.cursor_score(c))
+                    .sum();
+                for cursor in &mut optional_cursors {
+                    cursor.skip_to(doc_id);
+                    if cursor.curr_doc_id() == Some(doc_id) {
+                        score += self.cursor_score(cursor);
+                    }
+                }
+                top_k.push(score, doc_id);
+            }
+            intersection.advance();
+        }
+        top_k
+            .into_sorted_vec()
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect()
+    }
+
+    /// 导出某个词的倒排列表结构 (只读)，供外部遍历或可视化工具使用
+    /// 返回: (doc_count, [(last_doc_id, max_score, [(doc_id, freq, doc_len)])])，
+    /// 其中 doc_id 为内部 ID；词不在索引中时返回 None
+    pub fn export_term(&self, term: &str) -> Option<(usize, Vec<ExportedBlock>)> {
+        self.ensure_max_scores();
+        let list = self.index.get(&self.index_term(term))?;
+        let blocks = list
+            .blocks
+            .iter()
+            .map(|block| {
+                let postings = (0..block.doc_ids.len())
+                    .map(|i| {
+                        let doc_id = block.doc_ids[i];
+                        (doc_id, block.freqs[i], self.doc_lengths[doc_id as usize])
+                    })
+                    .collect();
+                (block.last_doc_id, block.max_score.get(), postings)
+            })
+            .collect();
+        Some((list.doc_count, blocks))
+    }
+
+    /// 外部 ID 对应的全部内部文档 (分块索引中为各分块，按原文顺序)
+    pub fn get_chunks(&self, external_id: u64) -> Vec<u32> {
+        (0..self.doc_ids.len() as u32)
+            .filter(|&doc_id| {
+                self.doc_ids[doc_id as usize] == external_id && !self.deleted.contains(&doc_id)
+            })
+            .collect()
+    }
+
+    /// 文档的稀疏词向量: 文档中每个词的 BM25 分量 (idf * 词频饱和)，按权重降序排列
+    ///
+    /// 与 get_scores(term) 中该文档的分数一致；权重为 0 的词 (如 ZeroWeight 停用词) 不返回。
+    /// 没有正排索引，需要扫描整个词表；文档不存在时返回 None
+    pub fn document_vector(&self, external_id: u64) -> Option<Vec<(String, f64)>> {
+        let mut vector = self.term_weights(external_id)?;
+        vector.sort_by(cmp_term_weight);
+        Some(vector)
+    }
+
+    /// 文档中权重最高的 n 个词 (用于摘要、自动打标签)，按权重降序排列
+    ///
+    /// 权重与 document_vector 相同；只对前 n 个排序。文档不存在时返回空列表
+    pub fn document_top_terms(&self, external_id: u64, n: usize) -> Vec<(String, f64)> {
+        top_terms(self.term_weights(external_id).unwrap_or_default(), n)
+    }
+
+    /// 文档的 TF-IDF 关键词: 权重为词频 * idf (不做词频饱和与长度归一化)，按权重降序
+    /// 返回前 top_n 个
+    ///
+    /// idf 与打分时一致；ZeroWeight 停用词不返回。文档不存在或不含任何词时返回空列表
+    pub fn extract_keywords(&self, external_id: u64, top_n: usize) -> Vec<(String, f64)> {
+        let Some(doc_id) = self.internal_id(external_id) else {
+            return Vec::new();
+        };
+        let doc_id = doc_id as u32;
+        let mut keywords: Vec<(String, f64)> = Vec::new();
+        for (term, list) in &self.index {
+            if self.is_zero_weight(term) {
+                continue;
+            }
+            if let Some((block_idx, i)) = list.locate(doc_id) {
+                let weight = list.blocks[block_idx].tf(i) * self.calc_idf(term, list);
+                if weight > 0.0 {
+                    keywords.push((term.clone(), weight));
+                }
+            }
+        }
+        top_terms(keywords, top_n)
+    }
+
+    /// 解释某个文档的得分: 每个命中该文档的查询词的 (词, idf, BM25 分量, 词频)
+    ///
+    /// 按查询顺序排列，各词分量之和与 get_scores 中该文档的分数一致 (重复的查询词
+    /// 只计一次)；不在索引中、未命中该文档的词与 ZeroWeight 停用词不返回。
+    /// 文档不存在时返回空列表
+    pub fn explain(&self, query: &str, external_id: u64) -> Vec<(String, f64, f64, u32)> {
+        let Some(doc_id) = self.internal_id(external_id) else {
+            return Vec::new();
+        };
+        let doc_id = doc_id as u32;
+        distinct_terms(self.query_tokens(query))
+            .into_iter()
+            .filter(|term| !self.is_zero_weight(term))
+            .filter_map(|term| {
+                let list = self.index.get(term.as_ref())?;
+                let (block_idx, i) = list.locate(doc_id)?;
+                let block = &list.blocks[block_idx];
+                let idf = self.calc_idf(&term, list);
+                let score = self.posting_score(idf, doc_id, block.tf(i));
+                Some((
+                    term.into_owned(),
+                    idf,
+                    self.output_score(score),
+                    block.freqs[i],
+                ))
+            })
+            .collect()
+    }
+
+    /// 获取所有文档的 BM25 分数 (按内部槽位排列，长度为 num_slots，已删除文档为 0)
+    ///
+    /// 按文档区间并行累加 (线程数由 set_num_threads 控制)。每个文档仍按查询词顺序
+    /// 依次累加各词分数，浮点求和顺序与串行相同，结果逐位一致
+    pub fn get_scores(&self, query: &str) -> Vec<f64> {
+        let terms = self.scoring_terms(query);
+        let mut scores = vec![0.0; self.corpus_size];
+        run_parallel(|| {
+            scores
+                .par_chunks_mut(SCORE_STRIPE_SIZE)
+                .enumerate()
+                .for_each(|(stripe, stripe_scores)| {
+                    let first = (stripe * SCORE_STRIPE_SIZE) as u32;
+                    for &(list, idf) in &terms {
+                        self.add_stripe_scores(list, idf, first, stripe_scores);
+                    }
+                })
+        });
+        if self.f32_scores {
+            scores.iter_mut().for_each(|s| *s = self.output_score(*s));
+        }
+        scores
+    }
+
+    /// 获取所有分数大于 0 的文档 (稀疏形式)
+    /// 返回: List[(doc_id, score)]，按内部文档顺序排列
+    pub fn get_nonzero_scores(&self, query: &str) -> Vec<(u64, f64)> {
+        self.sparse_scores(query)
+            .into_iter()
+            .map(|(doc_id, score)| (self.external_id(doc_id), self.output_score(score)))
+            .collect()
+    }
+
+    /// 查询分数分布统计，用于阈值校准
+    /// 返回: (均值, 标准差, 文档数)，仅统计分数大于 0 的文档；无命中时为 (0.0, 0.0, 0)
+    pub fn score_distribution(&self, query: &str) -> (f64, f64, usize) {
+        let scores = self.sparse_scores(query);
+        let count = scores.len();
+        if count == 0 {
+            return (0.0, 0.0, 0);
+        }
+        let mean = scores.iter().map(|&(_, s)| s).sum::<f64>() / count as f64;
+        let variance = scores
+            .iter()
+            .map(|&(_, s)| (s - mean) * (s - mean))
+            .sum::<f64>()
+            / count as f64;
+        (mean, variance.sqrt(), count)
+    }
+
+    /// 查询词影响力: 在 Top-K 结果上汇总每个查询词的分数贡献
+    /// 返回: List[(词, 总贡献)]，按贡献降序 (贡献相同时保持查询顺序)，不在索引中的词贡献为 0
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn term_impact(&self, query: &str, top_k: Option<usize>) -> Vec<(String, f64)> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let mut doc_ids: Vec<u32> = self
+            .search_internal(query, &options, |_| true)
+            .into_iter()
+            .map(|d| d.doc_id)
+            .collect();
+        doc_ids.sort_unstable();
+
+        let mut impacts: Vec<(String, f64)> = distinct_terms(self.query_tokens(query))
+            .into_iter()
+            .map(|term| {
+                let impact = match self.index.get(term.as_ref()) {
+                    Some(list) if !self.is_zero_weight(&term) => {
+                        let mut cursor = BlockCursor::new(list, self.calc_idf(&term, list));
+                        let mut total = 0.0;
+                        for &doc_id in &doc_ids {
+                            cursor.skip_to(doc_id);
+                            if cursor.curr_doc_id() == Some(doc_id) {
+                                total += self.cursor_score(&cursor);
+                            }
+                        }
+                        total
+                    }
+                    _ => 0.0,
+                };
+                (term.into_owned(), impact)
+            })
+            .collect();
+        impacts.sort_by(|a, b| b.1.total_cmp(&a.1));
+        impacts
+    }
+
+    /// 词表 idf 分布直方图
+    /// 返回: List[(下界, 上界, 词数)]，在最小与最大 idf 之间等宽划分 bins 个区间 (最后一个区间含上界)
+    pub fn idf_spectrum(&self, bins: usize) -> PyResult<Vec<(f64, f64, usize)>> {
+        if bins == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "bins must be greater than 0",
+            ));
+        }
+        let idfs: Vec<f64> = self
+            .index
+            .iter()
+            .map(|(term, list)| self.calc_idf(term, list))
+            .collect();
+        if idfs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let min = idfs.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = idfs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0; bins];
+        for idf in idfs {
+            let bin = if width > 0.0 {
+                (((idf - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Ok(counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let lo = min + width * i as f64;
+                let hi = if i + 1 == bins {
+                    max
+                } else {
+                    min + width * (i + 1) as f64
+                };
+                (lo, hi, count)
+            })
+            .collect())
+    }
+
+    /// 文档老化: 外部 ID 小于 older_than_id 的文档分数乘以 factor
+    ///
+    /// 假设外部 ID 按时间递增编码。乘数按文档保存并参与打分，多次调用会累乘
+    pub fn decay_documents(&mut self, factor: f64, older_than_id: u64) -> PyResult<()> {
+        if !factor.is_finite() || factor < 0.0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "factor must be a finite non-negative number",
+            ));
+        }
+        if self.doc_weights.is_empty() {
+            self.doc_weights = vec![1.0; self.doc_ids.len()];
+        }
+        for (weight, &external_id) in self.doc_weights.iter_mut().zip(&self.doc_ids) {
+            if external_id < older_than_id {
+                *weight *= factor;
+            }
+        }
+        self.mark_max_scores_dirty();
+        Ok(())
+    }
+
+    /// 追加单个文档 (无需重新 fit)，返回其外部 ID
+    ///
+    /// id 为空时使用当前最大 ID + 1 (默认 ID 下即文档下标)；ID 已存在时报错。
+    /// 新 Posting 追加到各词倒排列表末尾，corpus_size 与 avgdl 随之更新，
+    /// 之后的搜索结果与对全部文档重新 fit 一致
+    #[pyo3(signature = (document, id=None))]
+    pub fn add_document(&mut self, document: &str, id: Option<u64>) -> PyResult<u64> {
+        if self.chunk_size.is_some() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "add_document is not supported for chunked indexes",
+            ));
+        }
+        if !self.field_weights.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "add_document is not supported for indexes built with fit_fields",
+            ));
+        }
+        if self.empty_doc_policy == EmptyDocPolicy::Reject && is_blank(document) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "document is empty or contains only whitespace/control characters",
+            ));
+        }
+        let external_id = match id {
+            Some(id) if self.id_map.contains_key(&id) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "document id {id} already exists"
+                )))
+            }
+            Some(id) => id,
+            None => self.next_id.ok_or_else(|| {
+                pyo3::exceptions::PyValueError::new_err(
+                    "no default document id available: the largest id is already u64::MAX",
+                )
+            })?,
+        };
+        let doc_id = self.corpus_size as u32;
+
+        // 1. 插入 Posting (已合并到 <UNK> 的词同样映射)
+        let tokens = self
+            .tokenize(document, self.lowercase)
+            .map(|t| (self.unk_mapped(t), 1.0));
+        let (doc_len, postings) = self.doc_postings(doc_id, tokens);
+        for (term, posting) in postings {
+            let list = match self.index.get_mut(term.as_ref()) {
+                Some(list) => list,
+                None => self.index.entry(term.into_owned()).or_default(),
+            };
+            list.insert_posting(
+                posting,
+                self.block_size,
+                self.store_positions,
+                self.weighted_tf,
+            );
+        }
+
+        // 2. 更新文档表与 avgdl (总长度为整数，由 avgdl 还原，无需重新求和)
+        let total_length = (self.avgdl * self.live_docs() as f64).round() + doc_len as f64;
+        self.doc_lengths.push(doc_len);
+        self.doc_ids.push(external_id);
+        self.id_map.insert(external_id, doc_id as usize);
+        self.next_id = self
+            .next_id
+            .and_then(|next| external_id.checked_add(1).map(|after| next.max(after)));
+        if !self.metadata.is_empty() {
+            self.metadata.push(Metadata::new());
+        }
+        if !self.doc_weights.is_empty() {
+            self.doc_weights.push(1.0);
+        }
+        self.corpus_size += 1;
+        self.avgdl = total_length / self.live_docs() as f64;
+        self.mark_max_scores_dirty();
+        Ok(external_id)
+    }
+
+    /// 用新文本替换指定外部 ID 的文档 (无需重新 fit)
+    ///
+    /// 删除旧 Posting 需要扫描全部倒排列表 (按块二分定位)，耗时与词表大小成正比。
+    /// 文档 ID 与内部顺序不变；返回 False 表示该 ID 不存在
+    pub fn update_document(&mut self, external_id: u64, new_text: &str) -> PyResult<bool> {
+        if self.chunk_size.is_some() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "update_document is not supported for chunked indexes",
+            ));
+        }
+        if !self.field_weights.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "update_document is not supported for indexes built with fit_fields",
+            ));
+        }
+        let Some(doc_id) = self.internal_id(external_id) else {
+            return Ok(false);
+        };
+        if self.empty_doc_policy == EmptyDocPolicy::Reject && is_blank(new_text) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "document is empty or contains only whitespace/control characters",
+            ));
+        }
+        let doc_id = doc_id as u32;
+
+        // 1. 删除旧 Posting (删空的词从索引中移除)
+        self.index.retain(|_, list| {
+            list.remove_doc(doc_id);
+            list.doc_count > 0
+        });
+
+        // 2. 插入新 Posting (已合并到 <UNK> 的词同样映射)
+        let tokens = self
+            .tokenize(new_text, self.lowercase)
+            .map(|t| (self.unk_mapped(t), 1.0));
+        let (doc_len, postings) = self.doc_postings(doc_id, tokens);
+        for (term, posting) in postings {
+            let list = match self.index.get_mut(term.as_ref()) {
+                Some(list) => list,
+                None => self.index.entry(term.into_owned()).or_default(),
+            };
+            list.insert_posting(
+                posting,
+                self.block_size,
+                self.store_positions,
+                self.weighted_tf,
+            );
+        }
+
+        // 3. 更新文档长度与 avgdl
+        self.doc_lengths[doc_id as usize] = doc_len;
+        self.refresh_avgdl();
+        self.mark_max_scores_dirty();
+        Ok(true)
+    }
+
+    /// 删除文档 (墓碑标记)，返回 False 表示该 ID 不存在或已删除
+    ///
+    /// 立即从倒排列表移除其 Posting (文档频率只统计未删除文档)，并按剩余文档更新 avgdl；
+    /// 内部槽位保留，其余文档的 ID 不变 (get_scores 中该位置分数为 0)。
+    /// 分块索引中删除该 ID 的全部分块。调用 compact 可回收槽位
+    pub fn delete_document(&mut self, external_id: u64) -> PyResult<bool> {
+        let targets = self.get_chunks(external_id);
+        if targets.is_empty() {
+            return Ok(false);
+        }
+        self.index.retain(|_, list| {
+            for &doc_id in &targets {
+                list.remove_doc(doc_id);
+            }
+            list.doc_count > 0
+        });
+        for &doc_id in &targets {
+            self.doc_lengths[doc_id as usize] = 0;
+            self.deleted.insert(doc_id);
+        }
+        self.id_map.remove(&external_id);
+        self.refresh_avgdl();
+        self.mark_max_scores_dirty();
+        Ok(true)
+    }
+
+    /// 回收已删除文档的槽位: 重新编号内部 ID 并把倒排列表重新分块压实
+    ///
+    /// 外部 ID 不变；之后 get_scores 只包含未删除文档
+    pub fn compact(&mut self) {
+        if self.deleted.is_empty() {
+            return;
+        }
+        // 内部 ID 重新编号 (保持原有顺序，Posting 仍按 doc_id 递增)
+        let mut remap: Vec<u32> = vec![u32::MAX; self.corpus_size];
+        let mut next: u32 = 0;
+        for (doc_id, slot) in remap.iter_mut().enumerate() {
+            if !self.deleted.contains(&(doc_id as u32)) {
+                *slot = next;
+                next += 1;
+            }
+        }
+        // 与内部 ID 平行的数组 (元数据、文档乘数可能为空) 只保留未删除的文档
+        retain_live(&mut self.doc_lengths, &remap);
+        retain_live(&mut self.doc_ids, &remap);
+        retain_live(&mut self.metadata, &remap);
+        retain_live(&mut self.doc_weights, &remap);
+        for list in self.index.values_mut() {
+            for block in &mut list.blocks {
+                block
+                    .doc_ids
+                    .iter_mut()
+                    .for_each(|d| *d = remap[*d as usize]);
+                block.last_doc_id = remap[block.last_doc_id as usize];
+            }
+            list.compact(self.block_size, self.block_size);
+        }
+        self.corpus_size = next as usize;
+        self.deleted.clear();
+        self.rebuild_id_map();
+    }
+
+    /// 合并另一个索引 (如在不同机器上分别构建的分片)，other 的文档追加到本索引之后
+    ///
+    /// other 的内部 ID 整体后移 corpus_size，各词的 Posting 接在本索引之后并按 block_size
+    /// 重新分块，avgdl 与 Block max_score 随之更新，结果与对两者的文档合并后 fit 一致。
+    /// 两个索引的 k1、b、lowercase、打分公式 (use_idf、weighting、idf_length_coupling 等)、
+    /// block_size 与分词相关设置必须相同，外部 ID 不能重复；已有低频词合并到 <UNK> 的索引
+    /// (unk_df_threshold) 不能合并。元数据、文档乘数、已删除文档一并合并，
+    /// idf 注入等索引级设置保留本索引的
+    pub fn merge(&mut self, other: PyRef<'_, BM25>) -> PyResult<()> {
+        if self.chunk_size.is_some() || other.chunk_size.is_some() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "merge is not supported for chunked indexes",
+            ));
+        }
+        if !self.field_weights.is_empty() || !other.field_weights.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "merge is not supported for indexes built with fit_fields",
+            ));
+        }
+        // 各自合并到 <UNK> 的低频词按合并后的文档频率可能不再低频，无法还原
+        if !self.unk_terms.is_empty() || !other.unk_terms.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "merge is not supported for indexes with terms collapsed into <UNK>",
+            ));
+        }
+        let settings = |m: &BM25| {
+            (
+                (m.lowercase, m.store_positions, m.weighted_tf, m.binary_tf),
+                (m.stopword_mode, m.cut_mode, m.use_hmm),
+                (m.use_idf, m.weighting, m.idf_length_coupling),
+                (m.unk_df_threshold, m.f32_scores, m.block_size),
+            )
+        };
+        if self.k1 != other.k1
+            || self.b != other.b
+            || settings(self) != settings(&other)
+            || self.stopwords != other.stopwords
+            || self.segmenters != other.segmenters
+            || self.user_dict != other.user_dict
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "indexes must have the same k1, b, lowercase, scoring, block_size and tokenization settings to merge",
+            ));
+        }
+        if let Some(&id) = other.id_map.keys().find(|id| self.id_map.contains_key(id)) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "document id {id} already exists"
+            )));
+        }
+
+        // 1. 倒排列表: 平移 other 的内部 ID 后追加 Block，再重新分块
+        let offset = self.corpus_size as u32;
+        for (term, other_list) in &other.index {
+            let list = self.index.entry(term.clone()).or_default();
+            list.blocks
+                .extend(other_list.blocks.iter().map(|block| Block {
+                    max_score: MaxScore::default(),
+                    last_doc_id: block.last_doc_id + offset,
+                    doc_ids: block.doc_ids.iter().map(|&d| d + offset).collect(),
+                    freqs: block.freqs.clone(),
+                    doc_lens: Vec::new(),
+                    positions: block.positions.clone(),
+                    weighted_freqs: block.weighted_freqs.clone(),
+                }));
+            list.doc_count += other_list.doc_count;
+            list.collection_freq += other_list.collection_freq;
+            list.compact(self.block_size, self.block_size);
+        }
+
+        // 2. 文档表 (元数据与文档乘数只有一侧提供时，另一侧补默认值)
+        let total = self.corpus_size + other.corpus_size;
+        if !self.metadata.is_empty() || !other.metadata.is_empty() {
+            self.metadata.resize_with(self.corpus_size, Metadata::new);
+            self.metadata.extend(other.metadata.iter().cloned());
+            self.metadata.resize_with(total, Metadata::new);
+        }
+        if !self.doc_weights.is_empty() || !other.doc_weights.is_empty() {
+            self.doc_weights.resize(self.corpus_size, 1.0);
+            self.doc_weights.extend(&other.doc_weights);
+            self.doc_weights.resize(total, 1.0);
+        }
+        self.doc_lengths.extend(&other.doc_lengths);
+        self.doc_ids.extend(&other.doc_ids);
+        self.deleted
+            .extend(other.deleted.iter().map(|&d| d + offset));
+        self.corpus_size = total;
+        self.rebuild_id_map();
+        self.refresh_avgdl();
+        self.mark_max_scores_dirty();
+        Ok(())
+    }
+
+    /// 注入全局统计量，使多个独立索引的分数可比 (或可合并)
+    ///
+    /// corpus_size: 全局文档数; avgdl: 全局平均文档长度;
+    /// doc_freqs: 词 -> 全局文档频率 (缺失的词使用本索引的文档频率)。重新 fit 后失效
+    pub fn set_global_stats(
+        &mut self,
+        corpus_size: usize,
+        avgdl: f64,
+        doc_freqs: HashMap<String, usize>,
+    ) -> PyResult<()> {
+        if !avgdl.is_finite() || avgdl <= 0.0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "avgdl must be a finite positive number",
+            ));
+        }
+        self.global_stats = Some(GlobalStats {
+            corpus_size,
+            avgdl,
+            doc_freqs,
+        });
+        self.mark_max_scores_dirty();
+        Ok(())
+    }
+
+    /// 导出词表统计: 词 -> (文档频率, 集合频率)，不含倒排列表，远小于完整索引
+    ///
+    /// 用于词表分析或分布式计算全局 idf (各索引的统计相加后通过 import_vocab 注入)
+    pub fn export_vocab(&self) -> HashMap<String, (usize, u64)> {
+        self.index
+            .iter()
+            .map(|(term, list)| (term.clone(), (list.doc_count, list.collection_freq)))
+            .collect()
+    }
+
+    /// 索引中的全部词 (按字典序排列)
+    pub fn vocabulary(&self) -> Vec<String> {
+        let mut terms: Vec<String> = self.index.keys().cloned().collect();
+        terms.sort_unstable();
+        terms
+    }
+
+    /// 每个词及其文档频率: [(词, 文档频率)] (按词的字典序排列)
+    pub fn term_stats(&self) -> Vec<(String, usize)> {
+        let mut stats: Vec<(String, usize)> = self
+            .index
+            .iter()
+            .map(|(term, list)| (term.clone(), list.doc_count))
+            .collect();
+        stats.sort_unstable();
+        stats
+    }
+
+    /// 文档频率最高的 n 个词: [(词, 文档频率, idf)]，按文档频率降序 (同频按词排序)
+    ///
+    /// idf 与打分时使用的一致 (含 idf 注入与词级权重)，可用于发现候选停用词
+    pub fn top_frequent_terms(&self, n: usize) -> Vec<(String, usize, f64)> {
+        let mut terms: Vec<(&String, &InvertedList)> = self.index.iter().collect();
+        let cmp = |a: &(&String, &InvertedList), b: &(&String, &InvertedList)| {
+            b.1.doc_count.cmp(&a.1.doc_count).then_with(|| a.0.cmp(b.0))
+        };
+        if n == 0 {
+            return Vec::new();
+        }
+        if n < terms.len() {
+            terms.select_nth_unstable_by(n - 1, cmp);
+            terms.truncate(n);
+        }
+        terms.sort_by(cmp);
+        terms
+            .into_iter()
+            .map(|(term, list)| (term.clone(), list.doc_count, self.calc_idf(term, list)))
+            .collect()
+    }
+
+    /// 本索引的语料统计: (文档数, 平均文档长度)
+    pub fn corpus_stats(&self) -> (usize, f64) {
+        (self.live_docs(), self.avgdl)
+    }
+
+    /// 文档数 (不含已删除的文档)
+    #[getter]
+    pub fn corpus_size(&self) -> usize {
+        self.live_docs()
+    }
+
+    /// 内部文档槽位数 (含已删除但尚未 compact 的文档)
+    ///
+    /// get_scores 返回的列表与 search_with_secondary 的 secondary 都按槽位排列
+    #[getter]
+    pub fn num_slots(&self) -> usize {
+        self.corpus_size
+    }
+
+    /// 平均文档长度
+    #[getter]
+    pub fn avgdl(&self) -> f64 {
+        self.avgdl
+    }
+
+    /// 词表大小 (索引中不同词的个数)
+    #[getter]
+    pub fn vocab_size(&self) -> usize {
+        self.index.len()
+    }
+
+    /// 包含某个词的文档数 (词按索引规则规范化，不在索引中时为 0)
+    pub fn doc_count(&self, term: &str) -> usize {
+        self.index
+            .get(&self.index_term(term))
+            .map_or(0, |list| list.doc_count)
+    }
+
+    /// 以导出的词表统计作为全局统计量注入 (只使用文档频率)，等价于 set_global_stats
+    pub fn import_vocab(
+        &mut self,
+        corpus_size: usize,
+        avgdl: f64,
+        vocab: HashMap<String, (usize, u64)>,
+    ) -> PyResult<()> {
+        let doc_freqs = vocab
+            .into_iter()
+            .map(|(term, (doc_count, _))| (term, doc_count))
+            .collect();
+        self.set_global_stats(corpus_size, avgdl, doc_freqs)
+    }
+
+    /// 清除注入的全局统计量，恢复使用本索引统计
+    pub fn clear_global_stats(&mut self) {
+        if self.global_stats.take().is_some() {
+            self.mark_max_scores_dirty();
+        }
+    }
+
+    /// 设置外部 idf: 词 -> idf (如来自通用语料的词重要性)，替换之前的设置，传空字典即清除
+    ///
+    /// 指定的词直接使用给定的 idf (优先于 use_idf、weighting 与全局统计)，其余词照常计算。
+    /// 随索引保存，重新 fit 后仍然有效
+    pub fn set_idf_overrides(&mut self, overrides: HashMap<String, f64>) -> PyResult<()> {
+        if overrides.values().any(|idf| !idf.is_finite() || *idf < 0.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "idf overrides must be finite non-negative numbers",
+            ));
+        }
+        self.idf_overrides = self.normalize_term_keys(overrides);
+        self.mark_max_scores_dirty();
+        Ok(())
+    }
+
+    /// 设置词级权重: 词 -> 乘数 (如领域中的诊断编码)，替换之前的设置，传空字典即清除
+    ///
+    /// 该词在任何查询中的得分贡献都乘以 boost (等价于 idf 乘以 boost，同时作用于 Block
+    /// max_score，开启 idf_length_coupling 时 b 也按加权后的 idf 计算)。
+    /// 与查询时加权不同，这是索引的持久属性，随索引保存
+    pub fn set_term_boosts(&mut self, boosts: HashMap<String, f64>) -> PyResult<()> {
+        if boosts
+            .values()
+            .any(|boost| !boost.is_finite() || *boost < 0.0)
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "term boosts must be finite non-negative numbers",
+            ));
+        }
+        self.term_boosts = self.normalize_term_keys(boosts);
+        self.mark_max_scores_dirty();
+        Ok(())
+    }
+
+    /// 重新计算所有 Block 的 max_score
+    ///
+    /// 影响打分的修改 (如文档乘数) 只会把 max_score 标记为过期，
+    /// 下一次搜索会自动重建一次 (耗时与索引大小成正比)；
+    /// 也可以在修改后手动调用，把重建开销移出查询路径
+    pub fn rebuild_block_max_scores(&self) {
+        for (term, list) in &self.index {
+            let idf = self.calc_idf(term, list);
+            for block in &list.blocks {
+                let max_score = (0..block.doc_ids.len())
+                    .map(|i| self.posting_score(idf, block.doc_ids[i], block.tf(i)))
+                    .fold(0.0, f64::max);
+                block.max_score.set(self.block_max_score(max_score));
+            }
+        }
+        self.max_scores_dirty.store(false, AtomicOrdering::Release);
+    }
+
+    /// 整理增量修改 (update_document 等) 产生的碎片 Block
+    ///
+    /// 填充率低于 min_fill_ratio (相对 block_size) 的块与后一个块合并，接近满的块不改写，
+    /// 碎片不多时代价远小于完整重建；min_fill_ratio=1.0 时完全压实 (与重新 fit 的分块一致)。
+    /// 返回减少的 Block 数
+    #[pyo3(signature = (min_fill_ratio=1.0))]
+    pub fn optimize(&mut self, min_fill_ratio: f64) -> PyResult<usize> {
+        if !(0.0..=1.0).contains(&min_fill_ratio) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "min_fill_ratio must be between 0 and 1",
+            ));
+        }
+        let min_len = (min_fill_ratio * self.block_size as f64).ceil() as usize;
+        let block_size = self.block_size;
+        Ok(self
+            .index
+            .values_mut()
+            .map(|list| list.compact(min_len, block_size))
+            .sum())
+    }
+
+    /// 判断两个索引在功能上是否等价 (用于验证不同构建方式的结果，如合并与重新 fit)
+    ///
+    /// 要求参数、文档表与词表一致，各词的 Block 划分与 Posting (doc_id, 词频, 文档长度,
+    /// 位置) 完全相同；avgdl、加权词频、文档权重与 Block max_score 允许 tolerance 内的误差
+    #[pyo3(signature = (other, tolerance=1e-9))]
+    pub fn approx_eq(&self, other: PyRef<'_, BM25>, tolerance: f64) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= tolerance;
+        let all_close = |a: &[f64], b: &[f64]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| close(x, y))
+        };
+        let settings = |m: &BM25| {
+            (
+                (
+                    m.lowercase,
+                    m.store_positions,
+                    m.stopword_mode,
+                    m.unk_df_threshold,
+                ),
+                (
+                    m.f32_scores,
+                    m.empty_doc_policy,
+                    m.empty_query_fallback,
+                    m.weighted_tf,
+                ),
+                (m.binary_tf, m.use_idf, m.weighting, m.chunk_size),
+                (m.query_lowercase, m.char_fallback, m.cut_mode, m.use_hmm),
+                m.block_size,
+            )
+        };
+        let same_values = |a: &HashMap<String, f64>, b: &HashMap<String, f64>| {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(term, &x)| b.get(term).is_some_and(|&y| close(x, y)))
+        };
+        let same_global_stats = match (&self.global_stats, &other.global_stats) {
+            (None, None) => true,
+            (Some(a), Some(b)) => {
+                a.corpus_size == b.corpus_size
+                    && close(a.avgdl, b.avgdl)
+                    && a.doc_freqs == b.doc_freqs
+            }
+            _ => false,
+        };
+        if !(close(self.k1, other.k1)
+            && close(self.b, other.b)
+            && settings(self) == settings(&other)
+            && self.idf_length_coupling == other.idf_length_coupling
+            && self.segmenters == other.segmenters
+            && self.user_dict == other.user_dict
+            && self.stopwords == other.stopwords
+            && self.unk_terms == other.unk_terms
+            && same_values(&self.idf_overrides, &other.idf_overrides)
+            && same_values(&self.term_boosts, &other.term_boosts)
+            && same_values(&self.field_weights, &other.field_weights)
+            && same_global_stats
+            && self.corpus_size == other.corpus_size
+            && self.deleted == other.deleted
+            && close(self.avgdl, other.avgdl)
+            && self.doc_lengths == other.doc_lengths
+            && self.doc_ids == other.doc_ids
+            && self.metadata == other.metadata
+            && all_close(&self.doc_weights, &other.doc_weights)
+            && self.index.len() == other.index.len())
+        {
+            return false;
+        }
+
+        self.ensure_max_scores();
+        other.ensure_max_scores();
+        self.index.iter().all(|(term, list)| {
+            let Some(other_list) = other.index.get(term) else {
+                return false;
+            };
+            list.doc_count == other_list.doc_count
+                && list.blocks.len() == other_list.blocks.len()
+                && list.blocks.iter().zip(&other_list.blocks).all(|(a, b)| {
+                    a.doc_ids == b.doc_ids
+                        && a.freqs == b.freqs
+                        && a.positions == b.positions
+                        && all_close(&a.weighted_freqs, &b.weighted_freqs)
+                        && close(a.max_score.get(), b.max_score.get())
+                })
+        })
+    }
+
+    /// 转换为只读的检索结构 BM25Reader (用于构建完成后的在线服务)
+    ///
+    /// 索引数据移交给 BM25Reader，本实例变为参数相同的空索引；
+    /// 转换时释放各数组的预留容量、丢弃检索用不到的元数据与词位置，并预先重建 max_score
+    pub fn into_reader(&mut self) -> BM25Reader {
+        let empty = self.empty_copy();
+        let mut index = std::mem::replace(self, empty);
+        index.compact_for_reading();
+        BM25Reader { index }
+    }
+
+    /// 索引占用的堆内存估算 (字节)，按各容器的容量统计
+    pub fn memory_usage(&self) -> usize {
+        let strings = |set: &HashSet<String>| {
+            set.capacity() * size_of::<String>() + set.iter().map(String::capacity).sum::<usize>()
+        };
+        let index: usize = self
+            .index
+            .iter()
+            .map(|(term, list)| term.capacity() + list.memory_usage())
+            .sum();
+        let metadata: usize = self
+            .metadata
+            .iter()
+            .map(|m| {
+                m.capacity() * size_of::<(String, String)>()
+                    + m.iter()
+                        .map(|(k, v)| k.capacity() + v.capacity())
+                        .sum::<usize>()
+            })
+            .sum();
+        self.index.capacity() * size_of::<(String, InvertedList)>()
+            + index
+            + self.doc_lengths.capacity() * size_of::<u32>()
+            + self.doc_ids.capacity() * size_of::<u64>()
+            + self.doc_weights.capacity() * size_of::<f64>()
+            + self.metadata.capacity() * size_of::<Metadata>()
+            + metadata
+            + strings(&self.stopwords)
+            + strings(&self.unk_terms)
+            + self.user_dict.capacity()
+    }
+
+    /// 序列化索引为字节串 (MessagePack，与 save 的文件内容相同)
+    ///
+    /// 用于把索引存入对象存储、数据库或缓存，无需经过临时文件
+    pub fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        rmp_serde::to_vec(self)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    /// 从 to_bytes (或 save 的文件内容) 反序列化索引
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let mut bm25: BM25 = match rmp_serde::from_slice(data) {
+            Ok(bm25) => bm25,
+            // 新版本库写出的索引可能无法按当前结构解析，优先报告版本不兼容
+            Err(e) => {
+                check_format_version(peek_format_version(data))?;
+                return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()));
+            }
+        };
+        check_format_version(Some(bm25.version))?;
+        bm25.after_load()?;
+        Ok(bm25)
+    }
+
+    /// 保存索引到文件 (MessagePack)
+    pub fn save(&self, path: &str) -> PyResult<()> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// 从文件加载索引 (MessagePack)
+    #[staticmethod]
+    pub fn load(path: &str) -> PyResult<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// 保存索引到 JSON 文件，便于人工查看、比较或由其他语言读取 (体积远大于 MessagePack)
+    pub fn save_json(&self, path: &str) -> PyResult<()> {
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(())
+    }
+
+    /// 从 save_json 保存的 JSON 文件加载索引
+    ///
+    /// Block max_score 在 JSON 中可能按 f32 精度写出，加载后标记过期，在首次搜索时重建
+    #[staticmethod]
+    pub fn load_json(path: &str) -> PyResult<Self> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let mut bm25: BM25 = serde_json::from_reader(reader)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        check_format_version(Some(bm25.version))?;
+        bm25.after_load()?;
+        bm25.mark_max_scores_dirty();
+        Ok(bm25)
+    }
+
+    /// 尽力从部分损坏的索引文件中恢复 (应急用)
+    ///
+    /// 逐个读取词条，丢弃无法解析或内容不自洽的倒排列表，并向后搜索下一个完好的词条继续读取；
+    /// 文档表 (文档长度、外部 ID 等) 必须完好，否则仍然报错。
+    /// 返回: (BM25, LoadReport)；文件完好时与 load 结果相同
+    #[staticmethod]
+    pub fn load_lenient(path: &str) -> PyResult<(Self, LoadReport)> {
+        let bytes = std::fs::read(path)?;
+        check_format_version(peek_format_version(&bytes))?;
+        let (mut bm25, report) = match rmp_serde::from_slice::<BM25>(&bytes) {
+            Ok(bm25) => {
+                let report = LoadReport {
+                    recovered_terms: bm25.index.len(),
+                    ..LoadReport::default()
+                };
+                (bm25, report)
+            }
+            Err(_) => salvage_index(&bytes).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>("index file is too damaged to recover")
+            })?,
+        };
+        bm25.after_load()?;
+        Ok((bm25, report))
+    }
+}
+
+impl BM25 {
+    /// fit 的实现 (不持有 GIL)
+    fn fit_documents(
+        &mut self,
+        documents: Vec<String>,
+        ids: Option<Vec<u64>>,
+        metadata: Option<Vec<Metadata>>,
+        chunk_size: Option<usize>,
+    ) -> PyResult<()> {
+        if chunk_size == Some(0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "chunk_size must be positive",
+            ));
+        }
+        if let Some(ref external_ids) = ids {
+            if external_ids.len() != documents.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "documents and ids must have the same length",
+                ));
+            }
+        }
+        if let Some(ref metadata) = metadata {
+            if metadata.len() != documents.len() {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "documents and metadata must have the same length",
+                ));
+            }
+        }
+        let empty: Vec<usize> = documents
+            .iter()
+            .enumerate()
+            .filter(|(_, doc)| is_blank(doc))
+            .map(|(i, _)| i)
+            .collect();
+        self.check_empty_documents(&empty)?;
+
+        // 分块模式需要预先知道分块数，先切分全部文档；否则逐文档流式分词
+        let mut chunks = chunk_size.map(|size| self.split_chunks(&documents, size));
+        match &chunks {
+            Some((parents, _)) => {
+                let parent_ids = ids.unwrap_or_else(|| (0..documents.len() as u64).collect());
+                let chunk_ids = parents.iter().map(|&i| parent_ids[i]).collect();
+                let chunk_metadata =
+                    metadata.map(|m| parents.iter().map(|&i| m[i].clone()).collect());
+                self.reset_for_fit(parents.len(), Some(chunk_ids), chunk_metadata);
+                self.chunk_size = chunk_size;
+            }
+            None => self.reset_for_fit(documents.len(), ids, metadata),
+        }
+        self.weighted_tf = false;
+
+        let mut temp_index: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut total_length: u64 = 0;
+
+        // 1. 分词并收集 Postings: 每批文档并行分词统计，再按文档顺序合并 (结果与串行一致)
+        for start in (0..self.corpus_size).step_by(FIT_BATCH_SIZE) {
+            let end = (start + FIT_BATCH_SIZE).min(self.corpus_size);
+            let this = &*self;
+            let batch: Vec<DocPostings> = match &mut chunks {
+                Some((_, tokens)) => run_parallel(|| {
+                    tokens[start..end]
+                        .par_iter_mut()
+                        .enumerate()
+                        .map(|(i, tokens)| {
+                            let tokens = std::mem::take(tokens).into_iter();
+                            this.doc_postings((start + i) as u32, tokens.map(|t| (t, 1.0)))
+                        })
+                        .collect()
+                }),
+                None => run_parallel(|| {
+                    documents[start..end]
+                        .par_iter()
+                        .enumerate()
+                        .map(|(i, doc)| {
+                            let tokens = this.tokenize(doc, this.lowercase);
+                            this.doc_postings((start + i) as u32, tokens.map(|t| (t, 1.0)))
+                        })
+                        .collect()
+                }),
+            };
+
+            for (doc_len, postings) in batch {
+                self.doc_lengths.push(doc_len);
+                total_length += doc_len as u64;
+                merge_postings(&mut temp_index, postings);
+            }
+        }
+
+        self.finish_fit(temp_index, total_length);
+        Ok(())
+    }
+
+    /// 流式训练 (fit_from_file 系列与 fit_iter 的实现，不持有 GIL)
+    ///
+    /// next_batch 每次返回至多 FIT_BATCH_SIZE 个 (外部 ID, 文档)，返回空批表示结束；
+    /// 外部 ID 为 None 时使用文档序号。每批分词统计后即丢弃原文。
+    /// 读取或校验出错时索引保持不变
+    fn fit_streaming<F>(&mut self, mut next_batch: F) -> PyResult<()>
+    where
+        F: FnMut() -> PyResult<Vec<(Option<u64>, String)>>,
+    {
+        let mut temp_index: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut doc_ids: Vec<u64> = Vec::new();
+        let mut doc_lengths: Vec<u32> = Vec::new();
+        let mut total_length: u64 = 0;
+        loop {
+            let batch = next_batch()?;
+            if batch.is_empty() {
+                break;
+            }
+            let start = doc_ids.len();
+            let empty: Vec<usize> = batch
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, doc))| is_blank(doc))
+                .map(|(i, _)| start + i)
+                .collect();
+            self.check_empty_documents(&empty)?;
+
+            let this = &*self;
+            let postings: Vec<DocPostings> = run_parallel(|| {
+                batch
+                    .par_iter()
+                    .enumerate()
+                    .map(|(i, (_, doc))| {
+                        let tokens = this.tokenize(doc, this.lowercase);
+                        this.doc_postings((start + i) as u32, tokens.map(|t| (t, 1.0)))
+                    })
+                    .collect()
+            });
+            for (doc_len, postings) in postings {
+                doc_lengths.push(doc_len);
+                total_length += doc_len as u64;
+                merge_postings(&mut temp_index, postings);
+            }
+            doc_ids.extend(
+                batch
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (id, _))| id.unwrap_or((start + i) as u64)),
+            );
+        }
+
+        self.reset_for_fit(doc_ids.len(), Some(doc_ids), None);
+        self.weighted_tf = false;
+        self.doc_lengths = doc_lengths;
+        self.finish_fit(temp_index, total_length);
+        Ok(())
+    }
+
+    /// 搜索 Top-K 文档，返回按分数降序排列的内部结果
+    ///
+    /// filter: 候选文档过滤器，仅对能进入 Top-K 的候选调用，返回 false 则丢弃
+    fn search_internal<F>(&self, query: &str, options: &SearchOptions, filter: F) -> Vec<ScoredDoc>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        self.search_internal_with_stats(query, options, filter, None, None)
+            .0
+    }
+
+    /// 同 search_internal，额外返回诊断信息
+    ///
+    /// trace: 传入时记录进入 Top-K 的文档打分时各命中词所在的 Block 下标
+    /// timing: 传入时记录分词与建立游标的耗时 (其余阶段由调用方计时)
+    fn search_internal_with_stats<F>(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        mut filter: F,
+        mut trace: Option<&mut BlockTrace>,
+        timing: Option<&mut SearchTiming>,
+    ) -> (Vec<ScoredDoc>, SearchStats)
+    where
+        F: FnMut(u32) -> bool,
+    {
+        let mut stats = SearchStats::default();
+        // 退化情况直接返回，不分词也不访问索引 (空白查询在开启兜底排序时仍走兜底)
+        let blank_query = match &options.query_terms {
+            Some(terms) => terms.is_empty(),
+            None => query.trim().is_empty(),
+        } && self.empty_query_fallback == FallbackMode::Disabled;
+        if options.k == 0 || self.corpus_size == 0 || blank_query {
+            return (Vec::new(), stats);
+        }
+        self.ensure_max_scores();
+        let over_budget =
+            |scanned: usize| options.max_blocks_scanned.is_some_and(|max| scanned > max);
+        let start = timing.is_some().then(Instant::now);
+        let query_tokens = match &options.query_terms {
+            Some(terms) => terms.iter().map(|t| Cow::Borrowed(t.as_str())).collect(),
+            None => self.query_tokens(query),
+        };
+        let tokenized = start.map(|_| Instant::now());
+        let mut top_k = TopK::new(options.k, self.corpus_size);
+
+        // 收集所有相关词的 Block 迭代器 (terms 与 cursors 一一对应)。重复的查询词共用
+        // 一个游标，只计分一次；phrase_order 记录每个查询词对应的游标，用于短语匹配
+        let mut cursors: Vec<BlockCursor> = Vec::new();
+        let mut terms: Vec<&str> = Vec::new();
+        let mut phrase_order: Vec<usize> = Vec::with_capacity(query_tokens.len());
+        let mut all_found = true;
+        for token in query_tokens {
+            if let Some(i) = terms.iter().position(|&t| t == token.as_ref()) {
+                phrase_order.push(i);
+                continue;
+            }
+            match self.index.get_key_value(token.as_ref()) {
+                Some((term, inv_list)) if !inv_list.blocks.is_empty() => {
+                    // ZeroWeight 停用词仍参与短语匹配，但贡献为 0
+                    let idf = if self.is_zero_weight(&token) {
+                        0.0
+                    } else {
+                        self.calc_idf(&token, inv_list)
+                    };
+                    let mut cursor = BlockCursor::new(inv_list, idf);
+                    if let Some(&weight) = options.term_weights.get(token.as_ref()) {
+                        cursor.weight = weight;
+                    }
+                    phrase_order.push(cursors.len());
+                    cursors.push(cursor);
+                    terms.push(term);
+                }
+                // 合取查询中任一词不存在，则不可能有文档命中
+                _ if options.require_all_terms => return (Vec::new(), stats),
+                _ => all_found = false,
+            }
+        }
+        let distinct_found = cursors.len();
+
+        if let (Some(timing), Some(start), Some(tokenized)) = (timing, start, tokenized) {
+            timing.tokenize_us = micros(tokenized - start);
+            timing.setup_us = micros(tokenized.elapsed());
+        }
+
+        if cursors.is_empty() {
+            return (self.fallback_docs(options.k, filter), stats);
+        }
+
+        // 截断长查询: 仅保留 idf 最高的若干个词 (保持原查询顺序)
+        if let Some(max_terms) = options.max_query_terms {
+            if cursors.len() > max_terms {
+                let mut order: Vec<usize> = (0..cursors.len()).collect();
+                order.sort_by(|&a, &b| cursors[b].idf.total_cmp(&cursors[a].idf));
+                let keep: HashSet<usize> = order.into_iter().take(max_terms).collect();
+                let mut i = 0;
+                cursors.retain(|_| {
+                    i += 1;
+                    keep.contains(&(i - 1))
+                });
+                let mut i = 0;
+                terms.retain(|_| {
+                    i += 1;
+                    keep.contains(&(i - 1))
+                });
+            }
+        }
+
+        // 只有全部查询词都在索引中 (且未被截断) 时才可能命中完整短语
+        let phrase_boost = if all_found && cursors.len() == distinct_found {
+            options.phrase_boost
+        } else {
+            0.0
+        };
+
+        // 单词查询走快速路径 (长度先验不在 Block max_score 上界内，不能整块跳过；
+        // 加权的词走通用路径)
+        if cursors.len() == 1
+            && phrase_boost == 0.0
+            && options.length_prior.is_none()
+            && cursors[0].weight == 1.0
+        {
+            let cursor = &cursors[0];
+            let docs = self.single_term_top_k(cursor.list, cursor.idf, options, &mut stats, filter);
+            if let Some(trace) = trace {
+                // 快速路径按块顺序扫描，命中文档所在的块即其 Posting 所在块
+                for d in &docs {
+                    let block_idx = cursor
+                        .list
+                        .blocks
+                        .partition_point(|b| b.last_doc_id < d.doc_id);
+                    trace.insert(d.doc_id, vec![(terms[0].to_string(), block_idx)]);
+                }
+            }
+            return (docs, stats);
+        }
+
+        if options.require_all_terms {
+            let mut intersection = Intersection::new(cursors);
+            while let Some(doc_id) = intersection.next_doc() {
+                let cursors = intersection.cursors();
+                if over_budget(blocks_scanned(cursors)) {
+                    stats.truncated = true;
+                    break;
+                }
+                stats.docs_scored += 1;
+                let mut score: f64 = cursors.iter().map(|c| self.cursor_score(c)).sum();
+                if phrase_boost != 0.0 {
+                    let positions: Vec<&[u32]> = phrase_order
+                        .iter()
+                        .map(|&i| cursors[i].curr_positions())
+                        .collect();
+                    if is_phrase(&positions) {
+                        score += phrase_boost;
+                    }
+                }
+                // 只命中 ZeroWeight 停用词的文档不计入结果
+                let total = score + self.length_bonus(doc_id, options);
+                if score > 0.0 && top_k.accepts(total) && filter(doc_id) {
+                    top_k.push(total, doc_id);
+                    if let Some(trace) = trace.as_deref_mut() {
+                        let blocks = terms
+                            .iter()
+                            .zip(cursors)
+                            .map(|(term, c)| (term.to_string(), c.block_idx))
+                            .collect();
+                        trace.insert(doc_id, blocks);
+                    }
+                }
+                intersection.advance();
+            }
+            stats.blocks_scanned =
+                self.capped_blocks(blocks_scanned(intersection.cursors()), options);
+            return (top_k.into_sorted_vec(), stats);
+        }
+
+        // 覆盖率阈值换算为命中词 idf 之和的下限 (单词查询与合取查询的覆盖率恒为 1.0)
+        let min_covered_idf = options
+            .min_coverage
+            .map_or(0.0, |c| c * cursors.iter().map(|c| c.idf).sum::<f64>());
+
+        // WAND 剪枝使用的各词得分上界。完整短语加分只在全部词命中时出现，一并计入上界；
+        // 长度先验不在上界内，开启时不剪枝
+        let upper_bounds: Vec<f64> = cursors.iter().map(|c| c.max_score().max(0.0)).collect();
+        let phrase_bound = phrase_boost.max(0.0);
+        let prune = options.length_prior.is_none();
+        // pivot 选取时上界与实际得分的求和顺序不同，留出浮点误差余量，避免误剪恰好超过阈值的文档
+        let may_enter = |top_k: &TopK, bound: f64| top_k.accepts(bound * (1.0 + 1e-12));
+        let mut order: Vec<usize> = Vec::with_capacity(cursors.len());
+
+        // BMW/WAND 逻辑
+        let mut active_cursors: Vec<&mut BlockCursor> = cursors.iter_mut().collect();
+
+        loop {
+            // 1. 找出当前所有 cursor 中最小的 doc_id
+            let mut min_doc_id = u32::MAX;
+            let mut all_finished = true;
+
+            for cursor in &active_cursors {
+                if let Some(doc_id) = cursor.curr_doc_id() {
+                    all_finished = false;
+                    if doc_id < min_doc_id {
+                        min_doc_id = doc_id;
+                    }
+                }
+            }
+
+            if all_finished {
+                break;
+            }
+            if over_budget(active_cursors.iter().map(|c| c.blocks_scanned).sum()) {
+                stats.truncated = true;
+                break;
+            }
+
+            // 2. WAND 剪枝: 按当前 doc_id 排序游标并累加得分上界，累加值首次可能进入 Top-K
+            // 的游标为 pivot；pivot 之前的文档只可能命中其前面的游标，上界不足，直接跳过
+            if prune {
+                order.clear();
+                order.extend(
+                    (0..active_cursors.len())
+                        .filter(|&i| active_cursors[i].curr_doc_id().is_some()),
+                );
+                order.sort_by_key(|&i| active_cursors[i].curr_doc_id());
+                let mut bound = phrase_bound;
+                let pivot = order.iter().find_map(|&i| {
+                    bound += upper_bounds[i];
+                    may_enter(&top_k, bound).then(|| active_cursors[i].curr_doc_id())
+                });
+                // 剩余文档的上界都不足以进入 Top-K
+                let Some(Some(pivot_doc)) = pivot else {
+                    break;
+                };
+                if pivot_doc > min_doc_id {
+                    for cursor in &mut active_cursors {
+                        if cursor.curr_doc_id().is_some_and(|d| d < pivot_doc) {
+                            cursor.skip_to(pivot_doc);
+                        }
+                    }
+                    continue;
+                }
+
+                // Block 级检查: 用命中词当前 Block 的 max_score 收紧该文档的上界。
+                // 求和顺序与下面的实际打分相同 (查询顺序，短语加分最后)，无需误差余量
+                let block_bound = active_cursors
+                    .iter()
+                    .filter(|c| c.curr_doc_id() == Some(min_doc_id))
+                    .map(|c| c.block_max_score().max(0.0))
+                    .sum::<f64>()
+                    + phrase_bound;
+                if !top_k.accepts(block_bound) {
+                    // 在命中词的当前 Block 内、其余词的下一个文档之前，候选文档只可能命中这些
+                    // Block，上界同样不足，整段跳过 (Block-Max WAND)
+                    let next_doc = active_cursors
+                        .iter()
+                        .filter_map(|c| match c.curr_doc_id() {
+                            Some(d) if d == min_doc_id => {
+                                Some(c.block_last_doc_id().saturating_add(1))
+                            }
+                            other => other,
+                        })
+                        .min()
+                        .unwrap_or(u32::MAX);
+                    for cursor in &mut active_cursors {
+                        if cursor.curr_doc_id().is_some_and(|d| d < next_doc) {
+                            cursor.skip_to(next_doc);
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            // 3. 计算 min_doc_id 的准确分数
+            let mut score = 0.0;
+            let mut matched = 0;
+            let mut covered_idf = 0.0;
+
+            for cursor in &active_cursors {
+                if cursor.curr_doc_id() == Some(min_doc_id) {
+                    score += self.cursor_score(cursor);
+                    matched += 1;
+                    covered_idf += cursor.idf;
+                }
+            }
+
+            if matched == 0 {
+                break;
+            }
+            stats.docs_scored += 1;
+
+            // 全部查询词都命中时检查短语 (cursor 顺序即查询顺序)
+            if phrase_boost != 0.0 && matched == active_cursors.len() {
+                let positions: Vec<&[u32]> = phrase_order
+                    .iter()
+                    .map(|&i| active_cursors[i].curr_positions())
+                    .collect();
+                if is_phrase(&positions) {
+                    score += phrase_boost;
+                }
+            }
+
+            // 游标前进之前记录命中词所在的 Block
+            let blocks: Option<BlockHits> = trace.is_some().then(|| {
+                terms
+                    .iter()
+                    .zip(&active_cursors)
+                    .filter(|(_, c)| c.curr_doc_id() == Some(min_doc_id))
+                    .map(|(term, c)| (term.to_string(), c.block_idx))
+                    .collect()
+            });
+
+            for cursor in &mut active_cursors {
+                if cursor.curr_doc_id() == Some(min_doc_id) {
+                    cursor.advance();
+                }
+            }
+
+            // 4. 更新堆 (只命中 ZeroWeight 停用词或覆盖率不足的文档不计入结果)
+            let total = score + self.length_bonus(min_doc_id, options);
+            if score > 0.0
+                && covered_idf >= min_covered_idf
+                && top_k.accepts(total)
+                && filter(min_doc_id)
+            {
+                top_k.push(total, min_doc_id);
+                if let (Some(trace), Some(blocks)) = (trace.as_deref_mut(), blocks) {
+                    trace.insert(min_doc_id, blocks);
+                }
+            }
+        }
+
+        stats.blocks_scanned = self.capped_blocks(blocks_scanned(&cursors), options);
+        (top_k.into_sorted_vec(), stats)
+    }
+
+    /// 文档长度先验加分: ln(doc_len + 1) * length_prior (未开启时为 0)
+    fn length_bonus(&self, doc_id: u32, options: &SearchOptions) -> f64 {
+        options.length_prior.map_or(0.0, |weight| {
+            (self.doc_lengths[doc_id as usize] as f64 + 1.0).ln() * weight
+        })
+    }
+
+    /// 查询词全部不在词表中时按 empty_query_fallback 返回兜底结果 (分数为 0)
+    fn fallback_docs<F>(&self, k: usize, mut filter: F) -> Vec<ScoredDoc>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        let mut doc_ids: Vec<u32> = (0..self.corpus_size as u32)
+            .filter(|d| !self.deleted.contains(d))
+            .collect();
+        match self.empty_query_fallback {
+            FallbackMode::Disabled => return Vec::new(),
+            FallbackMode::LengthSorted => doc_ids.sort_by_key(|&d| self.doc_lengths[d as usize]),
+            FallbackMode::IdSorted => doc_ids.sort_by_key(|&d| self.external_id(d)),
+        }
+        doc_ids
+            .into_iter()
+            .filter(|&d| filter(d))
+            .take(k)
+            .map(|doc_id| ScoredDoc { score: 0.0, doc_id })
+            .collect()
+    }
+
+    /// 报告的扫描块数不超过上限 (提前停止时最后进入的块并未扫描)
+    fn capped_blocks(&self, scanned: usize, options: &SearchOptions) -> usize {
+        options
+            .max_blocks_scanned
+            .map_or(scanned, |max| scanned.min(max))
+    }
+
+    /// 所有词都出现且位置落在 slop 窗口内的 Top-K 文档 (search_near / search_phrase 共用)
+    ///
+    /// keys: 索引中的词；任一词不在索引中时没有结果
+    fn near_top_k<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        slop: u32,
+        ordered: bool,
+        k: usize,
+    ) -> Vec<(u64, f64)> {
+        if keys.is_empty() {
+            return Vec::new();
+        }
+
+        let mut cursors = Vec::with_capacity(keys.len());
+        for key in keys {
+            let key = key.as_ref();
+            match self.index.get(key) {
+                Some(list) if !list.blocks.is_empty() => {
+                    let idf = if self.is_zero_weight(key) {
+                        0.0
+                    } else {
+                        self.calc_idf(key, list)
+                    };
+                    cursors.push(BlockCursor::new(list, idf));
+                }
+                _ => return Vec::new(),
+            }
+        }
+
+        // 重复的词参与位置匹配，但只计分一次
+        let first_occurrence: Vec<bool> = (0..keys.len())
+            .map(|i| keys[..i].iter().all(|k| k.as_ref() != keys[i].as_ref()))
+            .collect();
+
+        let mut top_k = TopK::new(k, self.corpus_size);
+        let mut intersection = Intersection::new(cursors);
+        while let Some(doc_id) = intersection.next_doc() {
+            let cursors = intersection.cursors();
+            let positions: Vec<&[u32]> = cursors.iter().map(|c| c.curr_positions()).collect();
+            if within_window(&positions, slop, ordered) {
+                let score: f64 = cursors
+                    .iter()
+                    .zip(&first_occurrence)
+                    .filter(|(_, &first)| first)
+                    .map(|(c, _)| self.cursor_score(c))
+                    .sum();
+                top_k.push(score, doc_id);
+            }
+            intersection.advance();
+        }
+        top_k
+            .into_sorted_vec()
+            .into_iter()
+            .map(|d| (self.external_id(d.doc_id), self.output_score(d.score)))
+            .collect()
+    }
+
+    /// 参与打分的查询词: 按查询顺序的 (倒排列表, idf)，重复的词只保留一次，
+    /// 跳过不在索引中的词与 ZeroWeight 停用词
+    fn scoring_terms(&self, query: &str) -> Vec<(&InvertedList, f64)> {
+        distinct_terms(self.query_tokens(query))
+            .into_iter()
+            .filter(|token| !self.is_zero_weight(token))
+            .filter_map(|token| {
+                let inv_list = self.index.get(token.as_ref())?;
+                // 计算 idf (注意：inv_list.doc_count 存储包含词 t 的文档总数 n(t))
+                Some((inv_list, self.calc_idf(&token, inv_list)))
+            })
+            .collect()
+    }
+
+    /// 遍历查询词的全部 Posting，回调 (内部 doc_id, 该词的 BM25 分数)
+    fn for_each_term_score<F>(&self, query: &str, mut f: F)
+    where
+        F: FnMut(u32, f64),
+    {
+        for (inv_list, idf) in self.scoring_terms(query) {
+            for block in &inv_list.blocks {
+                for i in 0..block.doc_ids.len() {
+                    let freq = block.tf(i);
+                    let doc_id = block.doc_ids[i];
+                    f(doc_id, self.posting_score(idf, doc_id, freq));
+                }
+            }
+        }
+    }
+
+    /// 把一个词在文档区间 [first, first + scores.len()) 内的分数累加到 scores
+    fn add_stripe_scores(&self, list: &InvertedList, idf: f64, first: u32, scores: &mut [f64]) {
+        let end = first as usize + scores.len();
+        let start = list.blocks.partition_point(|b| b.last_doc_id < first);
+        for block in &list.blocks[start..] {
+            for i in 0..block.doc_ids.len() {
+                let doc_id = block.doc_ids[i];
+                if doc_id as usize >= end {
+                    return;
+                }
+                if doc_id >= first {
+                    scores[(doc_id - first) as usize] +=
+                        self.posting_score(idf, doc_id, block.tf(i));
+                }
+            }
+        }
+    }
+
+    /// 稀疏打分: 只累加命中文档，返回按内部 doc_id 排序的 (doc_id, score)
+    fn sparse_scores(&self, query: &str) -> Vec<(u32, f64)> {
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        self.for_each_term_score(query, |doc_id, score| {
+            *scores.entry(doc_id).or_insert(0.0) += score;
+        });
+        let mut scores: Vec<(u32, f64)> = scores.into_iter().filter(|&(_, s)| s > 0.0).collect();
+        scores.sort_unstable_by_key(|&(doc_id, _)| doc_id);
+        scores
+    }
+
+    /// 单词 Top-K 快速路径
+    ///
+    /// 不需要多游标对齐；Top-K 已满时直接跳过 max_score 不超过当前阈值的整块
+    fn single_term_top_k<F>(
+        &self,
+        list: &InvertedList,
+        idf: f64,
+        options: &SearchOptions,
+        stats: &mut SearchStats,
+        mut filter: F,
+    ) -> Vec<ScoredDoc>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        self.ensure_max_scores();
+        let mut top_k = TopK::new(options.k, self.corpus_size);
+        if idf <= 0.0 {
+            return Vec::new();
+        }
+        for block in &list.blocks {
+            if !top_k.accepts(block.max_score.get()) {
+                continue;
+            }
+            if options
+                .max_blocks_scanned
+                .is_some_and(|max| stats.blocks_scanned >= max)
+            {
+                stats.truncated = true;
+                break;
+            }
+            stats.blocks_scanned += 1;
+            stats.docs_scored += block.doc_ids.len();
+            for i in 0..block.doc_ids.len() {
+                let doc_id = block.doc_ids[i];
+                let score = self.posting_score(idf, doc_id, block.tf(i));
+                if top_k.accepts(score) && filter(doc_id) {
+                    top_k.push(score, doc_id);
+                }
+            }
+        }
+        top_k.into_sorted_vec()
+    }
+
+    /// 内部 ID -> 外部 ID
+    fn external_id(&self, internal_id: u32) -> u64 {
+        let internal_id = internal_id as usize;
+        if internal_id < self.doc_ids.len() {
+            self.doc_ids[internal_id]
+        } else {
+            internal_id as u64 // Fallback, shout not happen
+        }
+    }
+
+    /// 外部 ID -> 内部 ID (重复的外部 ID 取第一个未删除的文档)
+    fn internal_id(&self, external_id: u64) -> Option<usize> {
+        self.id_map.get(&external_id).copied()
+    }
+
+    /// 按 doc_ids 与墓碑重建外部 ID 映射与默认的下一个 ID
+    ///
+    /// fit、compact、merge 与加载后调用；add_document / delete_document 增量维护
+    fn rebuild_id_map(&mut self) {
+        self.id_map.clear();
+        for (doc_id, &id) in self.doc_ids.iter().enumerate() {
+            if !self.deleted.contains(&(doc_id as u32)) {
+                self.id_map.entry(id).or_insert(doc_id);
+            }
+        }
+        self.next_id = match self.doc_ids.iter().max() {
+            Some(&max) => max.checked_add(1),
+            None => Some(0),
+        };
+    }
+
+    /// 未删除的文档数 (idf 与 avgdl 的统计口径)
+    fn live_docs(&self) -> usize {
+        self.corpus_size - self.deleted.len()
+    }
+
+    /// 按文档长度表重新计算 avgdl (已删除文档的长度为 0，不计入)
+    fn refresh_avgdl(&mut self) {
+        let total_length: u64 = self.doc_lengths.iter().map(|&l| l as u64).sum();
+        self.avgdl = match self.live_docs() {
+            0 => 0.0,
+            live => total_length as f64 / live as f64,
+        };
+    }
+
+    /// 分词，尽量借用原文本以避免为每个词分配 String
+    ///
+    /// 仅当开启 lowercase 且词中含有大写字母时才会分配新字符串。
+    /// 返回迭代器，fit 时逐个词直接计入词频表，不收集中间的词列表
+    /// lowercase: 索引侧传 self.lowercase，查询侧传 query_lowercase()
+    fn tokenize<'s, 'a: 's>(
+        &'s self,
+        text: &'a str,
+        lowercase: bool,
+    ) -> impl Iterator<Item = Cow<'a, str>> + 's {
+        self.segment(text)
+            .into_iter()
+            .filter(|s| !is_blank(s))
+            .map(move |s| {
+                if lowercase && s.chars().any(char::is_uppercase) {
+                    Cow::Owned(s.to_lowercase())
+                } else {
+                    Cow::Borrowed(s)
+                }
+            })
+            .filter(move |s| {
+                self.stopword_mode != StopwordMode::Remove || !self.stopwords.contains(s.as_ref())
+            })
+    }
+
+    /// 切分原文本 (未做过滤与小写)
+    ///
+    /// 集成分词时合并各分词器的结果，按起始位置排序，并去除起始位置相同的重复词
+    fn segment<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        if let [tokenizer] = self.tokenizers.as_slice() {
+            return tokenizer.tokenize(text);
+        }
+
+        let mut pieces: Vec<(usize, &'a str)> = Vec::new();
+        for tokenizer in &self.tokenizers {
+            let segmented = tokenizer.tokenize(text);
+            // 切分结果都是原文本的切片，由指针偏移得到起始位置
+            pieces.extend(
+                segmented
+                    .into_iter()
+                    .map(|s| (s.as_ptr() as usize - text.as_ptr() as usize, s)),
+            );
+        }
+        // 稳定排序: 同一位置按分词器顺序排列
+        pieces.sort_by_key(|&(start, _)| start);
+        pieces.dedup();
+        pieces.into_iter().map(|(_, s)| s).collect()
+    }
+
+    /// 按 segmenters、cut_mode、use_hmm 与实例词典重建分词器 (segmenters 为空时仅使用 jieba)
+    ///
+    /// 分词器不序列化，由这些随索引保存的设置完全确定；创建、加载词典与加载索引后调用
+    fn rebuild_tokenizers(&mut self) {
+        let segmenters = match self.segmenters.as_slice() {
+            [] => &[Segmenter::Jieba][..],
+            segmenters => segmenters,
+        };
+        self.tokenizers = segmenters
+            .iter()
+            .map(|segmenter| -> Box<dyn Tokenizer> {
+                match segmenter {
+                    Segmenter::Jieba => Box::new(JiebaTokenizer {
+                        jieba: self.custom_jieba.clone(),
+                        cut_mode: self.cut_mode,
+                        use_hmm: self.use_hmm,
+                    }),
+                    Segmenter::Bigram => Box::new(BigramTokenizer),
+                    Segmenter::Whitespace => Box::new(WhitespaceTokenizer),
+                }
+            })
+            .collect();
+    }
+
+    /// 查询词是否不参与打分 (ZeroWeight 模式下的停用词)
+    fn is_zero_weight(&self, term: &str) -> bool {
+        self.stopword_mode == StopwordMode::ZeroWeight && self.stopwords.contains(term)
+    }
+
+    /// 将低频单字的 Postings 合并到 <UNK> 词下
+    fn collapse_unk_terms(&mut self, temp_index: &mut HashMap<String, Vec<Posting>>) {
+        self.unk_terms.clear();
+        let Some(threshold) = self.unk_df_threshold else {
+            return;
+        };
+
+        let rare: Vec<String> = temp_index
+            .iter()
+            .filter(|(term, postings)| term.chars().count() == 1 && postings.len() < threshold)
+            .map(|(term, _)| term.clone())
+            .collect();
+        if rare.is_empty() {
+            return;
+        }
+
+        // 同一文档中多个低频单字合并为一个 Posting
+        let mut merged: HashMap<u32, Posting> = HashMap::new();
+        for term in rare {
+            for posting in temp_index.remove(&term).unwrap_or_default() {
+                match merged.get_mut(&posting.doc_id) {
+                    Some(existing) => {
+                        existing.freq += posting.freq;
+                        existing.weight += posting.weight;
+                        existing.positions.extend(posting.positions);
+                        existing.positions.sort_unstable();
+                    }
+                    None => {
+                        merged.insert(posting.doc_id, posting);
+                    }
+                }
+            }
+            self.unk_terms.insert(term);
+        }
+        temp_index.insert(UNK_TOKEN.to_string(), merged.into_values().collect());
+    }
+
+    /// 查询侧是否转为小写 (未单独设置时与索引一致)
+    fn query_lowercase(&self) -> bool {
+        self.query_lowercase.unwrap_or(self.lowercase)
+    }
+
+    /// 已合并到 <UNK> 的低频词映射为 <UNK>
+    fn unk_mapped<'a>(&self, token: Cow<'a, str>) -> Cow<'a, str> {
+        if self.unk_terms.contains(token.as_ref()) {
+            Cow::Borrowed(UNK_TOKEN)
+        } else {
+            token
+        }
+    }
+
+    /// 将调用方直接给出的词 (不分词) 规范化为索引中的词: 小写与 <UNK> 映射
+    fn index_term(&self, term: &str) -> String {
+        let key = if self.query_lowercase() {
+            term.to_lowercase()
+        } else {
+            term.to_string()
+        };
+        if self.unk_terms.contains(&key) {
+            UNK_TOKEN.to_string()
+        } else {
+            key
+        }
+    }
+
+    /// 查询分词: 使用查询侧的小写设置，并将已合并的低频词映射到 <UNK>
+    ///
+    /// 开启 char_fallback 时，不在词表中的多字词拆分为单字 (Remove 模式下跳过停用词)
+    fn query_tokens<'a>(&self, query: &'a str) -> Vec<Cow<'a, str>> {
+        let mut tokens = Vec::new();
+        for token in self.tokenize(query, self.query_lowercase()) {
+            if !self.char_fallback
+                || self.index.contains_key(token.as_ref())
+                || token.chars().nth(1).is_none()
+            {
+                tokens.push(self.unk_mapped(token));
+                continue;
+            }
+            let chars: Vec<Cow<'a, str>> = match token {
+                Cow::Borrowed(s) => s
+                    .char_indices()
+                    .map(|(i, c)| Cow::Borrowed(&s[i..i + c.len_utf8()]))
+                    .collect(),
+                Cow::Owned(s) => s.chars().map(|c| Cow::Owned(c.to_string())).collect(),
+            };
+            tokens.extend(
+                chars
+                    .into_iter()
+                    .filter(|c| {
+                        self.stopword_mode != StopwordMode::Remove
+                            || !self.stopwords.contains(c.as_ref())
+                    })
+                    .map(|c| self.unk_mapped(c)),
+            );
+        }
+        tokens
+    }
+
+    /// Reject 策略下存在空文档时报错
+    fn check_empty_documents(&self, empty: &[usize]) -> PyResult<()> {
+        if self.empty_doc_policy == EmptyDocPolicy::Reject && !empty.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "documents at indices {empty:?} are empty or contain only whitespace/control characters"
+            )));
+        }
+        Ok(())
+    }
+
+    /// 重新训练前清空索引并初始化 ID 映射与元数据
+    fn reset_for_fit(
+        &mut self,
+        corpus_size: usize,
+        ids: Option<Vec<u64>>,
+        metadata: Option<Vec<Metadata>>,
+    ) {
+        self.corpus_size = corpus_size;
+        self.index.clear();
+        self.doc_lengths.clear();
+
+        // 初始化 ID 映射
+        self.doc_ids = ids.unwrap_or_else(|| (0..corpus_size as u64).collect());
+        self.metadata = metadata.unwrap_or_default();
+        self.doc_weights.clear();
+        self.global_stats = None;
+        self.chunk_size = None;
+        self.deleted.clear();
+        self.field_weights.clear();
+        *self.max_scores_dirty.get_mut() = false;
+        self.rebuild_id_map();
+    }
+
+    /// 将每个文档的词序列按 chunk_size 切分
+    ///
+    /// 返回: (每个分块所属的原文档下标, 每个分块的词)；空文档保留为一个空分块
+    fn split_chunks<'a>(
+        &self,
+        documents: &'a [String],
+        chunk_size: usize,
+    ) -> (Vec<usize>, Vec<Vec<Cow<'a, str>>>) {
+        let tokenized: Vec<Vec<Cow<'a, str>>> = run_parallel(|| {
+            documents
+                .par_iter()
+                .map(|doc| self.tokenize(doc, self.lowercase).collect())
+                .collect()
+        });
+        let mut parents = Vec::new();
+        let mut chunks = Vec::new();
+        for (i, tokens) in tokenized.into_iter().enumerate() {
+            if tokens.is_empty() {
+                parents.push(i);
+                chunks.push(Vec::new());
+                continue;
+            }
+            for chunk in tokens.chunks(chunk_size) {
+                parents.push(i);
+                chunks.push(chunk.to_vec());
+            }
+        }
+        (parents, chunks)
+    }
+
+    /// 计算 avgdl 并由收集到的 Postings 构建倒排索引
+    fn finish_fit(&mut self, mut temp_index: HashMap<String, Vec<Posting>>, total_length: u64) {
+        self.avgdl = if self.corpus_size > 0 {
+            total_length as f64 / self.corpus_size as f64
+        } else {
+            0.0
+        };
+
+        self.collapse_unk_terms(&mut temp_index);
+
+        // 2. 构建 Block-Max 倒排索引 (各词互相独立，并行构建)
+        let this = &*self;
+        let lists: Vec<(String, InvertedList)> = run_parallel(|| {
+            temp_index
+                .into_par_iter()
+                .map(|(term, postings)| {
+                    let inverted_list = this.build_inverted_list(&term, postings);
+                    (term, inverted_list)
+                })
+                .collect()
+        });
+        self.index.extend(lists);
+    }
+
+    /// 统计单个文档的 Postings，返回 (文档长度, [(词, Posting)])
+    fn doc_postings<'a, I>(&self, doc_id: u32, tokens: I) -> DocPostings<'a>
+    where
+        I: IntoIterator<Item = (Cow<'a, str>, f64)>,
+    {
+        // 词 -> (词频, 加权词频, 位置列表)
+        let mut freq_map: HashMap<Cow<str>, (u32, f64, Vec<u32>)> = HashMap::new();
+        let mut token_count = 0;
+        for (pos, (token, weight)) in tokens.into_iter().enumerate() {
+            let entry = freq_map.entry(token).or_default();
+            entry.0 += 1;
+            entry.1 += weight;
+            if self.store_positions {
+                entry.2.push(pos as u32);
+            }
+            token_count += 1;
+        }
+        let doc_len = match self.empty_doc_policy {
+            EmptyDocPolicy::MinLength => token_count.max(1),
+            _ => token_count,
+        };
+
+        let postings = freq_map
+            .into_iter()
+            .map(|(term, (freq, weight, positions))| {
+                // binary_tf: 词频截断为 1 (位置仍完整保留)
+                let (freq, weight) = if self.binary_tf {
+                    (1, 1.0)
+                } else {
+                    (freq, weight)
+                };
+                let posting = Posting {
+                    doc_id,
+                    freq,
+                    weight,
+                    positions,
+                };
+                (term, posting)
+            })
+            .collect();
+        (doc_len, postings)
+    }
+
+    /// 由 Postings 构建 Block-Max 倒排列表
+    fn build_inverted_list(&self, term: &str, mut postings: Vec<Posting>) -> InvertedList {
+        postings.sort_by_key(|p| p.doc_id); // 按 doc_id 排序
+
+        let mut inverted_list = InvertedList {
+            doc_count: postings.len(),
+            collection_freq: postings.iter().map(|p| p.freq as u64).sum(),
+            blocks: Vec::new(),
+        };
+        let idf = self.calc_idf(term, &inverted_list);
+
+        for chunk in postings.chunks_mut(self.block_size) {
+            let mut max_score: f64 = 0.0;
+            let mut block = Block {
+                max_score: MaxScore::default(),
+                last_doc_id: chunk.last().unwrap().doc_id,
+                doc_ids: Vec::with_capacity(chunk.len()),
+                freqs: Vec::with_capacity(chunk.len()),
+                doc_lens: Vec::new(),
+                positions: Vec::new(),
+                weighted_freqs: Vec::new(),
+            };
+
+            for posting in chunk {
+                block.doc_ids.push(posting.doc_id);
+                block.freqs.push(posting.freq);
+                if self.store_positions {
+                    block.positions.push(std::mem::take(&mut posting.positions));
+                }
+                if self.weighted_tf {
+                    block.weighted_freqs.push(posting.weight);
+                }
+
+                // 计算该文档的 BM25 分数，更新 Block Max Score
+                let score = self.posting_score(idf, posting.doc_id, posting.weight);
+                max_score = max_score.max(score);
+            }
+            block.max_score = MaxScore::new(self.block_max_score(max_score));
+            inverted_list.blocks.push(block);
+        }
+        inverted_list
+    }
+
+    /// 从元数据读取每个文档的数值 (按内部ID排列，缺失视为 0)
+    fn metadata_values(&self, key: &str) -> PyResult<Vec<f64>> {
+        (0..self.corpus_size)
+            .map(
+                |doc_id| match self.metadata.get(doc_id).and_then(|m| m.get(key)) {
+                    Some(value) => value.parse::<f64>().map_err(|_| {
+                        pyo3::exceptions::PyValueError::new_err(format!(
+                            "metadata value for key '{key}' is not a number: {value}"
+                        ))
+                    }),
+                    None => Ok(0.0),
+                },
+            )
+            .collect()
+    }
+
+    /// 长度归一化参数 b (开启 idf_length_coupling 时按归一化 idf 衰减，限制在 [0, b])
+    fn effective_b(&self, idf: f64) -> f64 {
+        let Some(coupling) = self.idf_length_coupling else {
+            return self.b;
+        };
+        // 最大 idf 对应只出现在一个文档中的词
+        let corpus_size = self
+            .global_stats
+            .as_ref()
+            .map_or(self.live_docs(), |stats| stats.corpus_size);
+        let max_idf = ((corpus_size as f64 - 0.5) / 1.5 + 1.0).ln();
+        let normalized = if max_idf > 0.0 {
+            (idf / max_idf).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.b * (1.0 - coupling * normalized).clamp(0.0, 1.0)
+    }
+
+    /// Block max_score 的存储值: f32_scores 模式下向上取整到 f32，保证仍是有效上界
+    fn block_max_score(&self, score: f64) -> f64 {
+        if !self.f32_scores {
+            return score;
+        }
+        let narrow = score as f32;
+        if (narrow as f64) < score {
+            narrow.next_up() as f64
+        } else {
+            narrow as f64
+        }
+    }
 
-        // 初始化 ID 映射
-        if let Some(external_ids) = ids {
-            self.doc_ids = external_ids;
+    /// 返回给调用方的分数: f32_scores 模式下降为 f32 精度
+    fn output_score(&self, score: f64) -> f64 {
+        if self.f32_scores {
+            score as f32 as f64
         } else {
-            self.doc_ids = (0..self.corpus_size as u64).collect();
+            score
         }
+    }
 
-        let mut temp_index: HashMap<String, Vec<(u32, u32, u32)>> = HashMap::new();
-        let mut total_length: u64 = 0;
+    /// 计算 idf (注入全局统计量时使用全局文档数与文档频率)
+    ///
+    /// Icf 方案使用本索引的集合频率，不受全局统计量影响
+    fn calc_idf(&self, term: &str, list: &InvertedList) -> f64 {
+        let boost = self.term_boosts.get(term).copied().unwrap_or(1.0);
+        self.base_idf(term, list) * boost
+    }
 
-        // 1. 分词并收集 Postings
-        for (doc_id, doc) in documents.iter().enumerate() {
-            let doc_id = doc_id as u32;
-            let tokens = self.tokenize(doc);
-            let doc_len = tokens.len() as u32;
+    /// 未乘词级权重的 idf
+    fn base_idf(&self, term: &str, list: &InvertedList) -> f64 {
+        if let Some(&idf) = self.idf_overrides.get(term) {
+            return idf;
+        }
+        if !self.use_idf {
+            return 1.0;
+        }
+        if self.weighting == Weighting::Icf {
+            let total_tokens = self.avgdl * self.live_docs() as f64;
+            return (total_tokens / list.collection_freq.max(1) as f64)
+                .ln()
+                .max(0.0);
+        }
+        let matched_docs = list.doc_count;
+        let (corpus_size, matched_docs) = match &self.global_stats {
+            Some(stats) => (
+                stats.corpus_size,
+                stats.doc_freqs.get(term).copied().unwrap_or(matched_docs),
+            ),
+            None => (self.live_docs(), matched_docs),
+        };
+        let numerator = corpus_size as f64 - matched_docs as f64 + 0.5;
+        let denominator = matched_docs as f64 + 0.5;
+        (numerator / denominator + 1.0).ln()
+    }
 
-            self.doc_lengths.push(doc_len);
-            total_length += doc_len as u64;
+    fn calc_bm25_score(&self, idf: f64, freq: f64, doc_len: u32) -> f64 {
+        let numerator = freq * (self.k1 + 1.0);
+        // BM25F: 长度归一化已在构建时按字段作用于合并词频
+        if !self.field_weights.is_empty() {
+            return idf * numerator / (freq + self.k1);
+        }
+        let avgdl = self
+            .global_stats
+            .as_ref()
+            .map_or(self.avgdl, |stats| stats.avgdl);
+        // 全部文档长度为 0 (如只含空白的语料) 时 avgdl 为 0，不做长度归一化 (等价于 b=0)，
+        // 避免 NaN/inf 分数破坏 Top-K 堆的排序
+        let length_norm = if avgdl > 0.0 {
+            let b = self.effective_b(idf);
+            1.0 - b + b * doc_len as f64 / avgdl
+        } else {
+            1.0
+        };
+        let denominator = freq + self.k1 * length_norm;
+        idf * numerator / denominator
+    }
 
-            let mut freq_map: HashMap<String, u32> = HashMap::new();
-            for token in tokens {
-                *freq_map.entry(token).or_insert(0) += 1;
-            }
+    /// 加载后的整理: 把旧格式升级到当前版本，重建分词器；
+    /// 保存了自定义词典的索引重建实例级 jieba，文件中的词典损坏时报错
+    fn after_load(&mut self) -> PyResult<()> {
+        if !self.user_dict.is_empty() {
+            let jieba = build_jieba(&self.user_dict).map_err(|e| {
+                pyo3::exceptions::PyValueError::new_err(format!(
+                    "invalid user_dict in saved index: {e}"
+                ))
+            })?;
+            self.custom_jieba = Some(Arc::new(jieba));
+        }
+        self.rebuild_tokenizers();
+        self.migrate();
+        self.rebuild_id_map();
+        Ok(())
+    }
 
-            for (term, freq) in freq_map {
-                temp_index
-                    .entry(term)
-                    .or_default()
-                    .push((doc_id, freq, doc_len));
+    /// 按版本逐步升级加载的旧格式索引 (调用前已确认版本不高于 FORMAT_VERSION)
+    ///
+    /// 新增不兼容的格式改动时，在这里追加从上一版本升级的步骤
+    fn migrate(&mut self) {
+        if self.version < 1 {
+            // 没有版本字段的旧索引: 部分文件在每个 Block 中冗余存储了文档长度，加载后释放；
+            // 更早的文件没有记录集合频率，由各 Block 的词频重新统计
+            for list in self.index.values_mut() {
+                for block in &mut list.blocks {
+                    block.doc_lens = Vec::new();
+                }
+                if list.collection_freq == 0 {
+                    list.collection_freq = list
+                        .blocks
+                        .iter()
+                        .flat_map(|block| &block.freqs)
+                        .map(|&f| f as u64)
+                        .sum();
+                }
             }
         }
+        // 版本 2: Block 的 doc_id 改为差值编码，旧的绝对 ID 在反序列化时 (StoredBlock) 已兼容
+        self.version = FORMAT_VERSION;
+    }
 
-        self.avgdl = if self.corpus_size > 0 {
-            total_length as f64 / self.corpus_size as f64
-        } else {
-            0.0
-        };
+    /// 标记 Block max_score 已过期，由下一次搜索 (或手动 rebuild) 重建
+    fn mark_max_scores_dirty(&mut self) {
+        *self.max_scores_dirty.get_mut() = true;
+    }
 
-        // 2. 构建 Block-Max 倒排索引
-        for (term, mut postings) in temp_index {
-            postings.sort_by_key(|k| k.0); // 按 doc_id 排序
+    /// 搜索前确保 max_score 上界有效，过期时重建一次
+    fn ensure_max_scores(&self) {
+        if self.max_scores_dirty.load(AtomicOrdering::Acquire) {
+            self.rebuild_block_max_scores();
+        }
+    }
 
-            let mut inverted_list = InvertedList {
-                doc_count: postings.len(),
-                blocks: Vec::new(),
-            };
+    /// 文档的分数乘数 (decay_documents 设置，默认 1.0)
+    fn doc_weight(&self, doc_id: u32) -> f64 {
+        self.doc_weights
+            .get(doc_id as usize)
+            .copied()
+            .unwrap_or(1.0)
+    }
 
-            for chunk in postings.chunks(BLOCK_SIZE) {
-                let mut block = Block {
-                    max_score: 0.0,
-                    last_doc_id: chunk.last().unwrap().0,
-                    doc_ids: Vec::with_capacity(chunk.len()),
-                    freqs: Vec::with_capacity(chunk.len()),
-                    doc_lens: Vec::with_capacity(chunk.len()),
+    /// 单个 Posting 的最终得分 (BM25 分数 × 文档乘数)
+    fn posting_score(&self, idf: f64, doc_id: u32, freq: f64) -> f64 {
+        let doc_len = self.doc_lengths[doc_id as usize];
+        self.calc_bm25_score(idf, freq, doc_len) * self.doc_weight(doc_id)
+    }
+
+    /// 游标当前 Posting 的得分
+    fn cursor_score(&self, cursor: &BlockCursor) -> f64 {
+        let (doc_id, freq) = cursor.curr_posting();
+        self.posting_score(cursor.idf, doc_id, freq) * cursor.weight
+    }
+
+    /// 按 lowercase 设置规范化按词配置的键 (与索引中的词一致)
+    fn normalize_term_keys(&self, values: HashMap<String, f64>) -> HashMap<String, f64> {
+        values
+            .into_iter()
+            .map(|(term, value)| {
+                let term = if self.lowercase {
+                    term.to_lowercase()
+                } else {
+                    term
                 };
+                (term, value)
+            })
+            .collect()
+    }
 
-                let idf = self.calc_idf(postings.len());
+    /// 参数相同的空索引
+    fn empty_copy(&self) -> BM25 {
+        let mut empty = BM25::new(
+            self.k1,
+            self.b,
+            self.lowercase,
+            self.store_positions,
+            None,
+            self.stopword_mode,
+            self.unk_df_threshold,
+            self.f32_scores,
+            self.empty_doc_policy,
+            self.empty_query_fallback,
+            self.binary_tf,
+            self.idf_length_coupling,
+            Some(self.segmenters.clone()),
+            self.use_idf,
+            self.weighting,
+            self.query_lowercase,
+            self.char_fallback,
+            self.cut_mode,
+            self.use_hmm,
+            self.block_size,
+        )
+        .expect("参数在创建索引时已校验");
+        empty.stopwords = self.stopwords.clone();
+        empty.idf_overrides = self.idf_overrides.clone();
+        empty.term_boosts = self.term_boosts.clone();
+        empty.user_dict = self.user_dict.clone();
+        empty.custom_jieba = self.custom_jieba.clone();
+        empty.rebuild_tokenizers();
+        empty
+    }
 
-                for &(doc_id, freq, doc_len) in chunk {
-                    block.doc_ids.push(doc_id);
-                    block.freqs.push(freq);
-                    block.doc_lens.push(doc_len);
+    /// 为只读检索整理内存: 释放预留容量，丢弃元数据与词位置，重建过期的 max_score
+    ///
+    /// BM25Reader 不提供短语与邻近查询，词位置一并丢弃；未使用加权词频时清空其数组
+    fn compact_for_reading(&mut self) {
+        self.ensure_max_scores();
+        self.metadata = Vec::new();
+        self.store_positions = false;
+        for list in self.index.values_mut() {
+            for block in &mut list.blocks {
+                block.positions = Vec::new();
+                if !self.weighted_tf {
+                    block.weighted_freqs = Vec::new();
+                }
+            }
+        }
+        self.doc_lengths.shrink_to_fit();
+        self.doc_ids.shrink_to_fit();
+        self.doc_weights.shrink_to_fit();
+        self.stopwords.shrink_to_fit();
+        self.unk_terms.shrink_to_fit();
+        self.index.shrink_to_fit();
+        for list in self.index.values_mut() {
+            list.shrink_to_fit();
+        }
+    }
 
-                    // 计算该文档的 BM25 分数，更新 Block Max Score
-                    let score = self.calc_bm25_score(idf, freq, doc_len);
-                    if score > block.max_score {
-                        block.max_score = score;
-                    }
+    /// 由倒排列表反查若干文档的词集合 (一次扫描整个词表)
+    fn term_sets(&self, doc_ids: &[u32]) -> HashMap<u32, HashSet<&str>> {
+        let mut sets: HashMap<u32, HashSet<&str>> =
+            doc_ids.iter().map(|&d| (d, HashSet::new())).collect();
+        if sets.is_empty() {
+            return sets;
+        }
+        for (term, list) in &self.index {
+            for &doc_id in doc_ids {
+                if list.locate(doc_id).is_some() {
+                    sets.get_mut(&doc_id).unwrap().insert(term.as_str());
                 }
-                inverted_list.blocks.push(block);
             }
+        }
+        sets
+    }
 
-            self.index.insert(term, inverted_list);
+    /// 文档中每个词的 BM25 分量 (未排序)，供 document_vector / document_top_terms 使用
+    fn term_weights(&self, external_id: u64) -> Option<Vec<(String, f64)>> {
+        let doc_id = self.internal_id(external_id)? as u32;
+        let mut vector: Vec<(String, f64)> = Vec::new();
+        for (term, list) in &self.index {
+            if self.is_zero_weight(term) {
+                continue;
+            }
+            if let Some((block_idx, i)) = list.locate(doc_id) {
+                let tf = list.blocks[block_idx].tf(i);
+                let weight = self.posting_score(self.calc_idf(term, list), doc_id, tf);
+                if weight > 0.0 {
+                    vector.push((term.clone(), self.output_score(weight)));
+                }
+            }
         }
-        Ok(())
+        Some(vector)
     }
+}
 
-    /// 搜索与查询最相关的文档 (Block-Max WAND)
-    /// 返回: List[(doc_id, score)]，其中 doc_id 是外部 ID (u64)
-    #[pyo3(signature = (query, top_k=None))]
-    pub fn search(&self, query: &str, top_k: Option<usize>) -> Vec<(u64, f64)> {
-        let k = top_k.unwrap_or(10); // 默认 Top 10
-        let query_tokens = self.tokenize(query);
-        let mut heap = BinaryHeap::new(); // 最小堆，保存 Top-K
+/// 多索引联合检索
+///
+/// 不做物理合并: 分别检索每个索引后按分数归并 Top-K。
+/// 默认在创建时计算全局统计量并注入各索引，使 idf 与 avgdl 跨索引可比
+#[pyclass]
+pub struct MultiBM25 {
+    shards: Vec<Py<BM25>>,
+}
 
-        // 收集所有相关词的 Block 迭代器
-        let mut cursors: Vec<BlockCursor> = Vec::new();
-        for token in query_tokens {
-            if let Some(inv_list) = self.index.get(&token) {
-                if !inv_list.blocks.is_empty() {
-                    let idf = self.calc_idf(inv_list.doc_count);
-                    cursors.push(BlockCursor::new(inv_list, idf));
-                }
+#[pymethods]
+impl MultiBM25 {
+    /// shards: 参与检索的 BM25 实例 (共享引用，注入全局统计会修改它们)
+    /// global_stats: 是否注入全局统计量；为 False 时各索引使用自身统计
+    #[new]
+    #[pyo3(signature = (shards, global_stats=true))]
+    pub fn new(py: Python<'_>, shards: Vec<Py<BM25>>, global_stats: bool) -> PyResult<Self> {
+        let multi = MultiBM25 { shards };
+        if global_stats {
+            multi.inject_global_stats(py)?;
+        }
+        Ok(multi)
+    }
+
+    /// 按全部索引重新计算并注入全局统计量 (索引内容变化后调用)
+    pub fn inject_global_stats(&self, py: Python<'_>) -> PyResult<()> {
+        let mut corpus_size = 0;
+        let mut total_length: u64 = 0;
+        let mut doc_freqs: HashMap<String, usize> = HashMap::new();
+        for shard in &self.shards {
+            let shard = shard.try_borrow(py)?;
+            corpus_size += shard.live_docs();
+            total_length += shard.doc_lengths.iter().map(|&l| l as u64).sum::<u64>();
+            for (term, list) in &shard.index {
+                *doc_freqs.entry(term.clone()).or_insert(0) += list.doc_count;
             }
         }
+        if corpus_size == 0 {
+            return Ok(());
+        }
+        let avgdl = total_length as f64 / corpus_size as f64;
+        for shard in &self.shards {
+            shard
+                .try_borrow_mut(py)?
+                .set_global_stats(corpus_size, avgdl, doc_freqs.clone())?;
+        }
+        Ok(())
+    }
 
-        if cursors.is_empty() {
-            return Vec::new();
+    /// 联合检索，返回归并后的 Top-K [(doc_id, score)]
+    /// 分数相同时按索引顺序、索引内部文档顺序排列 (与按顺序合并后的索引一致)
+    #[pyo3(signature = (query, top_k=None))]
+    pub fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        top_k: Option<usize>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        let options = SearchOptions::new(top_k.unwrap_or(10));
+        let mut results: Vec<(u64, f64)> = Vec::new();
+        for shard in &self.shards {
+            let shard = shard.try_borrow(py)?;
+            results.extend(
+                shard
+                    .search_internal(query, &options, |_| true)
+                    .into_iter()
+                    .map(|d| (shard.external_id(d.doc_id), shard.output_score(d.score))),
+            );
         }
+        // 稳定排序保留索引顺序
+        results.sort_by(|a, b| b.1.total_cmp(&a.1));
+        results.truncate(options.k);
+        Ok(results)
+    }
 
-        // 简化的 BMW/WAND 逻辑
-        let mut active_cursors: Vec<&mut BlockCursor> = cursors.iter_mut().collect();
+    /// 索引数量
+    pub fn __len__(&self) -> usize {
+        self.shards.len()
+    }
+}
 
-        loop {
-            // 1. 找出当前所有 cursor 中最小的 doc_id
-            let mut min_doc_id = u32::MAX;
-            let mut all_finished = true;
+/// 只读检索结构，由 BM25.into_reader 得到
+///
+/// 只支持基本检索 (search 的 top_k / require_all_terms 与 get_scores)，不能修改或重新 fit；
+/// 数组按实际长度分配、预先重建 max_score，适合构建完成后的在线服务
+#[pyclass(frozen)]
+pub struct BM25Reader {
+    index: BM25,
+}
 
-            for cursor in &active_cursors {
-                if let Some(doc_id) = cursor.curr_doc_id() {
-                    all_finished = false;
-                    if doc_id < min_doc_id {
-                        min_doc_id = doc_id;
-                    }
-                }
-            }
+#[pymethods]
+impl BM25Reader {
+    /// 搜索，与 BM25.search 的结果一致 (检索期间释放 GIL)
+    #[pyo3(signature = (query, top_k=None, require_all_terms=false))]
+    pub fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        top_k: Option<usize>,
+        require_all_terms: bool,
+    ) -> Vec<(u64, f64)> {
+        let options = SearchOptions {
+            require_all_terms,
+            ..SearchOptions::new(top_k.unwrap_or(10))
+        };
+        let index = &self.index;
+        py.detach(|| {
+            index
+                .search_internal(query, &options, |_| true)
+                .into_iter()
+                .map(|d| (index.external_id(d.doc_id), index.output_score(d.score)))
+                .collect()
+        })
+    }
 
-            if all_finished {
-                break;
-            }
+    /// 获取所有文档的 BM25 分数 (计算期间释放 GIL)
+    pub fn get_scores(&self, py: Python<'_>, query: &str) -> Vec<f64> {
+        py.detach(|| self.index.get_scores(query))
+    }
 
-            // 2. 剪枝检查
-            // TODO: WAND threshold check
+    /// 堆内存估算 (字节)
+    pub fn memory_usage(&self) -> usize {
+        self.index.memory_usage()
+    }
 
-            // 3. 计算 min_doc_id 的准确分数
-            let mut score = 0.0;
-            let mut advanced_any = false;
+    /// 文档数
+    pub fn __len__(&self) -> usize {
+        self.index.live_docs()
+    }
+}
 
-            for cursor in &mut active_cursors {
-                if let Some(doc_id) = cursor.curr_doc_id() {
-                    if doc_id == min_doc_id {
-                        score += cursor.curr_score(self.k1, self.b, self.avgdl);
-                        cursor.advance();
-                        advanced_any = true;
-                    }
-                }
-            }
+/// 按 compact 的重新编号保留未删除文档对应的元素 (remap 中 u32::MAX 表示已删除)
+fn retain_live<T>(values: &mut Vec<T>, remap: &[u32]) {
+    let mut doc_id = 0;
+    values.retain(|_| {
+        doc_id += 1;
+        remap[doc_id - 1] != u32::MAX
+    });
+}
 
-            if !advanced_any {
-                break;
-            }
+/// 文档中某个字段的文本 (缺失时为空)
+fn field_text<'a>(doc: &'a HashMap<String, String>, field: &str) -> &'a str {
+    doc.get(field).map_or("", |text| text.as_str())
+}
 
-            // 4. 更新堆
-            if heap.len() < k {
-                heap.push(ScoredDoc {
-                    score,
-                    doc_id: min_doc_id,
-                });
-            } else if let Some(min_node) = heap.peek() {
-                if score > min_node.score {
-                    heap.pop();
-                    heap.push(ScoredDoc {
-                        score,
-                        doc_id: min_doc_id,
-                    });
-                }
-            }
-        }
+/// 时长换算为微秒
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
 
-        // 结果排序 (分数降序)
-        let results: Vec<(u64, f64)> = heap
-            .into_sorted_vec()
-            .into_iter()
-            .map(|d| {
-                // 映射回外部 ID
-                let internal_id = d.doc_id as usize;
-                let external_id = if internal_id < self.doc_ids.len() {
-                    self.doc_ids[internal_id]
-                } else {
-                    internal_id as u64 // Fallback, shout not happen
-                };
-                (external_id, d.score)
-            })
-            .collect();
+/// 两个词集合的 Jaccard 相似度 (均为空时视为 1.0)
+fn jaccard(a: &HashSet<&str>, b: &HashSet<&str>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
 
-        results
+/// 词权重排序: 权重降序，相同时按词升序
+fn cmp_term_weight(a: &(String, f64), b: &(String, f64)) -> std::cmp::Ordering {
+    b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+/// 按权重降序取前 n 个词 (只对前 n 个排序)
+fn top_terms(mut terms: Vec<(String, f64)>, n: usize) -> Vec<(String, f64)> {
+    if n == 0 {
+        return Vec::new();
+    }
+    if n < terms.len() {
+        terms.select_nth_unstable_by(n - 1, cmp_term_weight);
+        terms.truncate(n);
     }
+    terms.sort_by(cmp_term_weight);
+    terms
+}
 
-    /// 获取所有文档的 BM25 分数
-    pub fn get_scores(&self, query: &str) -> Vec<f64> {
-        let mut scores = vec![0.0; self.corpus_size];
-        let query_tokens = self.tokenize(query);
+/// 游标合计进入的 Block 数
+fn blocks_scanned(cursors: &[BlockCursor]) -> usize {
+    cursors.iter().map(|c| c.blocks_scanned).sum()
+}
 
-        for token in query_tokens {
-            if let Some(inv_list) = self.index.get(&token) {
-                // 计算 idf (注意：inv_list.doc_count 存储包含词 t 的文档总数 n(t))
-                let idf = self.calc_idf(inv_list.doc_count);
+/// 将搜索结果编码为二进制帧: u32 结果数 | u64 ID 数组 | f64 分数数组 (小端)
+pub fn encode_frame(results: &[(u64, f64)]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + results.len() * 16);
+    frame.extend_from_slice(&(results.len() as u32).to_le_bytes());
+    for &(doc_id, _) in results {
+        frame.extend_from_slice(&doc_id.to_le_bytes());
+    }
+    for &(_, score) in results {
+        frame.extend_from_slice(&score.to_le_bytes());
+    }
+    frame
+}
+
+/// 解码二进制帧，长度不符时返回 None
+pub fn decode_frame(frame: &[u8]) -> Option<Vec<(u64, f64)>> {
+    let (header, body) = frame.split_first_chunk::<4>()?;
+    let n = u32::from_le_bytes(*header) as usize;
+    if body.len() != n.checked_mul(16)? {
+        return None;
+    }
+    let (ids, scores) = body.split_at(n * 8);
+    Some(
+        ids.chunks_exact(8)
+            .zip(scores.chunks_exact(8))
+            .map(|(id, score)| {
+                (
+                    u64::from_le_bytes(id.try_into().unwrap()),
+                    f64::from_le_bytes(score.try_into().unwrap()),
+                )
+            })
+            .collect(),
+    )
+}
 
-                for block in &inv_list.blocks {
-                    for i in 0..block.doc_ids.len() {
-                        let doc_id = block.doc_ids[i] as usize;
-                        let freq = block.freqs[i];
-                        let doc_len = block.doc_lens[i];
+/// BM25 序列化数组中 corpus_size、index 与 version 字段的位置 (新字段只追加在末尾)
+const CORPUS_SIZE_FIELD: usize = 3;
+const INDEX_FIELD: usize = 5;
+const VERSION_FIELD: usize = 37;
 
-                        scores[doc_id] += self.calc_bm25_score(idf, freq, doc_len);
-                    }
-                }
-            }
-        }
-        scores
+/// 读取 MessagePack 索引的格式版本，只跳过前面的字段而不解析类型
+/// (格式已不兼容时也能读出)。没有版本字段的旧索引为 0；数据无法识别时为 None
+fn peek_format_version(mut input: &[u8]) -> Option<u32> {
+    let field_count = rmp::decode::read_array_len(&mut input).ok()? as usize;
+    if field_count <= VERSION_FIELD {
+        return Some(0);
     }
+    for _ in 0..VERSION_FIELD {
+        next_raw(&mut input)?;
+    }
+    rmp_serde::from_slice(next_raw(&mut input)?).ok()
+}
 
-    /// 保存索引到文件 (MessagePack)
-    pub fn save(&self, path: &str) -> PyResult<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
-        rmp_serde::encode::write(&mut writer, self)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-        Ok(())
+/// 拒绝由更新版本的库写出的索引 (旧版本由 migrate 升级)
+fn check_format_version(version: Option<u32>) -> PyResult<()> {
+    match version {
+        Some(version) if version > FORMAT_VERSION => Err(pyo3::exceptions::PyValueError::new_err(
+            format!("index version {version} incompatible with library version {FORMAT_VERSION}"),
+        )),
+        _ => Ok(()),
     }
+}
 
-    /// 从文件加载索引 (MessagePack)
-    #[staticmethod]
-    pub fn load(path: &str) -> PyResult<Self> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let bm25: BM25 = rmp_serde::decode::from_read(reader)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-        Ok(bm25)
+/// 读取下一个 MessagePack 值的原始字节 (只检查结构，不解析类型)
+fn next_raw<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let start: &'a [u8] = input;
+    serde::de::IgnoredAny::deserialize(&mut rmp_serde::Deserializer::new(&mut *input)).ok()?;
+    Some(&start[..start.len() - input.len()])
+}
+
+/// 读取一个词条 (词, 倒排列表)，成功时返回词条的原始字节
+///
+/// 失败时返回可读出的词 (词本身也损坏时为 None)
+fn read_entry<'a>(input: &mut &'a [u8], corpus_size: usize) -> Result<&'a [u8], Option<String>> {
+    let start: &'a [u8] = input;
+    let key = next_raw(input).and_then(|raw| rmp_serde::from_slice::<String>(raw).ok());
+    let Some(key) = key else {
+        return Err(None);
+    };
+    let list = next_raw(input).and_then(|raw| rmp_serde::from_slice::<InvertedList>(raw).ok());
+    match list {
+        Some(list) if list.is_consistent(corpus_size) => Ok(&start[..start.len() - input.len()]),
+        _ => Err(Some(key)),
     }
 }
 
-impl BM25 {
-    fn tokenize(&self, text: &str) -> Vec<String> {
-        JIEBA
-            .cut(text, false)
-            .into_iter()
-            .map(|s| {
-                if self.lowercase {
-                    s.to_lowercase()
-                } else {
-                    s.to_string()
-                }
-            })
-            .filter(|s| !s.trim().is_empty())
-            .collect()
+/// 索引之后的字段能否从此处完整读到文件末尾 (且文档长度表与 corpus_size 相符)
+fn is_tail(mut input: &[u8], fields: usize, corpus_size: usize) -> bool {
+    let doc_lengths =
+        next_raw(&mut input).and_then(|raw| rmp_serde::from_slice::<Vec<u32>>(raw).ok());
+    if doc_lengths.is_none_or(|lengths| lengths.len() != corpus_size) {
+        return false;
     }
+    (1..fields).all(|_| next_raw(&mut input).is_some()) && input.is_empty()
+}
 
-    fn calc_idf(&self, matched_docs: usize) -> f64 {
-        let numerator = self.corpus_size as f64 - matched_docs as f64 + 0.5;
-        let denominator = matched_docs as f64 + 0.5;
-        (numerator / denominator + 1.0).ln()
+/// load_lenient 的恢复逻辑: 跳过损坏的词条，用完好的部分重新拼出一个合法的索引文件再解析
+fn salvage_index(bytes: &[u8]) -> Option<(BM25, LoadReport)> {
+    let mut input = bytes;
+    let field_count = rmp::decode::read_array_len(&mut input).ok()? as usize;
+    if field_count <= INDEX_FIELD + 1 {
+        return None;
+    }
+    let mut head: Vec<&[u8]> = Vec::with_capacity(INDEX_FIELD);
+    for _ in 0..INDEX_FIELD {
+        head.push(next_raw(&mut input)?);
     }
+    let corpus_size: usize = rmp_serde::from_slice(head[CORPUS_SIZE_FIELD]).ok()?;
+    let term_count = rmp::decode::read_map_len(&mut input).ok()? as usize;
+    let tail_fields = field_count - INDEX_FIELD - 1;
 
-    fn calc_bm25_score(&self, idf: f64, freq: u32, doc_len: u32) -> f64 {
-        let freq = freq as f64;
-        let numerator = freq * (self.k1 + 1.0);
-        let denominator = freq + self.k1 * (1.0 - self.b + self.b * doc_len as f64 / self.avgdl);
-        idf * numerator / denominator
+    let mut report = LoadReport::default();
+    let mut entries: Vec<&[u8]> = Vec::new();
+    let mut resynced = false;
+    // 重新同步后无法确定跳过了多少词条，需以索引之后的字段作为结束标志
+    while entries.len() + report.dropped_terms.len() + report.unreadable_terms < term_count {
+        if resynced && is_tail(input, tail_fields, corpus_size) {
+            break;
+        }
+        let mut probe = input;
+        match read_entry(&mut probe, corpus_size) {
+            Ok(raw) => {
+                entries.push(raw);
+                input = probe;
+                continue;
+            }
+            Err(Some(term)) => report.dropped_terms.push(term),
+            Err(None) => report.unreadable_terms += 1,
+        }
+        // 向后搜索下一个完好的词条或索引的结尾
+        let offset = (1..input.len()).find(|&offset| {
+            let mut probe = &input[offset..];
+            read_entry(&mut probe, corpus_size).is_ok()
+                || is_tail(&input[offset..], tail_fields, corpus_size)
+        })?;
+        input = &input[offset..];
+        resynced = true;
+    }
+    if !is_tail(input, tail_fields, corpus_size) {
+        return None;
+    }
+    report.recovered_terms = entries.len();
+    // 重新同步时可能整段跳过了若干词条，均计为无法读取
+    report.unreadable_terms = term_count - entries.len() - report.dropped_terms.len();
+
+    let mut rebuilt = Vec::with_capacity(bytes.len());
+    rmp::encode::write_array_len(&mut rebuilt, field_count as u32).ok()?;
+    head.iter().for_each(|raw| rebuilt.extend_from_slice(raw));
+    rmp::encode::write_map_len(&mut rebuilt, entries.len() as u32).ok()?;
+    entries
+        .iter()
+        .for_each(|raw| rebuilt.extend_from_slice(raw));
+    rebuilt.extend_from_slice(input);
+
+    let mut bm25: BM25 = rmp_serde::from_slice(&rebuilt).ok()?;
+    if bm25.doc_lengths.len() != bm25.corpus_size || bm25.doc_ids.len() != bm25.corpus_size {
+        return None;
     }
+    // 损坏可能波及 max_score，恢复后统一重建
+    bm25.mark_max_scores_dirty();
+    Some((bm25, report))
 }
 
 /// 辅助游标，用于遍历倒排索引
@@ -369,6 +4969,9 @@ struct BlockCursor<'a> {
     block_idx: usize,
     in_block_idx: usize,
     idf: f64,
+    weight: f64,           // 查询词权重，乘在该词的得分与上界上
+    blocks_scanned: usize, // 已进入的 Block 数 (用于扫描上限与诊断)
+    prefetch: bool,        // 进入新 Block 时预取其后一个 Block
 }
 
 impl<'a> BlockCursor<'a> {
@@ -378,6 +4981,19 @@ impl<'a> BlockCursor<'a> {
             block_idx: 0,
             in_block_idx: 0,
             idf,
+            weight: 1.0,
+            blocks_scanned: usize::from(!list.blocks.is_empty()),
+            prefetch: BLOCK_PREFETCH.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    /// 已进入新 Block 时预取下一个 Block
+    #[inline]
+    fn prefetch_next(&self) {
+        if self.prefetch {
+            if let Some(next) = self.list.blocks.get(self.block_idx + 1) {
+                prefetch_block(next);
+            }
         }
     }
 
@@ -392,14 +5008,35 @@ impl<'a> BlockCursor<'a> {
         Some(block.doc_ids[self.in_block_idx])
     }
 
-    fn curr_score(&self, k1: f64, b: f64, avgdl: f64) -> f64 {
+    /// 整个倒排列表的得分上界 (含查询词权重)
+    fn max_score(&self) -> f64 {
+        self.list.max_score() * self.weight
+    }
+
+    /// 当前 Block 的得分上界 (含查询词权重)
+    fn block_max_score(&self) -> f64 {
+        self.list.blocks[self.block_idx].max_score.get() * self.weight
+    }
+
+    /// 当前 Block 的最后一个 doc_id
+    fn block_last_doc_id(&self) -> u32 {
+        self.list.blocks[self.block_idx].last_doc_id
+    }
+
+    /// 当前文档中该词的位置列表 (未存储位置时为空)
+    fn curr_positions(&self) -> &'a [u32] {
         let block = &self.list.blocks[self.block_idx];
-        let freq = block.freqs[self.in_block_idx] as f64;
-        let doc_len = block.doc_lens[self.in_block_idx] as f64;
+        block
+            .positions
+            .get(self.in_block_idx)
+            .map_or(&[], |p| p.as_slice())
+    }
 
-        let numerator = freq * (k1 + 1.0);
-        let denominator = freq + k1 * (1.0 - b + b * doc_len / avgdl);
-        self.idf * numerator / denominator
+    /// 当前 Posting: (doc_id, 参与打分的词频)
+    fn curr_posting(&self) -> (u32, f64) {
+        let block = &self.list.blocks[self.block_idx];
+        let i = self.in_block_idx;
+        (block.doc_ids[i], block.tf(i))
     }
 
     fn advance(&mut self) {
@@ -413,16 +5050,198 @@ impl<'a> BlockCursor<'a> {
         if self.in_block_idx >= self.list.blocks[self.block_idx].doc_ids.len() {
             self.block_idx += 1;
             self.in_block_idx = 0;
+            if self.block_idx < self.list.blocks.len() {
+                self.blocks_scanned += 1;
+                self.prefetch_next();
+            }
+        }
+    }
+
+    /// 将游标移动到第一个 doc_id >= target 的位置
+    ///
+    /// 先借助 last_doc_id 整块跳过，再在块内做倍增 (galloping) + 二分查找
+    fn skip_to(&mut self, target: u32) {
+        let blocks = &self.list.blocks;
+        if self.block_idx >= blocks.len() {
+            return;
+        }
+
+        // 1. 跳过 last_doc_id < target 的整块
+        if blocks[self.block_idx].last_doc_id < target {
+            self.in_block_idx = 0;
+            self.block_idx += 1;
+            while self.block_idx < blocks.len() && blocks[self.block_idx].last_doc_id < target {
+                self.block_idx += 1;
+            }
+            if self.block_idx >= blocks.len() {
+                return;
+            }
+            self.blocks_scanned += 1;
+            self.prefetch_next();
+        }
+
+        // 2. 块内倍增查找上界，再二分定位
+        let doc_ids = &blocks[self.block_idx].doc_ids;
+        let start = self.in_block_idx;
+        if doc_ids[start] >= target {
+            return;
+        }
+        let mut step = 1;
+        let mut lo = start;
+        while lo + step < doc_ids.len() && doc_ids[lo + step] < target {
+            lo += step;
+            step *= 2;
+        }
+        let hi = (lo + step + 1).min(doc_ids.len());
+        // 由 last_doc_id >= target 可知 hi 范围内必定存在满足条件的位置
+        self.in_block_idx = lo + doc_ids[lo..hi].partition_point(|&id| id < target);
+    }
+}
+
+/// 多个倒排列表的求交迭代器 (用于合取查询)
+///
+/// 以最短的列表驱动，其余游标通过 skip_to 跳跃对齐
+struct Intersection<'a> {
+    cursors: Vec<BlockCursor<'a>>, // 保持查询顺序
+    order: Vec<usize>,             // 按列表长度升序的游标下标
+}
+
+impl<'a> Intersection<'a> {
+    fn new(cursors: Vec<BlockCursor<'a>>) -> Self {
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by_key(|&i| cursors[i].list.doc_count);
+        Intersection { cursors, order }
+    }
 
-            // TODO: 这里可以加入 Block 级剪枝逻辑
-            // if self.list.blocks[self.block_idx].max_score < threshold { skip }
+    /// 所有游标 (查询顺序)
+    fn cursors(&self) -> &[BlockCursor<'a>] {
+        &self.cursors
+    }
+
+    /// 定位到下一个所有列表共有的文档，所有游标停在该文档上
+    fn next_doc(&mut self) -> Option<u32> {
+        let (&lead, rest) = self.order.split_first()?;
+        let mut candidate = self.cursors[lead].curr_doc_id()?;
+        'outer: loop {
+            for &i in rest {
+                let cursor = &mut self.cursors[i];
+                cursor.skip_to(candidate);
+                let doc_id = cursor.curr_doc_id()?;
+                if doc_id > candidate {
+                    // 候选被跳过，以更大的 doc_id 重新对齐主游标
+                    let lead = &mut self.cursors[lead];
+                    lead.skip_to(doc_id);
+                    candidate = lead.curr_doc_id()?;
+                    continue 'outer;
+                }
+            }
+            return Some(candidate);
+        }
+    }
+
+    /// 越过当前共有文档
+    fn advance(&mut self) {
+        if let Some(&lead) = self.order.first() {
+            self.cursors[lead].advance();
         }
     }
 }
 
+/// 在默认词典基础上追加自定义词典 (jieba 词典格式) 构建分词器
+fn build_jieba(user_dict: &str) -> PyResult<Jieba> {
+    let mut jieba = Jieba::new();
+    jieba
+        .load_dict(&mut user_dict.as_bytes())
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    Ok(jieba)
+}
+
+/// 为全局 Jieba 加载自定义词典 (在默认词典基础上追加)
+///
+/// 必须在任何 BM25 实例首次分词 (fit / search 等) 之前调用，
+/// 全局实例一旦初始化便不可再修改，之后调用会报错
+#[pyfunction]
+fn configure_global_dict(path: &str) -> PyResult<()> {
+    if JIEBA.get().is_some() {
+        return Err(pyo3::exceptions::PyRuntimeError::new_err(
+            "global jieba is already initialized; call configure_global_dict before any tokenization",
+        ));
+    }
+    let jieba = build_jieba(&std::fs::read_to_string(path)?)?;
+    JIEBA.set(jieba).map_err(|_| {
+        pyo3::exceptions::PyRuntimeError::new_err(
+            "global jieba is already initialized; call configure_global_dict before any tokenization",
+        )
+    })
+}
+
+/// 设置并行方法 (如 search_batch、fit、get_scores) 使用的线程数
+///
+/// 使用独立的线程池而非 rayon 全局线程池，避免在共享机器上占满所有核心；
+/// None 表示恢复为 rayon 全局线程池 (默认为 CPU 核数)
+#[pyfunction]
+#[pyo3(signature = (num_threads=None))]
+fn set_num_threads(num_threads: Option<usize>) -> PyResult<()> {
+    let pool = match num_threads {
+        Some(0) => {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "num_threads must be positive",
+            ))
+        }
+        Some(n) => {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build()
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+            Some(Arc::new(pool))
+        }
+        None => None,
+    };
+    *THREAD_POOL.write().unwrap_or_else(|e| e.into_inner()) = pool;
+    Ok(())
+}
+
+/// 并行方法当前使用的线程数
+#[pyfunction]
+fn get_num_threads() -> usize {
+    run_parallel(rayon::current_num_threads)
+}
+
+/// 设置倒排列表遍历时是否预取下一个 Block (默认关闭)
+///
+/// 对很长的倒排列表可以减少块边界处的缓存缺失，不改变搜索结果；作用于之后开始的查询
+#[pyfunction]
+fn set_block_prefetch(enabled: bool) {
+    BLOCK_PREFETCH.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// 当前是否开启 Block 预取
+#[pyfunction]
+fn get_block_prefetch() -> bool {
+    BLOCK_PREFETCH.load(AtomicOrdering::Relaxed)
+}
+
 /// Python 模块定义
 #[pymodule]
 fn bm25_jieba(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(configure_global_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(get_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(set_block_prefetch, m)?)?;
+    m.add_function(wrap_pyfunction!(get_block_prefetch, m)?)?;
     m.add_class::<BM25>()?;
+    m.add_class::<MultiBM25>()?;
+    m.add_class::<BM25Reader>()?;
+    m.add_class::<StopwordMode>()?;
+    m.add_class::<SortOrder>()?;
+    m.add_class::<SecondaryMode>()?;
+    m.add_class::<EmptyDocPolicy>()?;
+    m.add_class::<SearchStats>()?;
+    m.add_class::<SearchTiming>()?;
+    m.add_class::<LoadReport>()?;
+    m.add_class::<FallbackMode>()?;
+    m.add_class::<Segmenter>()?;
+    m.add_class::<Weighting>()?;
+    m.add_class::<CutMode>()?;
     Ok(())
 }
@@ -4,10 +4,14 @@ BM25 性能测试
 测试不同规模数据集下的索引和搜索性能
 """
 
+import multiprocessing
+import os
+import resource
 import time
 import random
+import tempfile
 import string
-from bm25_jieba import BM25
+from bm25_jieba import BM25, set_block_prefetch
 
 
 def generate_chinese_text(length: int = 50) -> str:
@@ -33,6 +37,24 @@ def benchmark_fit(doc_count: int, doc_length: int = 100) -> float:
     return elapsed
 
 
+def _fit_peak_memory(doc_count: int, doc_length: int) -> tuple[float, float]:
+    """在独立进程中索引长文档，返回 (耗时, fit 期间峰值内存增长 MB)"""
+    documents = [generate_chinese_text(doc_length) for _ in range(doc_count)]
+    bm25 = BM25()
+    before = resource.getrusage(resource.RUSAGE_SELF).ru_maxrss
+    start = time.perf_counter()
+    bm25.fit(documents)
+    elapsed = time.perf_counter() - start
+    after = resource.getrusage(resource.RUSAGE_SELF).ru_maxrss
+    return elapsed, (after - before) / 1024  # Linux 下 ru_maxrss 单位为 KB
+
+
+def benchmark_fit_peak_memory(doc_count: int, doc_length: int) -> tuple[float, float]:
+    """测试长文档索引的峰值内存 (分词结果逐个计入词频表，不收集中间词列表)"""
+    with multiprocessing.get_context("spawn").Pool(1) as pool:
+        return pool.apply(_fit_peak_memory, (doc_count, doc_length))
+
+
 def benchmark_search(bm25: BM25, query: str, iterations: int = 100) -> float:
     """测试搜索性能"""
     start = time.perf_counter()
@@ -58,6 +80,14 @@ def run_benchmarks():
         elapsed = benchmark_fit(count)
         rate = count / elapsed
         print(f"  {count:>6} 文档: {elapsed:>6.3f}s ({rate:>8.0f} docs/s)")
+
+    # 长文档索引内存测试
+    print("\n📄 长文档索引测试 (fit)")
+    print("-" * 40)
+
+    for doc_count, doc_length in [(100, 50000), (20, 500000)]:
+        elapsed, peak_mb = benchmark_fit_peak_memory(doc_count, doc_length)
+        print(f"  {doc_count:>4} 文档 x {doc_length:>6} 字: {elapsed:>6.3f}s, 峰值内存增长 {peak_mb:.1f}MB")
     
     # 搜索性能测试
     print("\n🔍 搜索性能测试 (search)")
@@ -79,6 +109,50 @@ def run_benchmarks():
         qps = 1 / avg_time
         print(f"  查询「{query[:10]}...」: {avg_time*1000:.3f}ms ({qps:.0f} QPS)")
     
+    # 重复查询测试 (分词结果借用原文本，热点查询路径无需为每个词分配 String)
+    print("\n🔁 重复查询测试 (search x 10000)")
+    print("-" * 40)
+
+    for lowercase in [False, True]:
+        bm25 = BM25(lowercase=lowercase)
+        bm25.fit(documents)
+        for query in ["机器学习 数据", "Python Rust 编程"]:
+            avg_time = benchmark_search(bm25, query, iterations=10000)
+            print(f"  lowercase={lowercase!s:<5} 查询「{query}」: {avg_time*1000:.4f}ms ({1 / avg_time:.0f} QPS)")
+
+    # 长倒排列表扫描测试 (少量高频词，每个词的倒排列表跨越大量 Block)
+    print("\n📚 长倒排列表扫描测试 (Block 预取)")
+    print("-" * 40)
+
+    words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "榴莲", "芒果", "菠萝"]
+    documents = [" ".join(random.sample(words, 4)) for _ in range(200000)]
+    bm25 = BM25()
+    bm25.fit(documents)
+    query = "苹果 香蕉 葡萄 西瓜"
+    for prefetch in [False, True]:
+        set_block_prefetch(prefetch)
+        avg_time = benchmark_search(bm25, query, iterations=200)
+        print(f"  prefetch={prefetch!s:<5} 查询「{query}」: {avg_time*1000:.3f}ms ({1 / avg_time:.0f} QPS)")
+    set_block_prefetch(False)
+
+    # 多词查询剪枝测试 (WAND 跳过上界不足的文档，docs_scored 为完整打分的文档数)
+    print("\n✂️ 多词查询剪枝测试 (100000 文档)")
+    print("-" * 40)
+
+    documents = [
+        " ".join(random.choices(words, weights=[40, 20, 10, 5, 3, 2, 1, 1], k=8))
+        for _ in range(100000)
+    ]
+    bm25 = BM25()
+    bm25.fit(documents)
+    for query in ["苹果 香蕉 榴莲", "葡萄 芒果 菠萝"]:
+        avg_time = benchmark_search(bm25, query, iterations=100)
+        _, stats = bm25.search_with_stats(query, top_k=10)
+        print(
+            f"  查询「{query}」: {avg_time*1000:.3f}ms, "
+            f"完整打分 {stats.docs_scored} 文档, 扫描 {stats.blocks_scanned} 个 Block"
+        )
+
     # 内存效率测试（近似）
     print("\n💾 语料库规模测试")
     print("-" * 40)
@@ -93,8 +167,17 @@ def run_benchmarks():
         fit_elapsed = time.perf_counter() - fit_time
         
         search_time = benchmark_search(bm25, "测试查询", iterations=100)
+
+        # 序列化后的索引体积 (文档长度全局只存一份，不随 posting 数增长)
+        with tempfile.TemporaryDirectory() as tmp:
+            path = os.path.join(tmp, "bm25.bin")
+            bm25.save(path)
+            index_kb = os.path.getsize(path) / 1024
         
-        print(f"  {size:>6} 文档: 索引 {fit_elapsed:.3f}s, 搜索 {search_time*1000:.3f}ms")
+        print(
+            f"  {size:>6} 文档: 索引 {fit_elapsed:.3f}s, 搜索 {search_time*1000:.3f}ms, "
+            f"索引文件 {index_kb:.0f}KB"
+        )
     
     print("\n" + "=" * 60)
     print("✅ 性能测试完成")
@@ -2,8 +2,24 @@
 BM25 中文搜索测试
 """
 
+import json
+import math
+
 import pytest
-from bm25_jieba import BM25
+from bm25_jieba import (
+    BM25,
+    BM25Reader,
+    CutMode,
+    EmptyDocPolicy,
+    FallbackMode,
+    MultiBM25,
+    Segmenter,
+    SortOrder,
+    StopwordMode,
+    Weighting,
+    get_block_prefetch,
+    set_block_prefetch,
+)
 
 
 class TestBM25:
@@ -97,6 +113,58 @@ class TestBM25:
         results = bm25.search("Python")
         assert len(results) == 0
 
+    def test_degenerate_corpus_scores_finite(self):
+        """只含标点或空白的语料: 分数均为有限值 (avgdl 为 0 时不做长度归一化)"""
+        bm25 = BM25()
+        bm25.fit(["！！！", "。，、", "……", "？"])
+        results = bm25.search("！ ？ 。", top_k=10)
+        assert results
+        assert all(math.isfinite(score) for _, score in results)
+        assert all(math.isfinite(score) for score in bm25.get_scores("！ ？"))
+
+        blank = BM25()
+        blank.fit(["", "   ", "\t"])
+        assert blank.avgdl == 0.0
+        assert blank.search("！") == []
+        assert blank.get_scores("！") == [0.0, 0.0, 0.0]
+
+    def test_whitespace_query(self, bm25: BM25):
+        """只含空白的查询返回空结果，诊断信息为零"""
+        assert bm25.search("  \t\n ") == []
+        results, stats = bm25.search_with_stats("   ")
+        assert results == []
+        assert (stats.blocks_scanned, stats.docs_scored) == (0, 0)
+
+    def test_zero_top_k(self, bm25: BM25):
+        """top_k=0 时直接返回空结果，不扫描倒排列表"""
+        assert bm25.search("Python", top_k=0) == []
+        assert bm25.search("Python 编程", top_k=0, require_all_terms=True) == []
+        results, stats = bm25.search_with_stats("Python", top_k=0)
+        assert results == []
+        assert stats.blocks_scanned == 0
+        assert bm25.search_batch(["Python", "Java"], top_k=0) == [[], []]
+
+    def test_huge_top_k(self, bm25: BM25):
+        """top_k 远大于文档数时各检索入口返回全部命中文档，不按 top_k 预分配内存"""
+        huge = 10**12
+        expected = bm25.search("Python", top_k=len(bm25.get_scores("Python")))
+        assert bm25.search_tokens(["Python"], top_k=huge) == expected
+        assert bm25.search_batch(["Python"], top_k=huge) == [expected]
+        assert bm25.search_with_filter("Python", lambda meta: True, top_k=huge) == expected
+        assert bm25.search_boosted([("Python", 1.0)], top_k=huge) == expected
+        assert bm25.search_boolean("Python", top_k=huge) == expected
+        assert bm25.search_with_stats("Python", top_k=huge)[0] == expected
+        assert MultiBM25([bm25], global_stats=False).search("Python", top_k=huge) == expected
+        assert bm25.into_reader().search("Python", top_k=huge) == expected
+
+    def test_unfitted_model(self):
+        """未 fit 的新实例: 搜索返回空结果，分数列表为空"""
+        bm25 = BM25()
+        assert bm25.search("Python") == []
+        assert bm25.search_with_stats("Python")[0] == []
+        assert bm25.get_scores("Python") == []
+        assert bm25.get_nonzero_scores("Python") == []
+
     def test_single_document(self):
         """测试单文档语料库"""
         bm25 = BM25()
@@ -154,6 +222,37 @@ class TestBM25Scoring:
         assert scores[1] > scores[0]
 
 
+
+class TestBM25RepeatedQueryTerms:
+    """重复查询词测试: 重复的词只计分一次"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(store_positions=True)
+        model.fit(["北京 天气 晴朗", "北京 北京 欢迎 你", "上海 天气 多云", "北京 烤鸭"])
+        return model
+
+    def test_repeated_term_not_doubled(self, bm25: BM25):
+        """search、get_scores 与 explain 中重复的词与只出现一次时分数相同"""
+        assert bm25.search("北京 北京 天气") == bm25.search("北京 天气")
+        assert bm25.search("北京 天气 北京", require_all_terms=True) == bm25.search(
+            "北京 天气", require_all_terms=True
+        )
+        assert bm25.get_scores("北京 北京 天气") == bm25.get_scores("北京 天气")
+        assert bm25.explain("北京 北京", 0) == bm25.explain("北京", 0)
+        assert bm25.term_impact("北京 北京 天气") == bm25.term_impact("北京 天气")
+        assert bm25.search_boolean("+北京 北京 天气") == bm25.search_boolean("+北京 天气")
+
+    def test_repeated_term_in_phrase(self, bm25: BM25):
+        """短语匹配仍按完整查询 (含重复词) 检查位置，分数只计一次"""
+        single = dict(bm25.search("北京", top_k=10))
+        boosted = dict(bm25.search("北京 北京", top_k=10, phrase_boost=1.0))
+        assert boosted[1] == pytest.approx(single[1] + 1.0)
+        assert boosted[0] == pytest.approx(single[0])
+
+        phrase = bm25.search_phrase("北京 北京")
+        assert phrase == [(1, pytest.approx(single[1]))]
+
 class TestBM25CaseInsensitive:
     """BM25 大小写不敏感测试"""
 
@@ -200,3 +299,2785 @@ class TestBM25CaseInsensitive:
         results = bm25.search("机器学习ai")
         assert len(results) > 0
         assert results[0][0] == 1
+
+    def test_mixed_case_query_identical_results(self):
+        """大小写变体与重复查询的结果完全一致"""
+        bm25 = BM25(lowercase=True)
+        docs = ["Python 深度学习", "python 数据分析", "Rust 系统编程", "PYTHON Rust 混合"]
+        bm25.fit(docs)
+
+        expected = bm25.search("python rust")
+        assert len(expected) == 4
+        for query in ["Python Rust", "PYTHON RUST", "python rust"]:
+            for _ in range(3):
+                assert bm25.search(query) == expected
+
+        scores = bm25.get_scores("PyThOn RuSt")
+        assert scores == bm25.get_scores("python rust")
+        for doc_id, score in expected:
+            assert score == scores[doc_id]
+
+
+class TestBM25RequireAllTerms:
+    """合取查询 (require_all_terms) 测试"""
+
+    @pytest.fixture
+    def corpus(self) -> list[str]:
+        """跨越多个 Block 的语料，各词出现的文档集合不同"""
+        docs = []
+        for i in range(1000):
+            words = ["文档"]
+            if i % 3 == 0:
+                words.append("apple")
+            if i % 5 == 0:
+                words.append("banana")
+            if i % 7 == 0:
+                words.append("cherry")
+            docs.append(" ".join(words))
+        return docs
+
+    def test_intersection_matches_naive(self, corpus: list[str]):
+        """求交结果应与朴素集合求交一致"""
+        bm25 = BM25()
+        bm25.fit(corpus)
+        terms = ["apple", "banana", "cherry"]
+
+        naive = None
+        for term in terms:
+            matched = {i for i, s in enumerate(bm25.get_scores(term)) if s > 0}
+            naive = matched if naive is None else naive & matched
+
+        results = bm25.search(" ".join(terms), top_k=len(corpus), require_all_terms=True)
+        assert {doc_id for doc_id, _ in results} == naive
+
+        # 分数与全量打分一致
+        scores = bm25.get_scores(" ".join(terms))
+        for doc_id, score in results:
+            assert score == pytest.approx(scores[doc_id])
+
+    def test_missing_term_returns_empty(self, corpus: list[str]):
+        """任一查询词不在索引中时返回空"""
+        bm25 = BM25()
+        bm25.fit(corpus)
+        assert bm25.search("apple durian", top_k=10, require_all_terms=True) == []
+        assert len(bm25.search("apple durian", top_k=10)) > 0
+
+
+class TestBM25ResultFrame:
+    """二进制结果帧测试"""
+
+    def test_frame_round_trip(self):
+        """二进制帧解码后与 search 结果一致"""
+        bm25 = BM25()
+        bm25.fit(["Python 编程", "Python 数据 Python", "Java 编程"], ids=[7, 2**40, 9])
+        frame = bm25.search_frame("Python 编程")
+        assert isinstance(frame, bytes)
+        assert len(frame) == 4 + 3 * 16
+        assert BM25.decode_frame(frame) == bm25.search("Python 编程")
+
+    def test_empty_frame(self):
+        """无结果时为仅含计数的空帧"""
+        bm25 = BM25()
+        bm25.fit(["Python"])
+        frame = bm25.search_frame("Java")
+        assert frame == b"\x00\x00\x00\x00"
+        assert BM25.decode_frame(frame) == []
+
+    def test_truncated_frame(self):
+        """截断的帧解码报错"""
+        bm25 = BM25()
+        bm25.fit(["Python"])
+        frame = bm25.search_frame("Python")
+        with pytest.raises(ValueError, match="invalid or truncated"):
+            BM25.decode_frame(frame[:-1])
+
+
+class TestBM25TopDocsPerTerm:
+    """按词分别查询 Top-K 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        docs = []
+        for i in range(600):
+            words = ["填充"] * (i % 11 + 1)
+            if i % 2 == 0:
+                words += ["python"] * (i % 4 + 1)
+            if i % 3 == 0:
+                words.append("rust")
+            docs.append(" ".join(words))
+        model = BM25()
+        model.fit(docs)
+        return model
+
+    def test_matches_single_term_search(self, bm25: BM25):
+        """每个词的结果与单独 search 一致"""
+        per_term = bm25.top_docs_per_term(["python", "rust", "golang"], top_k=5)
+        assert set(per_term) == {"python", "rust", "golang"}
+        assert per_term["python"] == bm25.search("python", top_k=5)
+        assert per_term["rust"] == bm25.search("rust", top_k=5)
+        assert per_term["golang"] == []
+
+    def test_fast_path_matches_full_scoring(self, bm25: BM25):
+        """单词快速路径的 Top-K 与全量打分排序一致"""
+        results = bm25.search("python", top_k=20)
+        scores = bm25.get_scores("python")
+        expected = sorted(
+            ((i, s) for i, s in enumerate(scores) if s > 0), key=lambda x: (-x[1], x[0])
+        )[:20]
+        assert [s for _, s in results] == [s for _, s in expected]
+
+
+class TestBM25UnkBucket:
+    """低频单字合并 (<UNK>) 测试"""
+
+    @pytest.fixture
+    def docs(self) -> list[str]:
+        return ["甲 编程", "乙 编程", "丙 数据", "编程 数据 编程"]
+
+    def test_rare_chars_collapse(self, docs: list[str]):
+        """低频单字合并后共享同一个倒排列表"""
+        bm25 = BM25(unk_df_threshold=2)
+        bm25.fit(docs)
+        # 查询任一低频单字都会通过 <UNK> 命中所有含低频单字的文档
+        ids = {doc_id for doc_id, _ in bm25.search("甲")}
+        assert ids == {0, 1, 2}
+        assert bm25.search("甲") == bm25.search("乙")
+        assert bm25.top_docs_per_term(["丙"])["丙"] == bm25.search("丙")
+
+    def test_frequent_terms_untouched(self, docs: list[str]):
+        """高频词与多字词不受影响"""
+        bm25 = BM25(unk_df_threshold=2)
+        bm25.fit(docs)
+        plain = BM25()
+        plain.fit(docs)
+        assert bm25.search("编程") == plain.search("编程")
+        assert bm25.search("数据") == plain.search("数据")
+
+    def test_disabled_by_default(self, docs: list[str]):
+        """默认不合并"""
+        bm25 = BM25()
+        bm25.fit(docs)
+        assert [doc_id for doc_id, _ in bm25.search("甲")] == [0]
+
+    def test_unk_save_load(self, docs: list[str], tmp_path):
+        """合并状态随索引持久化，查询映射保持一致"""
+        bm25 = BM25(unk_df_threshold=2)
+        bm25.fit(docs)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("乙") == bm25.search("乙")
+
+
+class TestBM25SortOrder:
+    """结果排序方式测试"""
+
+    def test_sort_by_id(self):
+        """按 ID 排序时返回与相关性模式相同的文档"""
+        from bm25_jieba import SortOrder
+
+        bm25 = BM25()
+        docs = ["Python", "Python Python 教程", "Java", "Python 编程 入门 指南", "Python Python Python"]
+        bm25.fit(docs, ids=[50, 10, 40, 30, 20])
+
+        relevance = bm25.search("Python", top_k=3)
+        asc = bm25.search("Python", top_k=3, sort_by=SortOrder.AscId)
+        desc = bm25.search("Python", top_k=3, sort_by=SortOrder.DescId)
+
+        assert sorted(relevance) == sorted(asc) == sorted(desc)
+        assert [d for d, _ in asc] == sorted(d for d, _ in relevance)
+        assert [d for d, _ in desc] == sorted((d for d, _ in relevance), reverse=True)
+        assert bm25.search("Python", top_k=3, sort_by=SortOrder.Relevance) == relevance
+
+
+class TestBM25ScoreDistribution:
+    """分数分布统计测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["Python 编程", "Python Python 数据", "Java 编程", "Rust", "Python 编程 语言"],
+            ids=[10, 20, 30, 40, 50],
+        )
+        return model
+
+    def test_nonzero_scores(self, bm25: BM25):
+        """稀疏分数与全量分数一致"""
+        scores = bm25.get_scores("Python 编程")
+        nonzero = bm25.get_nonzero_scores("Python 编程")
+        ids = [10, 20, 30, 40, 50]
+        assert nonzero == [(ids[i], s) for i, s in enumerate(scores) if s > 0]
+
+    def test_distribution_matches_nonzero_scores(self, bm25: BM25):
+        """统计值与 get_nonzero_scores 计算结果一致"""
+        values = [s for _, s in bm25.get_nonzero_scores("Python 编程")]
+        mean = sum(values) / len(values)
+        std = (sum((v - mean) ** 2 for v in values) / len(values)) ** 0.5
+
+        got_mean, got_std, count = bm25.score_distribution("Python 编程")
+        assert count == len(values) == 4
+        assert got_mean == pytest.approx(mean)
+        assert got_std == pytest.approx(std)
+
+    def test_distribution_no_match(self, bm25: BM25):
+        """无命中时返回零值"""
+        assert bm25.score_distribution("区块链") == (0.0, 0.0, 0)
+
+
+class TestBM25DocumentDecay:
+    """文档老化测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(["Python 新闻", "Python 新闻", "Python 新闻"], ids=[100, 200, 300])
+        return model
+
+    def test_decay_older_documents(self, bm25: BM25):
+        """旧文档分数降低，新文档不受影响"""
+        before = dict(bm25.search("Python"))
+        bm25.decay_documents(0.5, 250)
+        after = dict(bm25.search("Python"))
+
+        assert after[100] == pytest.approx(before[100] * 0.5)
+        assert after[200] == pytest.approx(before[200] * 0.5)
+        assert after[300] == before[300]
+        assert bm25.search("Python")[0][0] == 300
+
+    def test_decay_compounds(self, bm25: BM25):
+        """多次老化累乘"""
+        before = dict(bm25.search("Python"))
+        bm25.decay_documents(0.5, 150)
+        bm25.decay_documents(0.5, 250)
+        after = dict(bm25.search("Python"))
+        assert after[100] == pytest.approx(before[100] * 0.25)
+        assert after[200] == pytest.approx(before[200] * 0.5)
+
+        scores = bm25.get_scores("Python")
+        assert scores[0] == pytest.approx(after[100])
+
+    def test_decay_invalid_factor(self, bm25: BM25):
+        """非法乘数报错"""
+        with pytest.raises(ValueError, match="factor"):
+            bm25.decay_documents(-1.0, 250)
+
+    def test_decay_save_load(self, bm25: BM25, tmp_path):
+        """文档乘数随索引持久化"""
+        bm25.decay_documents(0.3, 250)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("Python") == bm25.search("Python")
+
+
+class TestBM25StaleMaxScores:
+    """过期 Block max_score 自动重建测试"""
+
+    def test_search_after_mutation_without_rebuild(self):
+        """修改打分后不手动重建，搜索结果仍然正确"""
+        n = 600
+        docs = [" ".join(["Python"] + ["填充"] * (i % 13)) for i in range(n)]
+        ids = [10_000 - i for i in range(n)]  # 越靠后的内部文档外部 ID 越小
+        bm25 = BM25()
+        bm25.fit(docs, ids=ids)
+        bm25.search("Python")  # 先完成一次搜索
+
+        # 放大靠后 Block 中文档的分数，原有 max_score 会低于真实上界
+        bm25.decay_documents(10.0, 10_000 - 400)
+
+        scores = bm25.get_scores("Python")
+        expected = sorted(zip(ids, scores), key=lambda x: -x[1])[:10]
+        results = bm25.search("Python", top_k=10)
+        assert [s for _, s in results] == pytest.approx([s for _, s in expected])
+        assert all(doc_id <= 10_000 - 400 for doc_id, _ in results)
+
+    def test_manual_rebuild(self):
+        """手动重建后结果一致"""
+        bm25 = BM25()
+        bm25.fit(["Python 入门", "Python 进阶 教程"], ids=[1, 2])
+        bm25.decay_documents(3.0, 2)
+        bm25.rebuild_block_max_scores()
+        assert bm25.search("Python")[0][0] == 1
+
+
+class TestBM25MaxQueryTerms:
+    """长查询截断测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        docs = []
+        for i in range(40):
+            words = ["苹果"]  # 所有文档都有，idf 最低
+            if i % 2 == 0:
+                words.append("香蕉")
+            if i % 4 == 0:
+                words.append("葡萄")
+            if i % 8 == 0:
+                words.append("西瓜")
+            if i % 16 == 0:
+                words.append("草莓")
+            docs.append(" ".join(words))
+        model = BM25()
+        model.fit(docs)
+        return model
+
+    def test_keeps_highest_idf_terms(self, bm25: BM25):
+        """只保留 idf 最高的三个词参与检索"""
+        query = "苹果 香蕉 葡萄 西瓜 草莓"
+        truncated = bm25.search(query, top_k=40, max_query_terms=3)
+        assert truncated == bm25.search("葡萄 西瓜 草莓", top_k=40)
+        assert truncated != bm25.search(query, top_k=40)
+
+    def test_none_uses_all_terms(self, bm25: BM25):
+        """不设置或上限足够时使用全部查询词"""
+        query = "苹果 香蕉 葡萄"
+        assert bm25.search(query, max_query_terms=None) == bm25.search(query)
+        assert bm25.search(query, max_query_terms=10) == bm25.search(query)
+
+
+class TestBM25F32Scores:
+    """f32 分数精度测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        subjects = ["机器学习", "深度学习", "自然语言处理", "计算机视觉", "数据分析"]
+        return [
+            f"{subjects[i % 5]}是人工智能的重要方向，第{i}篇文档介绍{subjects[(i * 3) % 5]}"
+            for i in range(600)
+        ]
+
+    def test_scores_within_tolerance(self, documents: list[str]):
+        """f32 分数与 f64 分数在误差范围内一致"""
+        full = BM25()
+        full.fit(documents)
+        narrow = BM25(f32_scores=True)
+        narrow.fit(documents)
+
+        for query in ["机器学习", "深度学习 数据分析", "人工智能 计算机视觉"]:
+            expected = full.get_scores(query)
+            actual = narrow.get_scores(query)
+            assert actual == pytest.approx(expected, rel=1e-6)
+
+            expected_top = dict(full.search(query, top_k=20))
+            for doc_id, score in narrow.search(query, top_k=20):
+                assert score == pytest.approx(expected_top[doc_id], rel=1e-6)
+
+    def test_ranking_order_preserved(self, documents: list[str]):
+        """f32 模式的 Top-K 排序与 f64 一致 (只有分数在 f32 误差内相等的文档可能交换位置)"""
+        full = BM25()
+        full.fit(documents)
+        narrow = BM25(f32_scores=True)
+        narrow.fit(documents)
+
+        for query in ["机器学习", "深度学习 数据分析", "人工智能 计算机视觉 自然语言处理"]:
+            expected = full.search(query, top_k=50)
+            actual = narrow.search(query, top_k=50)
+            reference = full.get_scores(query)
+            for (expected_id, expected_score), (actual_id, _) in zip(expected, actual):
+                if actual_id != expected_id:
+                    assert reference[actual_id] == pytest.approx(expected_score, rel=1e-6)
+            assert {doc_id for doc_id, _ in actual} - {doc_id for doc_id, _ in expected} <= {
+                doc_id
+                for doc_id, score in enumerate(reference)
+                if score == pytest.approx(expected[-1][1], rel=1e-6)
+            }
+
+    def test_index_is_smaller(self, documents: list[str], tmp_path):
+        """f32 模式下保存的索引更小，加载后分数不变"""
+        full = BM25()
+        full.fit(documents)
+        narrow = BM25(f32_scores=True)
+        narrow.fit(documents)
+
+        full_path = tmp_path / "f64.bin"
+        narrow_path = tmp_path / "f32.bin"
+        full.save(str(full_path))
+        narrow.save(str(narrow_path))
+        assert narrow_path.stat().st_size < full_path.stat().st_size
+
+        loaded = BM25.load(str(narrow_path))
+        assert loaded.search("机器学习", top_k=10) == narrow.search("机器学习", top_k=10)
+
+    def test_rounded_up_bounds_keep_top_k_exact(self, documents: list[str]):
+        """向上取整的 Block max_score 不低于块内真实得分，剪枝后的 Top-K 与暴力排序一致"""
+        narrow = BM25(f32_scores=True)
+        narrow.fit(documents)
+
+        doc_count, blocks = narrow.export_term("数据分析")
+        assert doc_count > 0 and len(blocks) > 1
+        scores = narrow.get_scores("数据分析")
+        for _, max_score, postings in blocks:
+            assert max_score >= max(scores[doc_id] for doc_id, _, _ in postings)
+
+        for query in ["机器学习", "深度学习 数据分析", "人工智能 计算机视觉 自然语言处理"]:
+            scores = narrow.get_scores(query)
+            brute = sorted((s for s in scores if s > 0), reverse=True)[:20]
+            assert [score for _, score in narrow.search(query, top_k=20)] == brute
+
+
+class TestBM25EmptyDocuments:
+    """空文档 (空白或控制字符) 处理策略测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["机器学习入门", "   ", "深度学习教程", "\x00\x07\n\t", "机器学习实践"]
+
+    def test_keep_policy(self, documents: list[str]):
+        """默认保留空文档，分数中无 NaN"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        scores = bm25.get_scores("机器学习")
+        assert all(math.isfinite(s) for s in scores)
+        assert scores[1] == 0 and scores[3] == 0
+
+    def test_reject_policy(self, documents: list[str]):
+        """Reject 策略在 fit 时报错并列出下标"""
+        bm25 = BM25(empty_doc_policy=EmptyDocPolicy.Reject)
+        with pytest.raises(ValueError, match=r"\[1, 3\]"):
+            bm25.fit(documents)
+
+        bm25.fit([documents[0], documents[2]])
+        assert bm25.search("机器学习")[0][0] == 0
+
+    def test_min_length_policy(self, documents: list[str]):
+        """MinLength 策略下空文档按长度 1 归一化，avgdl 不再被拉低"""
+        keep = BM25()
+        keep.fit(documents)
+        min_length = BM25(empty_doc_policy=EmptyDocPolicy.MinLength)
+        min_length.fit(documents)
+
+        keep_scores = keep.get_scores("机器学习")
+        scores = min_length.get_scores("机器学习")
+        assert all(math.isfinite(s) for s in scores)
+        assert scores[0] > keep_scores[0]
+
+    def test_all_blank_corpus(self):
+        """全部为空文档时搜索不产生 NaN"""
+        for policy in [EmptyDocPolicy.Keep, EmptyDocPolicy.MinLength]:
+            bm25 = BM25(empty_doc_policy=policy)
+            bm25.fit(["  ", "\r\n"])
+            assert bm25.search("机器学习") == []
+            assert bm25.get_scores("机器学习") == [0.0, 0.0]
+
+
+class TestBM25TermImpact:
+    """查询词影响力测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            [
+                "苹果 香蕉",
+                "苹果 苹果 葡萄",
+                "苹果 西瓜",
+                "香蕉 西瓜",
+                "苹果",
+                "草莓 西瓜",
+            ]
+        )
+        return model
+
+    def test_impact_matches_term_contributions(self, bm25: BM25):
+        """影响力等于各词在 Top-K 文档上的分数之和"""
+        query = "苹果 香蕉 葡萄"
+        top_ids = [doc_id for doc_id, _ in bm25.search(query, top_k=3)]
+        impact = dict(bm25.term_impact(query, top_k=3))
+
+        for term in ["苹果", "香蕉", "葡萄"]:
+            scores = bm25.get_scores(term)
+            assert impact[term] == pytest.approx(sum(scores[i] for i in top_ids))
+
+    def test_ranking(self, bm25: BM25):
+        """按贡献降序排列 (较稀有的香蕉命中更少却贡献更多)，不存在的词贡献为 0"""
+        impact = bm25.term_impact("苹果 香蕉 榴莲", top_k=10)
+        assert [term for term, _ in impact] == ["香蕉", "苹果", "榴莲"]
+        assert impact[0][1] > impact[1][1] > 0
+        assert impact[2][1] == 0.0
+
+
+class TestBM25UpdateDocument:
+    """单文档增量更新测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return [
+            "Python是一种广泛使用的高级编程语言",
+            "机器学习是人工智能的一个分支",
+            "深度学习是机器学习的子领域",
+            "自然语言处理研究人与计算机之间的语言交互",
+        ]
+
+    def test_search_reflects_new_content(self, documents: list[str]):
+        """更新后搜索命中新内容而非旧内容"""
+        bm25 = BM25()
+        bm25.fit(documents, ids=[10, 11, 12, 13])
+        assert 13 in [doc_id for doc_id, _ in bm25.search("自然语言处理")]
+
+        assert bm25.update_document(13, "区块链技术与加密货币") is True
+        assert 13 not in [doc_id for doc_id, _ in bm25.search("自然语言处理")]
+        assert bm25.search("区块链")[0][0] == 13
+
+    def test_matches_refit(self, documents: list[str]):
+        """更新后的分数与用新语料重新 fit 一致"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        bm25.update_document(1, "机器学习 机器学习 与 Python 数据分析")
+
+        expected = BM25()
+        expected.fit([documents[0], "机器学习 机器学习 与 Python 数据分析", *documents[2:]])
+        for query in ["机器学习", "Python 数据分析", "人工智能", "深度学习"]:
+            assert bm25.get_scores(query) == pytest.approx(expected.get_scores(query))
+            assert bm25.search(query) == expected.search(query)
+
+    def test_multi_block_update(self):
+        """跨多个 Block 的倒排列表更新 (插入触发块拆分)"""
+        docs = [f"文档 编号{i}" if i % 2 else f"文档 测试 编号{i}" for i in range(600)]
+        bm25 = BM25()
+        bm25.fit(docs)
+        for doc_id in range(1, 600, 4):
+            docs[doc_id] = f"文档 测试 编号{doc_id}"
+            bm25.update_document(doc_id, docs[doc_id])
+
+        expected = BM25()
+        expected.fit(docs)
+        assert bm25.search("测试", top_k=50) == expected.search("测试", top_k=50)
+        assert bm25.get_scores("测试") == pytest.approx(expected.get_scores("测试"))
+
+    def test_update_with_positions(self):
+        """存储位置时更新后短语加权仍然正确"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(["机器 学习 入门", "学习 机器 原理"])
+        bm25.update_document(1, "机器 学习 原理")
+        results = bm25.search("机器 学习", phrase_boost=1.0)
+        assert results[0][1] == pytest.approx(results[1][1])
+
+    def test_unknown_id(self, documents: list[str]):
+        """不存在的 ID 返回 False"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        assert bm25.update_document(999, "新内容") is False
+
+    def test_term_statistics_after_update(self, documents: list[str]):
+        """更新后各词的文档频率与 Block max_score 与重新 fit 一致"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        new_text = "机器学习 与 深度学习 的 区别"
+        bm25.update_document(0, new_text)
+
+        expected = BM25()
+        expected.fit([new_text, *documents[1:]])
+        for term in ["Python", "机器学习", "深度学习", "区别"]:
+            assert bm25.doc_count(term) == expected.doc_count(term)
+        assert bm25.doc_count("Python") == 0
+        assert bm25.export_term("机器学习") == expected.export_term("机器学习")
+        assert bm25.approx_eq(expected)
+
+
+
+
+class TestBM25AddDocument:
+    """增量添加文档测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "榴莲", "芒果"]
+        return [" ".join(words[i % 7 : i % 7 + 1 + i % 4] * (1 + i % 3)) for i in range(700)]
+
+    @pytest.mark.parametrize("store_positions", [False, True])
+    def test_matches_refit(self, documents: list[str], store_positions: bool):
+        """逐个追加文档后与对全部文档重新 fit 的索引等价，分数一致"""
+        incremental = BM25(store_positions=store_positions)
+        incremental.fit(documents[:300])
+        for i, doc in enumerate(documents[300:], start=300):
+            assert incremental.add_document(doc) == i
+
+        refit = BM25(store_positions=store_positions)
+        refit.fit(documents)
+        assert incremental.approx_eq(refit)
+        for query in ["苹果", "香蕉 榴莲", "葡萄 西瓜 芒果"]:
+            assert incremental.search(query, top_k=20) == refit.search(query, top_k=20)
+            assert incremental.get_scores(query) == refit.get_scores(query)
+
+    def test_ids(self, documents: list[str]):
+        """默认 ID 为当前最大 ID + 1；可指定 ID；重复 ID 报错"""
+        bm25 = BM25()
+        bm25.fit(documents[:3], ids=[10, 30, 20])
+        assert bm25.add_document("榴莲 芒果") == 31
+        assert bm25.add_document("榴莲", id=5) == 5
+        with pytest.raises(ValueError, match="already exists"):
+            bm25.add_document("榴莲", id=30)
+        assert {doc_id for doc_id, _ in bm25.search("榴莲")} == {31, 5}
+
+    def test_id_bookkeeping(self, documents: list[str], tmp_path):
+        """ID 映射在删除、compact、加载后保持一致；最大 ID 为 u64::MAX 时默认 ID 报错"""
+        bm25 = BM25()
+        bm25.fit(documents[:3], ids=[10, 30, 20])
+        assert bm25.delete_document(30)
+        assert bm25.add_document("榴莲", id=30) == 30
+        assert bm25.add_document("芒果") == 31
+        bm25.compact()
+        assert bm25.delete_document(20)
+        assert not bm25.delete_document(20)
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        with pytest.raises(ValueError, match="already exists"):
+            loaded.add_document("榴莲", id=31)
+        assert loaded.add_document("西瓜") == 32
+        assert loaded.update_document(30, "草莓")
+
+        top = BM25()
+        top.fit(["苹果"], ids=[2**64 - 1])
+        with pytest.raises(ValueError, match="u64::MAX"):
+            top.add_document("香蕉")
+        assert top.add_document("香蕉", id=0) == 0
+        with pytest.raises(ValueError, match="u64::MAX"):
+            top.add_document("西瓜")
+
+    def test_many_adds(self):
+        """逐个追加大量文档 (重复检查与默认 ID 为常数时间)"""
+        bm25 = BM25()
+        for i in range(20000):
+            bm25.add_document(f"文档 编号{i % 50}")
+        assert bm25.corpus_size == 20000
+        assert bm25.add_document("文档") == 20000
+
+    def test_empty_index_and_metadata(self):
+        """未 fit 的索引同样可以追加；元数据表保持与文档对齐"""
+        bm25 = BM25()
+        assert bm25.add_document("苹果 香蕉") == 0
+        assert bm25.add_document("香蕉") == 1
+        assert [doc_id for doc_id, _ in bm25.search("苹果")] == [0]
+
+        with_metadata = BM25()
+        with_metadata.fit(["苹果"], metadata=[{"source": "fit"}])
+        with_metadata.add_document("苹果 香蕉")
+        results = with_metadata.search_with_metadata("香蕉")
+        assert results == [(1, results[0][1], {})]
+
+
+
+class TestBM25DeleteDocument:
+    """文档删除 (墓碑) 与槽位回收测试"""
+
+    QUERIES = ["苹果", "香蕉 榴莲", "葡萄 西瓜 芒果"]
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "榴莲", "芒果"]
+        return [" ".join(words[i % 7 : i % 7 + 1 + i % 4] * (1 + i % 3)) for i in range(400)]
+
+    @pytest.fixture
+    def deleted_ids(self) -> set[int]:
+        return set(range(0, 400, 3)) | set(range(128, 256))
+
+    def refit(self, documents: list[str], deleted_ids: set[int]) -> BM25:
+        kept = [i for i in range(len(documents)) if i not in deleted_ids]
+        bm25 = BM25(store_positions=True)
+        bm25.fit([documents[i] for i in kept], ids=kept)
+        return bm25
+
+    def test_scores_match_refit(self, documents: list[str], deleted_ids: set[int]):
+        """删除后的分数 (idf 与 avgdl 只统计未删除文档) 与只对剩余文档 fit 一致"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(documents)
+        for doc_id in deleted_ids:
+            assert bm25.delete_document(doc_id) is True
+        expected = self.refit(documents, deleted_ids)
+        assert bm25.corpus_stats() == pytest.approx(expected.corpus_stats())
+
+        for query in self.QUERIES:
+            results = bm25.search(query, top_k=30)
+            assert [doc_id for doc_id, _ in results] == [doc_id for doc_id, _ in expected.search(query, top_k=30)]
+            assert not {doc_id for doc_id, _ in results} & deleted_ids
+            scores = bm25.get_scores(query)
+            assert len(scores) == 400
+            assert all(scores[i] == 0.0 for i in deleted_ids)
+            kept = [scores[i] for i in range(400) if i not in deleted_ids]
+            assert kept == pytest.approx(expected.get_scores(query))
+
+    def test_missing_and_reuse(self, documents: list[str]):
+        """不存在或已删除的 ID 返回 False；已删除的 ID 可以重新添加"""
+        bm25 = BM25()
+        bm25.fit(documents[:5])
+        assert bm25.delete_document(99) is False
+        assert bm25.delete_document(2) is True
+        assert bm25.delete_document(2) is False
+        assert bm25.update_document(2, "榴莲") is False
+        assert bm25.document_vector(2) is None
+        assert bm25.add_document("榴莲 榴莲", id=2) == 2
+        assert bm25.search("榴莲")[0][0] == 2
+
+    def test_compact(self, documents: list[str], deleted_ids: set[int], tmp_path):
+        """墓碑随索引保存；compact 回收槽位后与只对剩余文档 fit 的索引等价"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(documents)
+        for doc_id in deleted_ids:
+            bm25.delete_document(doc_id)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("香蕉 榴莲") == bm25.search("香蕉 榴莲")
+
+        expected = self.refit(documents, deleted_ids)
+        bm25.compact()
+        assert len(bm25.get_scores("苹果")) == 400 - len(deleted_ids)
+        bm25.rebuild_block_max_scores()
+        assert bm25.approx_eq(expected)
+        for query in self.QUERIES:
+            assert bm25.search(query, top_k=30) == expected.search(query, top_k=30)
+
+
+class TestBM25Optimize:
+    """碎片 Block 整理测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["苹果 西瓜"] * 1000
+
+    @staticmethod
+    def block_sizes(bm25: BM25, term: str) -> list[int]:
+        _, blocks = bm25.export_term(term)
+        return [len(postings) for _, _, postings in blocks]
+
+    @pytest.fixture
+    def fragmented(self, documents: list[str]) -> BM25:
+        bm25 = BM25()
+        bm25.fit(documents)
+        # 前 4 个 Block 中删掉 3/4 的 "苹果"
+        for i in range(512):
+            if i % 4 != 0:
+                bm25.update_document(i, "西瓜")
+        return bm25
+
+    def test_low_ratio_is_noop(self, fragmented: BM25):
+        """填充率阈值低于碎片块的填充率时不改写任何块"""
+        assert self.block_sizes(fragmented, "苹果") == [32] * 4 + [128] * 3 + [104]
+        before = fragmented.export_term("苹果")
+        assert fragmented.optimize(min_fill_ratio=0.2) == 0
+        assert fragmented.export_term("苹果") == before
+
+    def test_high_ratio_compacts(self, fragmented: BM25, documents: list[str]):
+        """min_fill_ratio=1.0 时完全压实，分块与重新 fit 一致，结果不变"""
+        query = "苹果 西瓜"
+        expected = fragmented.search(query, top_k=20)
+        assert fragmented.optimize() >= 2
+        assert self.block_sizes(fragmented, "苹果") == [128] * 4 + [104]
+
+        refit = BM25()
+        refit.fit(["西瓜" if i < 512 and i % 4 != 0 else doc for i, doc in enumerate(documents)])
+        for term in ["苹果", "西瓜"]:
+            assert self.block_sizes(fragmented, term) == self.block_sizes(refit, term)
+        assert fragmented.search(query, top_k=20) == expected
+        fragmented.rebuild_block_max_scores()
+        assert fragmented.approx_eq(refit)
+
+    def test_invalid_ratio(self, fragmented: BM25):
+        """min_fill_ratio 超出 [0, 1] 时报错"""
+        with pytest.raises(ValueError, match="min_fill_ratio"):
+            fragmented.optimize(min_fill_ratio=1.5)
+
+
+class TestBM25MaxBlocksScanned:
+    """单次查询扫描 Block 数上限测试"""
+
+    @pytest.fixture
+    def docs(self) -> list[str]:
+        # 每个文档都包含两个词，词频随 doc_id 伪随机变化 (共 8 个 Block)
+        return [
+            " ".join(["文档"] * (1 + (i * 7919) % 5) + ["测试"] * (1 + (i * 104729) % 3))
+            for i in range(1000)
+        ]
+
+    def test_cap_limits_blocks(self, docs: list[str]):
+        """达到上限后停止扫描，返回已扫描部分中的最优结果"""
+        bm25 = BM25()
+        bm25.fit(docs)
+
+        results, stats = bm25.search_with_stats("文档 测试", top_k=10, max_blocks_scanned=4)
+        assert stats.truncated
+        assert stats.blocks_scanned <= 4
+        assert results == bm25.search("文档 测试", top_k=10, max_blocks_scanned=4)
+
+        # 两个词各扫描了前两个 Block，即 doc_id < 256 的文档
+        scores = bm25.get_scores("文档 测试")
+        expected = sorted(range(256), key=lambda i: (-scores[i], i))[:10]
+        assert [doc_id for doc_id, _ in results] == expected
+
+    def test_no_cap(self, docs: list[str]):
+        """不设上限时结果精确且不截断"""
+        bm25 = BM25()
+        bm25.fit(docs)
+        results, stats = bm25.search_with_stats("文档 测试", top_k=10)
+        assert not stats.truncated
+        assert stats.blocks_scanned == 16
+        assert stats.docs_scored == 1000
+        assert results == bm25.search("文档 测试", top_k=10)
+
+    def test_single_term_cap(self):
+        """单词快速路径同样受上限约束"""
+        docs = [" ".join(["文档"] * (1 + i // 128) + ["填充"] * 8) for i in range(1000)]
+        bm25 = BM25()
+        bm25.fit(docs)
+
+        results, stats = bm25.search_with_stats("文档", top_k=5, max_blocks_scanned=2)
+        assert stats.truncated
+        assert stats.blocks_scanned == 2
+        assert all(128 <= doc_id < 256 for doc_id, _ in results)
+
+        full = bm25.search("文档", top_k=5)
+        assert all(doc_id >= 896 for doc_id, _ in full)
+
+
+class TestBM25SearchTimed:
+    """搜索分阶段计时测试"""
+
+    def test_timing_fields_populated(self):
+        """各阶段耗时非负，之和与总耗时大致相等；结果与普通搜索一致"""
+        bm25 = BM25()
+        bm25.fit([f"文档 测试 第{i}篇" for i in range(2000)])
+        results, timing = bm25.search_timed("文档 测试", top_k=10)
+        assert results == bm25.search("文档 测试", top_k=10)
+
+        phases = [timing.tokenize_us, timing.setup_us, timing.traversal_us, timing.assembly_us]
+        assert all(t >= 0.0 for t in phases)
+        assert timing.tokenize_us > 0.0 and timing.traversal_us > 0.0
+        assert sum(phases) == pytest.approx(timing.total_us, rel=0.05, abs=5.0)
+        assert "traversal_us" in repr(timing)
+
+    def test_unknown_query(self):
+        """查询词不在词表中时返回空结果，计时仍然有效"""
+        bm25 = BM25()
+        bm25.fit(["苹果 香蕉"])
+        results, timing = bm25.search_timed("榴莲")
+        assert results == []
+        assert timing.total_us >= timing.tokenize_us >= 0.0
+
+
+
+class TestBM25WandPruning:
+    """多词查询 WAND 剪枝测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        # 高频词遍布全部文档，低频词集中在少数文档且词频较高
+        docs = []
+        for i in range(6000):
+            words = ["苹果"] * (1 + i % 3)
+            if i % 2 == 0:
+                words += ["香蕉"] * (1 + i % 5)
+            if i % 997 == 0:
+                words += ["榴莲"] * 4
+            if i % 13 == 0:
+                words.append("葡萄")
+            docs.append(" ".join(words))
+        model = BM25(store_positions=True)
+        model.fit(docs)
+        return model
+
+    @staticmethod
+    def expected(bm25: BM25, query: str, top_k: int) -> list[int]:
+        scores = bm25.get_scores(query)
+        ranked = sorted((i for i, s in enumerate(scores) if s > 0), key=lambda i: (-scores[i], i))
+        return ranked[:top_k]
+
+    @pytest.mark.parametrize(
+        "query, top_k",
+        [
+            ("苹果 香蕉 榴莲", 1),
+            ("苹果 香蕉 榴莲", 5),
+            ("香蕉 葡萄", 5),
+            ("香蕉 葡萄", 50),
+            ("苹果 葡萄 榴莲", 10),
+            ("榴莲 苹果", 50),
+        ],
+    )
+    def test_matches_exhaustive_ranking(self, bm25: BM25, query: str, top_k: int):
+        """剪枝后的结果与按 get_scores 全量排序完全一致"""
+        results = bm25.search(query, top_k=top_k)
+        assert [doc_id for doc_id, _ in results] == self.expected(bm25, query, top_k)
+        scores = bm25.get_scores(query)
+        for doc_id, score in results:
+            assert score == pytest.approx(scores[doc_id])
+
+    def test_skips_scoring(self, bm25: BM25):
+        """上界不足的文档被跳过，完整打分的文档远少于命中文档"""
+        query = "苹果 香蕉 榴莲"
+        results, stats = bm25.search_with_stats(query, top_k=5)
+        matched = sum(1 for s in bm25.get_scores(query) if s > 0)
+        assert matched == 6000
+        assert stats.docs_scored < matched // 2
+        assert results == bm25.search(query, top_k=5)
+
+    def test_with_phrase_and_coverage(self, bm25: BM25):
+        """短语加分与覆盖率阈值下剪枝同样不影响结果"""
+        boosted = bm25.search("苹果 香蕉", top_k=10, phrase_boost=5.0)
+        plain = bm25.get_scores("苹果 香蕉")
+        assert len(boosted) == 10
+        assert all(score >= plain[doc_id] for doc_id, score in boosted)
+
+        covered = bm25.search("苹果 香蕉 榴莲", top_k=3, min_coverage=0.9)
+        assert {doc_id for doc_id, _ in covered} <= set(range(0, 6000, 997))
+
+    def test_skips_low_max_block(self):
+        """第一个 Block 的上界不足以进入 Top-K 时整块跳过，直接跳到第二个 Block"""
+        bm25 = BM25()
+        bm25.fit(["苹果 香蕉"] * 128 + ["苹果 苹果 苹果 香蕉 香蕉"] * 128)
+        results, stats = bm25.search_with_stats("苹果 香蕉", top_k=5)
+        assert [doc_id for doc_id, _ in results] == [128, 129, 130, 131, 132]
+        # 堆填满前打分 5 个文档，之后第一个 Block 整块跳过；第二个 Block 同样只打分前 5 个
+        assert stats.docs_scored == 10
+        for _, _, blocks in bm25.search_debug("苹果 香蕉", top_k=5):
+            assert blocks == [("苹果", 1), ("香蕉", 1)]
+
+
+class TestBM25EmptyQueryFallback:
+    """查询词全部未登录时的兜底排序测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return [
+            "机器学习是人工智能的一个分支，应用非常广泛",
+            "Python 编程",
+            "深度学习是机器学习的子领域",
+        ]
+
+    def test_disabled_returns_empty(self, documents: list[str]):
+        """默认不兜底"""
+        bm25 = BM25()
+        bm25.fit(documents, ids=[30, 10, 20])
+        assert bm25.search("区块链") == []
+
+    def test_length_sorted(self, documents: list[str]):
+        """按文档长度升序返回，分数为 0"""
+        bm25 = BM25(empty_query_fallback=FallbackMode.LengthSorted)
+        bm25.fit(documents, ids=[30, 10, 20])
+        assert bm25.search("区块链", top_k=2) == [(10, 0.0), (20, 0.0)]
+        # 有词命中时不受影响
+        assert [doc_id for doc_id, _ in bm25.search("Python")] == [10]
+
+    def test_id_sorted_and_persisted(self, documents: list[str], tmp_path):
+        """按外部 ID 升序返回，配置随索引保存"""
+        bm25 = BM25(empty_query_fallback=FallbackMode.IdSorted)
+        bm25.fit(documents, ids=[30, 10, 20])
+        assert bm25.search("") == [(10, 0.0), (20, 0.0), (30, 0.0)]
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("区块链", top_k=1) == [(10, 0.0)]
+
+
+class TestBM25IdfSpectrum:
+    """idf 分布直方图测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        # 苹果出现在 8 篇文档中，香蕉/葡萄各 2 篇，西瓜/草莓/芒果各 1 篇
+        docs = ["苹果"] * 4 + ["苹果 香蕉 葡萄"] * 2 + ["苹果 西瓜", "苹果 草莓 芒果"]
+        model = BM25()
+        model.fit(docs)
+        return model
+
+    def test_bin_counts(self, bm25: BM25):
+        """三种文档频率落在不同区间"""
+        spectrum = bm25.idf_spectrum(4)
+        assert len(spectrum) == 4
+        assert [count for _, _, count in spectrum] == [1, 0, 2, 3]
+        assert sum(count for _, _, count in spectrum) == 6
+
+        lows = [lo for lo, _, _ in spectrum]
+        assert lows == sorted(lows)
+        assert spectrum[0][0] == pytest.approx(math.log((8 - 8 + 0.5) / (8 + 0.5) + 1))
+        assert spectrum[-1][1] == pytest.approx(math.log((8 - 1 + 0.5) / (1 + 0.5) + 1))
+
+    def test_single_bin_and_errors(self, bm25: BM25):
+        """单个区间包含全部词；空索引返回空列表；bins 为 0 报错"""
+        assert bm25.idf_spectrum(1)[0][2] == 6
+        assert BM25().idf_spectrum(3) == []
+        with pytest.raises(ValueError, match="bins"):
+            bm25.idf_spectrum(0)
+
+
+class TestBM25FitWeighted:
+    """预加权文档测试"""
+
+    def test_higher_weight_contributes_more(self):
+        """权重更高的词比同词频的普通词贡献更多"""
+        bm25 = BM25()
+        bm25.fit_weighted(
+            [
+                [("苹果", 3.0), ("正文", 1.0)],
+                [("香蕉", 1.0), ("正文", 1.0)],
+                [("正文", 1.0), ("其他", 1.0)],
+            ],
+            ids=[1, 2, 3],
+        )
+        keyword = dict(bm25.search("苹果"))[1]
+        normal = dict(bm25.search("香蕉"))[2]
+        assert keyword > normal
+
+        # 饱和函数作用于加权词频: 权重 3 的贡献小于 3 倍
+        assert keyword < 3 * normal
+
+    def test_unit_weights_match_fit(self):
+        """权重全为 1 时与普通 fit 结果一致"""
+        docs = [["机器", "学习", "机器"], ["深度", "学习"], ["Python", "编程"]]
+        plain = BM25()
+        plain.fit([" ".join(doc) for doc in docs])
+        weighted = BM25()
+        weighted.fit_weighted([[(term, 1.0) for term in doc] for doc in docs])
+
+        for query in ["机器", "学习", "Python 编程"]:
+            assert weighted.get_scores(query) == pytest.approx(plain.get_scores(query))
+
+    def test_save_load_and_validation(self, tmp_path):
+        """加权词频随索引保存；非法权重报错"""
+        bm25 = BM25(lowercase=True)
+        bm25.fit_weighted([[("Rust", 2.5)], [("rust", 1.0), ("系统", 1.0)]])
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).get_scores("RUST") == bm25.get_scores("rust")
+
+        with pytest.raises(ValueError, match="weights"):
+            bm25.fit_weighted([[("词", -1.0)]])
+
+
+
+class TestBM25FitFields:
+    """BM25F 多字段打分测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[dict[str, str]]:
+        return [
+            {"title": "苹果 手机", "body": "新款 发布 香蕉"},
+            {"title": "香蕉", "body": "苹果 苹果 价格 便宜 好吃"},
+            {"title": "西瓜", "body": "夏天"},
+            {"body": "苹果 西瓜 香蕉"},
+        ]
+
+    def test_matches_bm25f_formula(self, documents: list[dict[str, str]]):
+        """各字段按自身平均长度归一化，加权合并词频后再做 k1 饱和"""
+        weights = {"title": 3.0, "body": 1.0}
+        k1, b = 1.5, 0.75
+        bm25 = BM25(k1=k1, b=b)
+        bm25.fit_fields(documents, weights, ids=[10, 20, 30, 40])
+
+        lens = {f: [len(d.get(f, "").split()) for d in documents] for f in weights}
+        avg = {f: sum(lens[f]) / len(documents) for f in weights}
+        n = sum("苹果" in " ".join(d.values()).split() for d in documents)
+        idf = math.log((len(documents) - n + 0.5) / (n + 0.5) + 1.0)
+        expected = []
+        for i, doc in enumerate(documents):
+            tf = sum(
+                weights[f] * doc.get(f, "").split().count("苹果") / (1 - b + b * lens[f][i] / avg[f])
+                for f in weights
+            )
+            expected.append(idf * tf * (k1 + 1) / (tf + k1))
+        assert bm25.get_scores("苹果") == pytest.approx(expected)
+
+        # 标题命中的文档排在正文多次命中的文档之前
+        assert [doc_id for doc_id, _ in bm25.search("苹果")][0] == 10
+
+    def test_save_load_and_errors(self, documents: list[dict[str, str]], tmp_path):
+        """字段打分随索引保存；不支持增量更新；重新 fit 恢复普通打分"""
+        bm25 = BM25()
+        bm25.fit_fields(documents, {"title": 2.0, "body": 1.0})
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).get_scores("香蕉 苹果") == bm25.get_scores("香蕉 苹果")
+
+        with pytest.raises(ValueError, match="fit_fields"):
+            bm25.add_document("苹果")
+        with pytest.raises(ValueError, match="fit_fields"):
+            bm25.update_document(0, "苹果")
+        with pytest.raises(ValueError, match="field_weights"):
+            bm25.fit_fields(documents, {})
+        with pytest.raises(ValueError, match="weights"):
+            bm25.fit_fields(documents, {"title": -1.0})
+
+        texts = [" ".join(doc.values()) for doc in documents]
+        bm25.fit(texts)
+        plain = BM25()
+        plain.fit(texts)
+        assert bm25.get_scores("苹果") == plain.get_scores("苹果")
+
+
+class TestBM25SearchJson:
+    """JSON 导出测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["Python是一种广泛使用的高级编程语言", "Python在机器学习领域非常流行", "Java 编程"],
+            ids=[101, 102, 103],
+        )
+        return model
+
+    def test_full_precision(self, bm25: BM25):
+        """默认输出与 search 结果一致"""
+        hits = json.loads(bm25.search_json("Python 编程"))
+        assert [(h["id"], h["score"]) for h in hits] == bm25.search("Python 编程")
+
+    def test_score_decimals(self, bm25: BM25):
+        """分数按指定小数位数四舍五入，底层搜索不受影响"""
+        raw = bm25.search_json("Python 编程", score_decimals=3)
+        hits = json.loads(raw)
+        expected = bm25.search("Python 编程")
+        assert [h["id"] for h in hits] == [doc_id for doc_id, _ in expected]
+        for hit, (_, score) in zip(hits, expected):
+            assert hit["score"] == round(score, 3)
+            assert len(str(hit["score"]).split(".")[1]) <= 3
+
+        assert bm25.search("Python 编程") == expected
+        assert json.loads(bm25.search_json("区块链")) == []
+
+
+
+class TestBM25SearchCumulative:
+    """累计分数占比测试"""
+
+    def test_monotonic_and_reaches_one(self):
+        """累计占比单调递增，最后一个结果为 1.0，前两项与 search 一致"""
+        bm25 = BM25()
+        bm25.fit(["苹果 苹果 香蕉", "苹果 葡萄", "香蕉 西瓜", "苹果", "草莓"])
+        results = bm25.search_cumulative("苹果 香蕉")
+        assert [(doc_id, score) for doc_id, score, _ in results] == bm25.search("苹果 香蕉")
+        fractions = [cumulative for _, _, cumulative in results]
+        assert all(a < b for a, b in zip(fractions, fractions[1:]))
+        assert fractions[-1] == 1.0
+        total = sum(score for _, score, _ in results)
+        assert fractions[0] == pytest.approx(results[0][1] / total)
+
+    def test_empty_and_zero_scores(self):
+        """无结果时返回空列表；兜底结果分数全为 0 时按名次均分"""
+        bm25 = BM25(empty_query_fallback=FallbackMode.IdSorted)
+        bm25.fit(["苹果", "香蕉", "葡萄", "西瓜"])
+        assert [c for _, _, c in bm25.search_cumulative("榴莲")] == [0.25, 0.5, 0.75, 1.0]
+        assert BM25().search_cumulative("苹果") == []
+
+
+class TestBM25BinaryTf:
+    """二值词频 (binary_tf) 测试"""
+
+    def test_repeated_term_scores_identically(self):
+        """重复出现的词与只出现一次的词得分相同"""
+        bm25 = BM25(binary_tf=True)
+        bm25.fit(["苹果 苹果 苹果 香蕉", "苹果 西瓜 香蕉 草莓", "葡萄"])
+        scores = bm25.get_scores("苹果")
+        # 两个文档长度同为 4，词频都按 1 计
+        assert scores[0] == pytest.approx(scores[1])
+        assert bm25.search("苹果", top_k=2)[0][1] == pytest.approx(scores[0])
+
+        plain = BM25()
+        plain.fit(["苹果 苹果 苹果 香蕉", "苹果 西瓜 香蕉 草莓", "葡萄"])
+        plain_scores = plain.get_scores("苹果")
+        assert plain_scores[0] > plain_scores[1]
+        assert plain_scores[1] == pytest.approx(scores[1])
+
+    def test_persisted_and_update(self, tmp_path):
+        """配置随索引保存，增量更新同样截断词频"""
+        bm25 = BM25(binary_tf=True)
+        bm25.fit(["苹果 香蕉", "西瓜 草莓"])
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+
+        loaded.update_document(1, "苹果 苹果")
+        scores = loaded.get_scores("苹果")
+        assert scores[0] == pytest.approx(scores[1])
+
+
+class TestBM25ExportTerm:
+    """倒排列表导出测试"""
+
+    def test_structure_matches_postings(self):
+        """导出结构与已知的 Posting 一致"""
+        docs = ["苹果 香蕉" if i % 3 == 0 else "西瓜 草莓 葡萄" for i in range(300)]
+        docs[3] = "苹果 苹果 香蕉 西瓜"
+        bm25 = BM25()
+        bm25.fit(docs)
+
+        doc_count, blocks = bm25.export_term("苹果")
+        expected_ids = [i for i in range(300) if i % 3 == 0]
+        assert doc_count == len(expected_ids) == 100
+
+        # 100 个文档放在同一个 Block 中
+        assert len(blocks) == 1
+        last_doc_id, max_score, postings = blocks[0]
+        assert last_doc_id == expected_ids[-1]
+        assert [doc_id for doc_id, _, _ in postings] == expected_ids
+        assert postings[1] == (3, 2, 4)
+        assert postings[0] == (0, 1, 2)
+
+        scores = bm25.get_scores("苹果")
+        assert max_score == pytest.approx(max(scores))
+
+    def test_multiple_blocks_and_missing(self):
+        """跨 Block 的 last_doc_id；不存在的词返回 None"""
+        bm25 = BM25(lowercase=True)
+        bm25.fit(["Python 文档"] * 300)
+        doc_count, blocks = bm25.export_term("PYTHON")
+        assert doc_count == 300
+        assert [len(postings) for _, _, postings in blocks] == [128, 128, 44]
+        assert [last for last, _, _ in blocks] == [127, 255, 299]
+        assert bm25.export_term("Rust") is None
+
+
+
+class TestBM25BlockSize:
+    """可配置 Block 大小测试"""
+
+    @pytest.fixture
+    def docs(self) -> list[str]:
+        return [f"苹果 {'香蕉' if i % 3 else '西瓜'} 文档{i % 7}" for i in range(300)]
+
+    @staticmethod
+    def block_sizes(bm25: BM25, term: str) -> list[int]:
+        _, blocks = bm25.export_term(term)
+        return [len(postings) for _, _, postings in blocks]
+
+    def test_fit_chunking(self, docs: list[str]):
+        """fit 按 block_size 分块，不同块大小的检索结果一致"""
+        small = BM25(block_size=32)
+        small.fit(docs)
+        assert self.block_sizes(small, "苹果") == [32] * 9 + [12]
+
+        default = BM25()
+        default.fit(docs)
+        assert self.block_sizes(default, "苹果") == [128, 128, 44]
+        for query in ["苹果", "香蕉 西瓜", "西瓜 文档"]:
+            assert small.search(query, top_k=10) == default.search(query, top_k=10)
+
+    def test_incremental_and_optimize(self, docs: list[str]):
+        """增量添加与 optimize 同样遵守 block_size"""
+        bm25 = BM25(block_size=16)
+        bm25.fit(docs[:40])
+        for i, doc in enumerate(docs[40:80]):
+            bm25.add_document(doc, 40 + i)
+        assert max(self.block_sizes(bm25, "苹果")) <= 16
+        bm25.optimize()
+        assert self.block_sizes(bm25, "苹果") == [16] * 5
+
+    def test_save_load(self, docs: list[str], tmp_path):
+        """block_size 随索引保存，加载后继续按原大小分块"""
+        bm25 = BM25(block_size=50)
+        bm25.fit(docs)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.approx_eq(bm25)
+        assert loaded.search("香蕉 西瓜", top_k=10) == bm25.search("香蕉 西瓜", top_k=10)
+        loaded.fit(docs)
+        assert self.block_sizes(loaded, "苹果") == [50] * 6
+
+    def test_invalid(self):
+        """block_size 必须为正数"""
+        with pytest.raises(ValueError, match="block_size"):
+            BM25(block_size=0)
+
+class TestBM25IdfLengthCoupling:
+    """idf 与长度惩罚耦合测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        filler = " ".join(["葡萄"] * 20)
+        # 苹果出现在所有文档中 (常见词)，榴莲只出现在一短一长两个文档中 (稀有词)
+        return ["苹果 榴莲", f"苹果 榴莲 {filler}"] + ["苹果 西瓜"] * 18
+
+    @staticmethod
+    def length_ratio(bm25: BM25, term: str) -> float:
+        """长文档与短文档的分数比 (越接近 1 受长度惩罚越小)"""
+        scores = bm25.get_scores(term)
+        return scores[1] / scores[0]
+
+    def test_rare_terms_penalized_less(self, documents: list[str]):
+        """开启耦合后稀有词在长文档中的惩罚小于常见词"""
+        classic = BM25()
+        classic.fit(documents)
+        assert self.length_ratio(classic, "榴莲") == pytest.approx(
+            self.length_ratio(classic, "苹果")
+        )
+
+        coupled = BM25(idf_length_coupling=0.8)
+        coupled.fit(documents)
+        assert self.length_ratio(coupled, "榴莲") > self.length_ratio(coupled, "苹果")
+        assert self.length_ratio(coupled, "榴莲") > self.length_ratio(classic, "榴莲")
+
+    def test_search_consistent_with_scores(self, documents: list[str]):
+        """剪枝上界与打分一致，搜索结果与 get_scores 相符"""
+        bm25 = BM25(idf_length_coupling=0.5)
+        bm25.fit(documents)
+        scores = bm25.get_scores("榴莲 苹果")
+        for doc_id, score in bm25.search("榴莲 苹果", top_k=3):
+            assert score == pytest.approx(scores[doc_id])
+
+
+class TestBM25EnsembleSegmenters:
+    """集成分词 (多种切分结果取并集) 测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["深度学习框架", "自然语言处理", "Python 编程"]
+
+    def test_word_and_bigram_queries_match(self, documents: list[str]):
+        """词语查询与二元组查询都能命中"""
+        bm25 = BM25(segmenters=[Segmenter.Jieba, Segmenter.Bigram])
+        bm25.fit(documents)
+        assert bm25.search("深度学习")[0][0] == 0
+        assert bm25.search("度学")[0][0] == 0
+        assert bm25.search("语言处")[0][0] == 1
+        assert bm25.search("Python")[0][0] == 2
+
+        jieba_only = BM25()
+        jieba_only.fit(documents)
+        assert jieba_only.search("度学") == []
+
+    def test_bigram_only(self, documents: list[str], tmp_path):
+        """只使用二元组切分，配置随索引保存"""
+        bm25 = BM25(segmenters=[Segmenter.Bigram])
+        bm25.fit(documents)
+        assert {doc_id for doc_id, _ in bm25.search("学习")} == {0}
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("度学") == bm25.search("度学")
+
+    def test_default_unchanged(self, documents: list[str]):
+        """默认配置与显式仅使用 jieba 一致"""
+        default = BM25()
+        default.fit(documents)
+        explicit = BM25(segmenters=[Segmenter.Jieba])
+        explicit.fit(documents)
+        for query in ["深度学习", "自然语言", "Python 编程"]:
+            assert default.search(query) == explicit.search(query)
+
+    def test_whitespace_segmenter(self, tmp_path):
+        """空白分词: 英文语料按空格切分，不经过 jieba，配置随索引保存"""
+        documents = ["the quick brown fox", "a lazy dog", "quick thinking wins", "深度学习 框架"]
+        bm25 = BM25(segmenters=[Segmenter.Whitespace], lowercase=True)
+        bm25.fit(documents)
+        assert {doc_id for doc_id, _ in bm25.search("Quick")} == {0, 2}
+        assert [doc_id for doc_id, _ in bm25.search("lazy   dog")] == [1]
+        # 不做中文切分: 未以空格分开的中文整体成词
+        assert bm25.search("深度") == []
+        assert bm25.search("深度学习")[0][0] == 3
+        assert bm25.vocab_size == 11
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("quick fox") == bm25.search("quick fox")
+
+
+
+class TestBM25CutMode:
+    """jieba 分词模式测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["中华人民共和国成立", "人民 广场", "共和 制度"]
+
+    def test_search_mode_improves_recall(self, documents: list[str]):
+        """搜索引擎模式额外索引长词中的短词，精确模式下无法命中的子词可以召回"""
+        plain = BM25()
+        plain.fit(documents)
+        assert plain.search("共和国") == []
+
+        bm25 = BM25(cut_mode=CutMode.Search)
+        bm25.fit(documents)
+        assert bm25.analyze("中华人民共和国成立") == [
+            "中华", "华人", "人民", "共和", "共和国", "中华人民共和国", "成立"
+        ]
+        # 查询同样按搜索引擎模式切分为 "共和" 与 "共和国"
+        assert [doc_id for doc_id, _ in bm25.search("共和国")] == [0, 2]
+        assert {doc_id for doc_id, _ in bm25.search("人民")} == {0, 1}
+
+    def test_all_mode_and_save_load(self, documents: list[str], tmp_path):
+        """全模式输出所有可能成词的片段；模式随索引保存"""
+        bm25 = BM25(cut_mode=CutMode.All)
+        bm25.fit(documents)
+        tokens = bm25.analyze("中华人民共和国")
+        assert {"中华", "人民共和国", "共和国"} <= set(tokens)
+        assert {doc_id for doc_id, _ in bm25.search("共和")} == {0, 2}
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.analyze("中华人民共和国") == tokens
+        assert loaded.search("共和国") == bm25.search("共和国")
+
+
+
+class TestBM25UseHmm:
+    """jieba HMM 新词识别测试"""
+
+    def test_hmm_keeps_new_words(self, tmp_path):
+        """开启 HMM 时词典外的新词不再被切为单字，索引与查询使用相同切分"""
+        docs = ["他来到了网易杭研大厦", "杭州 研究院"]
+        plain = BM25()
+        plain.fit(docs)
+        assert plain.analyze("网易杭研大厦") == ["网易", "杭", "研", "大厦"]
+
+        bm25 = BM25(use_hmm=True)
+        bm25.fit(docs)
+        assert bm25.analyze("网易杭研大厦") == ["网易", "杭研", "大厦"]
+        assert bm25.analyze("杭研", query=True) == ["杭研"]
+        assert [doc_id for doc_id, _ in bm25.search("杭研")] == [0]
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.analyze("网易杭研大厦") == ["网易", "杭研", "大厦"]
+        assert loaded.search("杭研") == bm25.search("杭研")
+
+
+class TestBM25GlobalDocLengths:
+    """文档长度全局存储测试 (分数与参考公式一致)"""
+
+    WORDS = ["苹果", "香蕉", "葡萄", "西瓜", "草莓"]
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        # 超过一个 Block 的文档数，长度各不相同
+        return [" ".join(self.WORDS[: i % 5 + 1] * (i % 3 + 1)) for i in range(300)]
+
+    @staticmethod
+    def reference_scores(documents: list[str], term: str, k1=1.5, b=0.75) -> list[float]:
+        tokenized = [doc.split() for doc in documents]
+        avgdl = sum(len(tokens) for tokens in tokenized) / len(tokenized)
+        df = sum(1 for tokens in tokenized if term in tokens)
+        idf = math.log((len(tokenized) - df + 0.5) / (df + 0.5) + 1.0)
+        scores = []
+        for tokens in tokenized:
+            tf = tokens.count(term)
+            scores.append(idf * tf * (k1 + 1) / (tf + k1 * (1 - b + b * len(tokens) / avgdl)))
+        return scores
+
+    def test_scores_match_reference(self, documents: list[str]):
+        """各种检索路径的分数都与参考公式一致"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        expected = self.reference_scores(documents, "草莓")
+        assert bm25.get_scores("草莓") == pytest.approx(expected)
+        for doc_id, score in bm25.search("草莓", top_k=20):
+            assert score == pytest.approx(expected[doc_id])
+        for doc_id, score in bm25.search("草莓 西瓜", top_k=20, require_all_terms=True):
+            assert score == pytest.approx(bm25.get_scores("草莓 西瓜")[doc_id])
+
+    def test_update_and_reload(self, documents: list[str], tmp_path):
+        """更新文档后长度同步变化，保存加载后分数不变"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        documents[7] = "草莓 " * 40
+        assert bm25.update_document(7, documents[7])
+        expected = self.reference_scores(documents, "草莓")
+        assert bm25.get_scores("草莓") == pytest.approx(expected)
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("草莓", top_k=50) == bm25.search("草莓", top_k=50)
+
+
+class TestBM25SearchDebug:
+    """调试搜索 (返回命中词所在 Block) 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        # 苹果出现在全部文档中 (跨多个 Block)，香蕉只出现在部分文档中
+        documents = [
+            "苹果 香蕉" if i % 7 == 0 else "苹果 " * (i % 4 + 1) for i in range(400)
+        ]
+        model = BM25()
+        model.fit(documents)
+        return model
+
+    @staticmethod
+    def block_of(bm25: BM25, term: str, doc_id: int) -> int:
+        _, blocks = bm25.export_term(term)
+        for index, (_, _, postings) in enumerate(blocks):
+            if any(posting[0] == doc_id for posting in postings):
+                return index
+        raise AssertionError(f"{term} 不包含文档 {doc_id}")
+
+    @pytest.mark.parametrize("require_all_terms", [False, True])
+    def test_blocks_match_export(self, bm25: BM25, require_all_terms: bool):
+        """报告的 Block 下标与 export_term 中包含该 Posting 的块一致"""
+        results = bm25.search_debug("苹果 香蕉", top_k=20, require_all_terms=require_all_terms)
+        assert len(results) == 20
+        for doc_id, _, blocks in results:
+            assert [term for term, _ in blocks] == ["苹果", "香蕉"]
+            for term, block_index in blocks:
+                assert block_index == self.block_of(bm25, term, doc_id)
+        assert {index for _, _, blocks in results for _, index in blocks} != {0}
+
+    def test_single_term_and_scores(self, bm25: BM25):
+        """单词查询同样返回块信息，结果与普通搜索一致"""
+        results = bm25.search_debug("苹果", top_k=30)
+        assert [(doc_id, score) for doc_id, score, _ in results] == bm25.search("苹果", top_k=30)
+        for doc_id, _, blocks in results:
+            assert blocks == [("苹果", self.block_of(bm25, "苹果", doc_id))]
+
+        plain = bm25.search("苹果 香蕉", top_k=10)
+        debug = bm25.search_debug("苹果 香蕉", top_k=10)
+        assert [(doc_id, score) for doc_id, score, _ in debug] == plain
+        assert bm25.search_debug("榴莲") == []
+
+
+
+class TestBM25SearchWithMatches:
+    """返回命中查询词 (高亮) 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(lowercase=True)
+        model.fit(["苹果 香蕉 Python", "香蕉 西瓜", "苹果 苹果", "葡萄"], ids=[10, 20, 30, 40])
+        return model
+
+    def test_matched_terms_in_query_order(self, bm25: BM25):
+        """每个结果列出文档中出现的查询词，按查询顺序排列且去重"""
+        results = bm25.search_with_matches("香蕉 苹果 python 香蕉 榴莲", top_k=10)
+        assert [(doc_id, score) for doc_id, score, _ in results] == bm25.search(
+            "香蕉 苹果 python 香蕉 榴莲", top_k=10
+        )
+        matches = {doc_id: terms for doc_id, _, terms in results}
+        assert matches == {10: ["香蕉", "苹果", "python"], 20: ["香蕉"], 30: ["苹果"]}
+
+    def test_single_term_and_conjunctive(self, bm25: BM25):
+        """单词快速路径与合取查询同样返回命中词"""
+        assert bm25.search_with_matches("西瓜") == [(20, bm25.search("西瓜")[0][1], ["西瓜"])]
+        results = bm25.search_with_matches("苹果 香蕉", require_all_terms=True)
+        assert [(doc_id, terms) for doc_id, _, terms in results] == [(10, ["苹果", "香蕉"])]
+        assert bm25.search_with_matches("榴莲") == []
+
+
+
+class TestBM25SearchBoolean:
+    """布尔查询 (+必选 / -排除 / 可选) 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["苹果 香蕉 西瓜", "苹果 香蕉", "苹果 葡萄", "香蕉 葡萄", "西瓜"],
+            ids=[10, 20, 30, 40, 50],
+        )
+        return model
+
+    @staticmethod
+    def ids(results) -> set[int]:
+        return {doc_id for doc_id, _ in results}
+
+    def test_required_excluded_optional(self, bm25: BM25):
+        """必选词全部出现、排除词不出现，可选词只影响分数"""
+        assert self.ids(bm25.search_boolean("+苹果")) == {10, 20, 30}
+        assert self.ids(bm25.search_boolean("+苹果 +香蕉")) == {10, 20}
+        assert self.ids(bm25.search_boolean("+苹果 -葡萄")) == {10, 20}
+        assert self.ids(bm25.search_boolean("+苹果 -西瓜 -葡萄")) == {20}
+
+        results = bm25.search_boolean("+苹果 西瓜")
+        assert self.ids(results) == {10, 20, 30}
+        assert results[0][0] == 10
+        scores = dict(bm25.search("苹果 西瓜"))
+        for doc_id, score in results:
+            assert score == pytest.approx(scores[doc_id])
+
+    def test_optional_only(self, bm25: BM25):
+        """没有必选词时等同普通检索，排除词过滤结果"""
+        assert bm25.search_boolean("香蕉 西瓜") == bm25.search("香蕉 西瓜")
+        assert self.ids(bm25.search_boolean("香蕉 -苹果")) == {40}
+
+    def test_edge_cases(self, bm25: BM25):
+        """只有排除词、必选词不存在时没有结果；不存在的排除词不影响结果"""
+        assert bm25.search_boolean("-苹果") == []
+        assert bm25.search_boolean("+榴莲 苹果") == []
+        assert bm25.search_boolean("+苹果 -榴莲") == bm25.search_boolean("+苹果")
+        assert bm25.search_boolean("") == []
+        assert len(bm25.search_boolean("+苹果", top_k=2)) == 2
+
+
+class TestBM25UseIdf:
+    """关闭 idf 加权 (纯词频排序) 测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        # 苹果出现在所有文档中，榴莲只出现在一个文档中
+        return ["苹果 榴莲 香蕉", "苹果 葡萄", "苹果 西瓜", "苹果 草莓"]
+
+    def test_terms_contribute_equally(self, documents: list[str]):
+        """文档频率不同但词频相同的词贡献一致"""
+        bm25 = BM25(use_idf=False)
+        bm25.fit(documents)
+        assert bm25.get_scores("苹果")[0] == pytest.approx(bm25.get_scores("榴莲")[0])
+
+        classic = BM25()
+        classic.fit(documents)
+        assert classic.get_scores("榴莲")[0] > classic.get_scores("苹果")[0]
+
+    def test_search_and_persistence(self, documents: list[str], tmp_path):
+        """Top-K 搜索与全量打分一致，配置随索引保存"""
+        bm25 = BM25(use_idf=False)
+        bm25.fit(documents)
+        scores = bm25.get_scores("苹果 榴莲")
+        results = bm25.search("苹果 榴莲")
+        assert results[0][0] == 0
+        for doc_id, score in results:
+            assert score == pytest.approx(scores[doc_id])
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("苹果 榴莲") == results
+
+
+
+
+class TestBM25Bytes:
+    """字节串序列化测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(store_positions=True, stopwords=["的"])
+        model.fit(["苹果 的 香蕉", "西瓜 苹果 草莓", "香蕉 葡萄"], ids=[10, 20, 30])
+        return model
+
+    def test_roundtrip(self, bm25: BM25):
+        """from_bytes(to_bytes()) 与原索引等价"""
+        data = bm25.to_bytes()
+        assert isinstance(data, bytes)
+        loaded = BM25.from_bytes(data)
+        assert loaded.approx_eq(bm25)
+        assert loaded.search("苹果 香蕉") == bm25.search("苹果 香蕉")
+        assert loaded.analyze("苹果的香蕉") == bm25.analyze("苹果的香蕉")
+
+    def test_same_as_file(self, bm25: BM25, tmp_path):
+        """字节串与 save 的文件内容相同，可互相加载"""
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert path.read_bytes() == bm25.to_bytes()
+        assert BM25.from_bytes(path.read_bytes()).approx_eq(bm25)
+
+    def test_invalid_bytes(self, bm25: BM25):
+        """损坏或截断的数据报 IOError"""
+        with pytest.raises(IOError):
+            BM25.from_bytes(b"not an index")
+        with pytest.raises(IOError):
+            BM25.from_bytes(bm25.to_bytes()[:20])
+
+
+class TestBM25DocIdGaps:
+    """doc_id 差值编码测试"""
+
+    def test_gaps_shrink_postings(self):
+        """每个 Posting 的 doc_id 只占约 1 字节 (绝对 ID 超过 255 时需要 3 字节)"""
+        n = 3000
+        single = BM25()
+        single.fit(["苹果"] * n)
+        double = BM25()
+        double.fit(["苹果 香蕉"] * n)
+        # 多出的 "香蕉" 倒排列表: 每个 Posting 为 1 字节差值 + 1 字节词频，加上少量 Block 开销
+        assert len(double.to_bytes()) - len(single.to_bytes()) < 2.5 * n
+
+        loaded = BM25.from_bytes(double.to_bytes())
+        assert loaded.export_term("香蕉") == double.export_term("香蕉")
+        assert loaded.search("香蕉", top_k=5) == double.search("香蕉", top_k=5)
+
+    def test_absolute_ids_still_load(self, tmp_path):
+        """旧格式 (Block 中为绝对 doc_id) 的索引仍可加载"""
+        bm25 = BM25(block_size=4)
+        bm25.fit([f"苹果 文档{i % 3}" for i in range(30)])
+        path = tmp_path / "bm25.json"
+        bm25.save_json(str(path))
+        data = json.loads(path.read_text(encoding="utf-8"))
+        for _, list_data in data["index"].items():
+            for block in list_data["blocks"]:
+                gaps = block.pop("doc_gaps")
+                block["doc_ids"] = [sum(gaps[: i + 1]) for i in range(len(gaps))]
+        path.write_text(json.dumps(data), encoding="utf-8")
+
+        loaded = BM25.load_json(str(path))
+        assert loaded.approx_eq(bm25)
+        assert loaded.search("文档1", top_k=20) == bm25.search("文档1", top_k=20)
+
+class TestBM25FitFromFile:
+    """从文件流式训练测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["北京 天气 晴朗", "上海 天气 多云", "广州 下雨", "北京 烤鸭 美食"]
+
+    def test_matches_fit(self, documents: list[str], tmp_path):
+        """每行一个文档，结果与 fit 相同"""
+        path = tmp_path / "corpus.txt"
+        path.write_text("\n".join(documents) + "\n", encoding="utf-8")
+        expected = BM25()
+        expected.fit(documents)
+
+        bm25 = BM25()
+        bm25.fit_from_file(str(path))
+        assert bm25.corpus_size == 4
+        assert bm25.approx_eq(expected)
+        assert bm25.search("北京 天气") == expected.search("北京 天气")
+
+    def test_with_ids(self, documents: list[str], tmp_path):
+        """TSV 格式的 ID 与文本；格式错误时报错且索引不变"""
+        ids = [101, 205, 7, 42]
+        path = tmp_path / "corpus.tsv"
+        path.write_text("".join(f"{i}\t{doc}\n" for i, doc in zip(ids, documents)), encoding="utf-8")
+        expected = BM25()
+        expected.fit(documents, ids=ids)
+
+        bm25 = BM25()
+        bm25.fit_from_file_with_ids(str(path))
+        assert bm25.search("北京") == expected.search("北京")
+        assert bm25.approx_eq(expected)
+
+        bad = tmp_path / "bad.tsv"
+        bad.write_text("1\t北京\nabc\t上海\n", encoding="utf-8")
+        with pytest.raises(ValueError, match="line 2"):
+            bm25.fit_from_file_with_ids(str(bad))
+        assert bm25.search("北京") == expected.search("北京")
+
+        with pytest.raises(IOError):
+            bm25.fit_from_file(str(tmp_path / "missing.txt"))
+
+
+class TestBM25FitIter:
+    """从可迭代对象流式训练测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return [f"文档 {i} 关键词{i % 7} 主题{i % 3}" for i in range(5000)]
+
+    def test_matches_fit(self, documents: list[str]):
+        """生成器跨越多个批次，结果与 fit 相同"""
+        expected = BM25(store_positions=True)
+        expected.fit(documents, ids=[i * 2 for i in range(len(documents))])
+
+        bm25 = BM25(store_positions=True)
+        bm25.fit_iter((doc for doc in documents), ids=[i * 2 for i in range(len(documents))])
+        assert bm25.approx_eq(expected)
+        assert bm25.search("关键词3 主题1") == expected.search("关键词3 主题1")
+
+        plain = BM25()
+        plain.fit_iter(iter(["北京 天气", "上海 天气"]))
+        assert [doc_id for doc_id, _ in plain.search("天气")] == [0, 1]
+
+    def test_errors_keep_index(self):
+        """ids 长度不符或元素不是字符串时报错，索引保持不变"""
+        bm25 = BM25()
+        bm25.fit(["北京 天气"])
+        with pytest.raises(ValueError, match="same length"):
+            bm25.fit_iter(iter(["上海", "广州"]), ids=[1])
+        with pytest.raises(ValueError, match="same length"):
+            bm25.fit_iter(iter(["上海"]), ids=[1, 2])
+        with pytest.raises(TypeError):
+            bm25.fit_iter(iter(["上海", 42]))
+        with pytest.raises(TypeError):
+            bm25.fit_iter(42)
+        assert bm25.search("北京")[0][0] == 0
+
+
+class TestBM25SaveJson:
+    """JSON 格式保存与加载测试"""
+
+    QUERIES = ["苹果", "香蕉 葡萄", "西瓜 草莓 苹果"]
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return [f"苹果 {'香蕉' if i % 2 else '西瓜'} 文档{i % 5} 葡萄{i % 3}" for i in range(200)]
+
+    @pytest.mark.parametrize(
+        "options",
+        [{}, {"f32_scores": True, "store_positions": True}, {"lowercase": True, "block_size": 16}],
+    )
+    def test_roundtrip(self, documents: list[str], options: dict, tmp_path):
+        """加载后与原索引等价，检索结果逐位一致"""
+        bm25 = BM25(**options)
+        bm25.fit(documents, metadata=[{"n": str(i)} for i in range(len(documents))])
+        path = str(tmp_path / "bm25.json")
+        bm25.save_json(path)
+        loaded = BM25.load_json(path)
+        assert loaded.approx_eq(bm25, tolerance=0.0)
+        for query in self.QUERIES:
+            assert loaded.search(query, top_k=20) == bm25.search(query, top_k=20)
+            assert loaded.get_scores(query) == bm25.get_scores(query)
+        assert loaded.get_metadata(7) == {"n": "7"}
+
+    def test_readable_json(self, documents: list[str], tmp_path):
+        """文件为普通 JSON，可用标准库读取；格式错误时报 IOError"""
+        bm25 = BM25()
+        bm25.fit(documents[:10])
+        path = tmp_path / "bm25.json"
+        bm25.save_json(str(path))
+        data = json.loads(path.read_text(encoding="utf-8"))
+        assert data["avgdl"] == bm25.avgdl
+        assert set(data["index"]) == set(bm25.export_vocab())
+
+        path.write_text("{not json", encoding="utf-8")
+        with pytest.raises(IOError):
+            BM25.load_json(str(path))
+
+
+class TestBM25FormatVersion:
+    """索引格式版本测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(["苹果 香蕉", "西瓜 苹果", "葡萄"])
+        return model
+
+    @staticmethod
+    def with_fields(data: bytes, count: int, tail: bytes) -> bytes:
+        """替换 MessagePack 数组长度并把末尾的 version 字段换成 tail"""
+        assert data[:3] == b"\xdc\x00\x26" and data[-1] == 2  # 38 个字段，version 在最后
+        return b"\xdc" + count.to_bytes(2, "big") + data[3:-1] + tail
+
+    def test_newer_version_rejected(self, bm25: BM25, tmp_path):
+        """更新版本写出的索引报 ValueError，包括无法按当前结构解析的新格式"""
+        message = "index version 99 incompatible with library version 2"
+        newer = self.with_fields(bm25.to_bytes(), 38, b"\x63")
+        with pytest.raises(ValueError, match=message):
+            BM25.from_bytes(newer)
+        path = tmp_path / "bm25.bin"
+        path.write_bytes(newer)
+        with pytest.raises(ValueError, match=message):
+            BM25.load(str(path))
+        with pytest.raises(ValueError, match=message):
+            BM25.load_lenient(str(path))
+
+        extended = self.with_fields(bm25.to_bytes(), 39, b"\x03\xc0")
+        with pytest.raises(ValueError, match="index version 3"):
+            BM25.from_bytes(extended)
+
+    def test_legacy_index_migrated(self, bm25: BM25):
+        """没有版本字段的旧索引正常加载，重新保存时写出当前版本"""
+        legacy = self.with_fields(bm25.to_bytes(), 37, b"")
+        loaded = BM25.from_bytes(legacy)
+        assert loaded.approx_eq(bm25)
+        assert loaded.to_bytes()[-1] == 2
+
+    def test_json_version(self, bm25: BM25, tmp_path):
+        """JSON 格式同样校验版本"""
+        path = tmp_path / "bm25.json"
+        bm25.save_json(str(path))
+        data = json.loads(path.read_text(encoding="utf-8"))
+        assert data["version"] == 2
+
+        data["version"] = 5
+        path.write_text(json.dumps(data), encoding="utf-8")
+        with pytest.raises(ValueError, match="index version 5"):
+            BM25.load_json(str(path))
+
+        del data["version"]
+        path.write_text(json.dumps(data), encoding="utf-8")
+        assert BM25.load_json(str(path)).approx_eq(bm25)
+
+class TestBM25LoadLenient:
+    """从部分损坏的索引文件恢复测试"""
+
+    QUERIES = ["苹果", "香蕉 葡萄", "西瓜 草莓", "芒果"]
+
+    @pytest.fixture
+    def saved(self, tmp_path) -> tuple[BM25, bytes, str]:
+        bm25 = BM25(store_positions=True)
+        bm25.fit(
+            [
+                "苹果 香蕉 葡萄",
+                "西瓜 榴莲 草莓",
+                "芒果 苹果 榴莲 榴莲",
+                "香蕉 西瓜 葡萄 草莓",
+            ]
+        )
+        path = str(tmp_path / "bm25.bin")
+        bm25.save(path)
+        with open(path, "rb") as f:
+            return bm25, f.read(), path
+
+    @staticmethod
+    def corrupt(data: bytes, path: str, offset: int, length: int = 1):
+        damaged = bytearray(data)
+        damaged[offset : offset + length] = b"\xc1" * length  # MessagePack 中未使用的标记
+        with open(path, "wb") as f:
+            f.write(bytes(damaged))
+
+    @staticmethod
+    def key_offset(data: bytes, term: str) -> int:
+        encoded = term.encode("utf-8")
+        offset = data.find(bytes([0xA0 | len(encoded)]) + encoded)
+        assert offset >= 0
+        return offset
+
+    def test_intact_file(self, saved):
+        """文件完好时与 load 结果一致"""
+        bm25, _, path = saved
+        loaded, report = BM25.load_lenient(path)
+        assert report.dropped_terms == [] and report.unreadable_terms == 0
+        assert report.recovered_terms > 0
+        for query in self.QUERIES:
+            assert loaded.search(query) == bm25.search(query)
+
+    def test_damaged_postings(self, saved):
+        """倒排列表损坏的词被丢弃，其余词与文档完整恢复"""
+        bm25, data, path = saved
+        offset = self.key_offset(data, "榴莲") + len("榴莲".encode("utf-8")) + 1
+        self.corrupt(data, path, offset, length=3)
+        with pytest.raises(IOError):
+            BM25.load(path)
+
+        loaded, report = BM25.load_lenient(path)
+        assert report.dropped_terms == ["榴莲"]
+        assert report.unreadable_terms == 0
+        assert loaded.search("榴莲") == []
+        for query in self.QUERIES:
+            assert loaded.search(query) == bm25.search(query)
+        assert loaded.search("香蕉 葡萄", phrase_boost=1.0) == bm25.search("香蕉 葡萄", phrase_boost=1.0)
+
+    def test_damaged_term(self, saved):
+        """词本身损坏时计入 unreadable_terms"""
+        bm25, data, path = saved
+        self.corrupt(data, path, self.key_offset(data, "芒果"))
+        loaded, report = BM25.load_lenient(path)
+        assert report.dropped_terms == []
+        assert report.unreadable_terms == 1
+        assert loaded.search("芒果") == []
+        assert loaded.search("苹果 西瓜") == bm25.search("苹果 西瓜")
+
+    def test_unrecoverable(self, saved):
+        """文档表损坏 (如文件被截断) 时仍然报错"""
+        _, data, path = saved
+        with open(path, "wb") as f:
+            f.write(data[: len(data) // 2])
+        with pytest.raises(IOError, match="too damaged"):
+            BM25.load_lenient(path)
+
+
+class TestBM25MinCoverage:
+    """查询 idf 覆盖率阈值测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        # 苹果几乎出现在所有文档中 (低 idf)，榴莲与芒果较少见
+        model = BM25()
+        model.fit(
+            [
+                "苹果 榴莲 芒果",
+                "苹果 香蕉",
+                "苹果 葡萄",
+                "苹果 榴莲",
+                "苹果 西瓜 苹果",
+            ]
+        )
+        return model
+
+    @staticmethod
+    def ids(results) -> set[int]:
+        return {doc_id for doc_id, _ in results}
+
+    def test_filters_trivial_matches(self, bm25: BM25):
+        """只命中低 idf 词的文档被过滤"""
+        assert self.ids(bm25.search("苹果 榴莲")) == {0, 1, 2, 3, 4}
+        assert self.ids(bm25.search("苹果 榴莲", min_coverage=0.8)) == {0, 3}
+        assert self.ids(bm25.search("苹果 榴莲 芒果", min_coverage=0.9)) == {0}
+        assert bm25.search("苹果 榴莲", min_coverage=0.0) == bm25.search("苹果 榴莲")
+
+    def test_scores_unchanged(self, bm25: BM25):
+        """过滤不改变保留文档的分数"""
+        plain = dict(bm25.search("苹果 榴莲"))
+        for doc_id, score in bm25.search("苹果 榴莲", min_coverage=0.8):
+            assert score == plain[doc_id]
+
+    def test_single_term_and_validation(self, bm25: BM25):
+        """单词查询覆盖率恒为 1.0；阈值需在 0~1 之间"""
+        assert bm25.search("苹果", min_coverage=1.0) == bm25.search("苹果")
+        assert self.ids(bm25.search("苹果 榴莲", min_coverage=1.0)) == {0, 3}
+        with pytest.raises(ValueError, match="min_coverage"):
+            bm25.search("苹果", min_coverage=1.5)
+
+
+
+class TestBM25MinScore:
+    """最低分数阈值测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(store_positions=True)
+        model.fit(["苹果 榴莲 芒果", "苹果 香蕉", "苹果 葡萄", "苹果 榴莲", "苹果 西瓜 苹果"])
+        return model
+
+    def test_drops_weak_matches(self, bm25: BM25):
+        """低于阈值的结果被丢弃，即使少于 top_k 个；保留结果的分数与顺序不变"""
+        plain = bm25.search("苹果 榴莲", top_k=5)
+        threshold = (plain[1][1] + plain[2][1]) / 2
+        assert bm25.search("苹果 榴莲", top_k=5, min_score=threshold) == plain[:2]
+        assert bm25.search("苹果 榴莲", top_k=5, min_score=plain[1][1]) == plain[:2]
+        assert bm25.search("苹果 榴莲", min_score=plain[0][1] + 1.0) == []
+        assert bm25.search("苹果 榴莲", top_k=5, min_score=0.0) == plain
+
+    def test_applies_to_final_score(self, bm25: BM25):
+        """阈值作用于包含短语加分的最终分数，并与 sort_by 组合"""
+        plain = dict(bm25.search("苹果 榴莲", top_k=5))
+        boosted = bm25.search("苹果 榴莲", top_k=5, phrase_boost=10.0, min_score=10.0)
+        assert {doc_id for doc_id, _ in boosted} == {0, 3}
+        for doc_id, score in boosted:
+            assert score == pytest.approx(plain[doc_id] + 10.0)
+
+        results = bm25.search("苹果", top_k=5, min_score=0.0, sort_by=SortOrder.DescId)
+        assert [doc_id for doc_id, _ in results] == [4, 3, 2, 1, 0]
+        with pytest.raises(ValueError, match="min_score"):
+            bm25.search("苹果", min_score=float("nan"))
+
+
+class TestBM25Pagination:
+    """分页偏移测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit([" ".join(["苹果"] * (i % 7 + 1) + ["文档"] * (i % 5 + 1)) for i in range(60)])
+        return model
+
+    def test_pages_match_full_ranking(self, bm25: BM25):
+        """各页拼接起来与一次取全部结果相同"""
+        full = bm25.search("苹果 文档", top_k=60)
+        pages = [bm25.search("苹果 文档", top_k=7, offset=offset) for offset in range(0, 60, 7)]
+        assert [hit for page in pages for hit in page] == full
+        assert bm25.search("苹果 文档", top_k=5, offset=0) == full[:5]
+        assert bm25.search("苹果 文档", offset=10) == full[10:20]
+
+    def test_offset_beyond_matches(self, bm25: BM25):
+        """偏移超出命中数时返回空列表；最后一页可能不满"""
+        assert bm25.search("苹果", top_k=10, offset=60) == []
+        assert bm25.search("苹果", top_k=10, offset=2**62) == []
+        assert len(bm25.search("苹果", top_k=10, offset=55)) == 5
+        assert bm25.search("苹果", top_k=0, offset=5) == []
+
+    def test_with_sort_and_min_score(self, bm25: BM25):
+        """offset 按相关性分页，之后再按 sort_by 排序；与 min_score 组合"""
+        full = bm25.search("苹果 文档", top_k=60)
+        page = bm25.search("苹果 文档", top_k=5, offset=5, sort_by=SortOrder.AscId)
+        assert page == sorted(full[5:10])
+
+        threshold = full[12][1]
+        assert bm25.search("苹果 文档", top_k=10, offset=10, min_score=threshold) == [
+            hit for hit in full[10:20] if hit[1] >= threshold
+        ]
+
+
+class TestBM25ExcludeTerms:
+    """排除词过滤测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(lowercase=True)
+        model.fit(
+            ["手机 优惠 促销", "手机 评测", "手机 促销 广告", "手机 参数 对比", "电脑 促销"],
+            ids=[10, 20, 30, 40, 50],
+        )
+        return model
+
+    def test_excludes_documents(self, bm25: BM25):
+        """包含任一排除词的文档被跳过，其余文档的分数与排序不变"""
+        full = bm25.search("手机 促销", top_k=10)
+        results = bm25.search("手机 促销", top_k=10, exclude_terms=["广告", "优惠"])
+        assert results == [hit for hit in full if hit[0] not in (10, 30)]
+        assert bm25.search("手机", top_k=2, exclude_terms=["促销"]) == [
+            hit for hit in bm25.search("手机", top_k=10) if hit[0] not in (10, 30)
+        ][:2]
+
+    def test_missing_and_case(self, bm25: BM25):
+        """排除词按索引规则规范化；不在索引中的排除词不影响结果"""
+        assert bm25.search("手机", exclude_terms=["榴莲"]) == bm25.search("手机")
+        assert bm25.search("手机", exclude_terms=[]) == bm25.search("手机")
+        bm25.update_document(20, "手机 SPAM")
+        assert 20 not in {doc_id for doc_id, _ in bm25.search("手机", exclude_terms=["spam"])}
+
+class TestBM25Icf:
+    """逆集合频率 (ICF) 权重测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        # 苹果只出现在一个文档中但重复多次 (df=1, cf=6)；香蕉出现在三个文档中各一次 (df=3, cf=3)
+        return [
+            "苹果 苹果 苹果 苹果 苹果 苹果 香蕉",
+            "香蕉 葡萄",
+            "香蕉 西瓜",
+            "草莓 西瓜",
+            "葡萄 草莓",
+        ]
+
+    @staticmethod
+    def weight(bm25: BM25, term: str, doc_id: int) -> float:
+        # k1=0 时单个词的分数即为其权重
+        return bm25.get_scores(term)[doc_id]
+
+    def test_rarity_differs_from_idf(self, documents: list[str]):
+        """文档频率与集合频率不一致时两种方案给出相反的稀有度"""
+        idf = BM25(k1=0.0, b=0.0)
+        idf.fit(documents)
+        assert self.weight(idf, "苹果", 0) > self.weight(idf, "香蕉", 0)
+
+        icf = BM25(k1=0.0, b=0.0, weighting=Weighting.Icf)
+        icf.fit(documents)
+        assert self.weight(icf, "苹果", 0) == pytest.approx(math.log(15 / 6))
+        assert self.weight(icf, "香蕉", 0) == pytest.approx(math.log(15 / 3))
+        assert self.weight(icf, "香蕉", 0) > self.weight(icf, "苹果", 0)
+        assert icf.search("苹果 葡萄")[0][0] == 1
+
+    def test_update_and_persistence(self, documents: list[str], tmp_path):
+        """更新文档后集合频率同步变化，配置随索引保存"""
+        bm25 = BM25(k1=0.0, b=0.0, weighting=Weighting.Icf)
+        bm25.fit(documents)
+        assert bm25.update_document(0, "香蕉")
+        assert self.weight(bm25, "香蕉", 0) == pytest.approx(math.log(9 / 3))
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("香蕉 西瓜") == bm25.search("香蕉 西瓜")
+
+
+class TestBM25DocumentVector:
+    """文档稀疏词向量测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["苹果 香蕉 苹果", "香蕉 葡萄", "西瓜 苹果 草莓 芒果", "葡萄"]
+
+    def test_weights_match_reference(self, documents: list[str]):
+        """权重与按公式计算的单词 BM25 分数一致，按权重降序排列"""
+        bm25 = BM25()
+        bm25.fit(documents, ids=[10, 20, 30, 40])
+        vector = bm25.document_vector(10)
+        assert {term for term, _ in vector} == {"苹果", "香蕉"}
+        weights = [weight for _, weight in vector]
+        assert weights == sorted(weights, reverse=True)
+
+        tokenized = [doc.split() for doc in documents]
+        avgdl = sum(map(len, tokenized)) / len(tokenized)
+        for term, weight in vector:
+            df = sum(1 for tokens in tokenized if term in tokens)
+            idf = math.log((4 - df + 0.5) / (df + 0.5) + 1.0)
+            tf = tokenized[0].count(term)
+            expected = idf * tf * 2.5 / (tf + 1.5 * (0.25 + 0.75 * 3 / avgdl))
+            assert weight == pytest.approx(expected)
+            assert weight == pytest.approx(bm25.get_scores(term)[0])
+
+    def test_missing_and_updated(self, documents: list[str]):
+        """文档不存在时返回 None；更新后反映新内容"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        assert bm25.document_vector(99) is None
+        assert [term for term, _ in bm25.document_vector(3)] == ["葡萄"]
+        assert bm25.update_document(3, "榴莲 西瓜")
+        assert {term for term, _ in bm25.document_vector(3)} == {"榴莲", "西瓜"}
+
+    def test_top_terms(self):
+        """文档关键词: 最高权重的词即 idf × tf 最大的词，结果为 document_vector 的前 n 项"""
+        documents = ["苹果 苹果 苹果 香蕉 西瓜 葡萄", "香蕉 西瓜", "香蕉 葡萄", "香蕉"]
+        bm25 = BM25()
+        bm25.fit(documents)
+
+        tokenized = [doc.split() for doc in documents]
+
+        def idf_tf(term: str) -> float:
+            df = sum(1 for tokens in tokenized if term in tokens)
+            return math.log((4 - df + 0.5) / (df + 0.5) + 1.0) * tokenized[0].count(term)
+        top_term = max(set(tokenized[0]), key=idf_tf)
+        assert top_term == "苹果"
+
+        top = bm25.document_top_terms(0, 2)
+        assert top[0][0] == top_term
+        assert top == bm25.document_vector(0)[:2]
+        assert bm25.document_top_terms(0, 10) == bm25.document_vector(0)
+        assert bm25.document_top_terms(0, 0) == []
+        assert bm25.document_top_terms(99, 3) == []
+
+    def test_extract_keywords(self):
+        """TF-IDF 关键词: 权重为词频 × idf，不做词频饱和"""
+        documents = ["苹果 苹果 苹果 香蕉 西瓜 葡萄", "香蕉 西瓜", "香蕉 葡萄", "香蕉"]
+        bm25 = BM25()
+        bm25.fit(documents, ids=[5, 6, 7, 8])
+
+        tokenized = [doc.split() for doc in documents]
+        keywords = bm25.extract_keywords(5, 10)
+        assert [term for term, _ in keywords][0] == "苹果"
+        assert len(keywords) == 4
+        for term, weight in keywords:
+            df = sum(1 for tokens in tokenized if term in tokens)
+            idf = math.log((4 - df + 0.5) / (df + 0.5) + 1.0)
+            assert weight == pytest.approx(tokenized[0].count(term) * idf)
+        assert bm25.extract_keywords(5, 2) == keywords[:2]
+        assert bm25.extract_keywords(5, 0) == []
+        assert bm25.extract_keywords(99, 3) == []
+
+
+
+class TestBM25Explain:
+    """单文档得分解释测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(stopwords=["的"], stopword_mode=StopwordMode.ZeroWeight)
+        model.fit(["苹果 香蕉 苹果 的", "香蕉 葡萄", "西瓜 苹果 草莓", "葡萄"], ids=[10, 20, 30, 40])
+        return model
+
+    def test_breakdown_matches_scores(self, bm25: BM25):
+        """各词分量之和等于 get_scores 中的分数，idf 与词频与倒排列表一致"""
+        explained = bm25.explain("香蕉 苹果 榴莲 的", 10)
+        assert [(term, tf) for term, _, _, tf in explained] == [("香蕉", 1), ("苹果", 2)]
+        assert sum(score for _, _, score, _ in explained) == pytest.approx(
+            bm25.get_scores("香蕉 苹果 榴莲 的")[0]
+        )
+        for term, idf, score, _ in explained:
+            df = bm25.doc_count(term)
+            assert idf == pytest.approx(math.log((4 - df + 0.5) / (df + 0.5) + 1.0))
+            assert score == pytest.approx(bm25.get_scores(term)[0])
+
+    def test_unmatched_and_missing(self, bm25: BM25):
+        """只返回命中该文档的词；文档不存在时返回空列表"""
+        assert [term for term, _, _, _ in bm25.explain("苹果 葡萄", 20)] == ["葡萄"]
+        assert bm25.explain("榴莲", 20) == []
+        assert bm25.explain("苹果", 99) == []
+
+class TestBM25SearchRerank:
+    """召回后重排序测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["苹果 苹果 苹果", "苹果 苹果 香蕉", "苹果 香蕉 葡萄", "西瓜"],
+            ids=[100, 200, 300, 400],
+        )
+        return model
+
+    def test_reverse_reranker(self, bm25: BM25):
+        """最终排序反映重排序函数的分数"""
+        calls = []
+
+        def reverse(query, candidate_ids):
+            calls.append((query, list(candidate_ids)))
+            return [float(i) for i in range(len(candidate_ids))]
+
+        retrieved = [doc_id for doc_id, _ in bm25.search("苹果")]
+        results = bm25.search_rerank("苹果", reverse, retrieve_k=10, top_k=2)
+        assert calls == [("苹果", retrieved)]
+        assert results == [(retrieved[-1], 2.0), (retrieved[-2], 1.0)]
+
+    def test_retrieve_k_and_errors(self, bm25: BM25):
+        """只将 retrieve_k 个候选交给重排序；返回长度不符时报错"""
+        results = bm25.search_rerank("苹果", lambda q, ids: [1.0] * len(ids), retrieve_k=2)
+        assert results == [(doc_id, 1.0) for doc_id, _ in bm25.search("苹果", top_k=2)]
+        assert bm25.search_rerank("榴莲", lambda q, ids: []) == []
+        with pytest.raises(ValueError, match="one score per candidate"):
+            bm25.search_rerank("苹果", lambda q, ids: [1.0])
+
+
+
+class TestBM25SearchDedup:
+    """近似重复结果折叠测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            [
+                "苹果 香蕉 葡萄 西瓜 草莓",
+                "苹果 香蕉 葡萄 西瓜 草莓 芒果",  # 与 0 近似重复 (Jaccard 5/6)
+                "苹果 榴莲",
+                "苹果 柠檬 菠萝",
+            ],
+            ids=[10, 11, 12, 13],
+        )
+        return model
+
+    def test_collapses_near_duplicates(self, bm25: BM25):
+        """近似重复的两个文档只保留得分较高的一个，空位由后续候选补上"""
+        plain = bm25.search("苹果 香蕉", top_k=2)
+        assert {doc_id for doc_id, _ in plain} == {10, 11}
+
+        results = bm25.search_dedup("苹果 香蕉", top_k=2)
+        assert results[0] == plain[0]
+        assert len(results) == 2
+        assert results[1][0] in {12, 13}
+        assert results[1] in bm25.search("苹果 香蕉", top_k=4)
+
+    def test_threshold_and_errors(self, bm25: BM25):
+        """阈值高于相似度时不折叠；阈值越界报错"""
+        assert bm25.search_dedup("苹果 香蕉", top_k=4, similarity_threshold=0.9) == bm25.search(
+            "苹果 香蕉", top_k=4
+        )
+        assert [d for d, _ in bm25.search_dedup("苹果", top_k=4)] == [
+            d for d, _ in bm25.search("苹果", top_k=4) if d != 11
+        ]
+        assert bm25.search_dedup("榴莲 草莓", top_k=1, retrieve_k=1) == bm25.search("榴莲 草莓", top_k=1)
+        assert bm25.search_dedup("没有") == []
+        for threshold in (0.0, 1.5):
+            with pytest.raises(ValueError, match="similarity_threshold"):
+                bm25.search_dedup("苹果", similarity_threshold=threshold)
+
+    def test_huge_top_k(self, bm25: BM25):
+        """top_k 极大时候选数不溢出，返回全部去重后的命中文档"""
+        expected = bm25.search_dedup("苹果", top_k=4)
+        assert bm25.search_dedup("苹果", top_k=2**64 - 1) == expected
+        assert bm25.search_dedup("苹果", top_k=10**12) == expected
+
+
+class TestBM25Chunking:
+    """长文档分块索引测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        long_doc = " ".join(["苹果 香蕉 葡萄 西瓜 草莓"] * 4) + " 榴莲"  # 21 个词
+        return [long_doc, "芒果 苹果", ""]
+
+    def test_chunks_share_external_id(self, documents: list[str]):
+        """长文档切分为多个分块，全部映射到同一外部 ID"""
+        bm25 = BM25()
+        bm25.fit(documents, ids=[7, 8, 9], metadata=[{"n": "a"}, {"n": "b"}, {}], chunk_size=5)
+        assert bm25.get_chunks(7) == [0, 1, 2, 3, 4]
+        assert bm25.get_chunks(8) == [5]
+        assert bm25.get_chunks(9) == [6]  # 空文档保留为一个空分块
+
+        # 只有最后一个分块包含榴莲
+        assert [doc_id for doc_id, _ in bm25.search("榴莲")] == [7]
+        assert bm25.export_term("榴莲")[1][0][2][0][0] == 4
+        assert [doc_id for doc_id, _ in bm25.search("苹果", top_k=10)].count(7) == 4
+        assert bm25.get_metadata(7) == {"n": "a"}
+
+    def test_without_chunking_and_errors(self, documents: list[str]):
+        """不分块时行为不变；分块索引不支持 update_document"""
+        plain = BM25()
+        plain.fit(documents)
+        assert plain.get_chunks(0) == [0]
+
+        chunked = BM25()
+        chunked.fit(documents, chunk_size=100)
+        assert chunked.search("苹果 榴莲") == plain.search("苹果 榴莲")
+        with pytest.raises(ValueError, match="chunked"):
+            chunked.update_document(0, "苹果")
+        with pytest.raises(ValueError, match="chunk_size"):
+            BM25().fit(documents, chunk_size=0)
+
+        # 重新 fit 后恢复为普通索引
+        chunked.fit(documents)
+        assert chunked.update_document(0, "苹果")
+
+
+class TestBM25ApproxEq:
+    """索引等价比较测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["苹果 香蕉 葡萄", "西瓜 苹果", "草莓 芒果 苹果 苹果", "榴莲"]
+
+    @staticmethod
+    def build(documents: list[str], **kwargs) -> BM25:
+        bm25 = BM25(**kwargs)
+        bm25.fit(documents)
+        return bm25
+
+    def test_equivalent_indices(self, documents: list[str], tmp_path):
+        """相同构建、保存加载与更新后还原的索引等价"""
+        bm25 = self.build(documents)
+        assert bm25.approx_eq(bm25)
+        assert bm25.approx_eq(self.build(documents))
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).approx_eq(bm25)
+
+        updated = self.build(documents)
+        assert updated.update_document(1, "榴莲 榴莲")
+        assert not updated.approx_eq(bm25)
+        assert updated.update_document(1, documents[1])
+        assert updated.approx_eq(bm25)
+
+    def test_differing_indices(self, documents: list[str]):
+        """参数、文档或词表不同时不等价"""
+        bm25 = self.build(documents)
+        assert not bm25.approx_eq(self.build(documents, k1=1.2))
+        assert not bm25.approx_eq(self.build(documents, store_positions=True))
+        assert not bm25.approx_eq(self.build(documents[:3]))
+        assert not bm25.approx_eq(self.build(documents[:3] + ["榴莲 榴莲"]))
+        assert bm25.approx_eq(self.build(documents, k1=1.5 + 1e-12))
+        assert not bm25.approx_eq(self.build(documents, k1=1.5 + 1e-6))
+        assert bm25.approx_eq(self.build(documents, k1=1.5 + 1e-6), tolerance=1e-3)
+
+
+class TestBM25QueryLowercase:
+    """查询侧单独的小写设置测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        # 语料已预先转为小写
+        return ["python 编程 入门", "rust 系统 编程", "java 企业 开发"]
+
+    def test_query_side_lowercase(self, documents: list[str]):
+        """索引不做小写、查询侧小写时，大小写混合的原始查询仍能匹配"""
+        raw = BM25()
+        raw.fit(documents)
+        assert raw.search("Python") == []
+
+        bm25 = BM25(query_lowercase=True)
+        bm25.fit(documents)
+        assert bm25.search("Python")[0][0] == 0
+        assert bm25.search("RUST 编程") == raw.search("rust 编程")
+        assert bm25.export_term("PYTHON") == raw.export_term("python")
+
+    def test_index_side_unchanged(self, tmp_path):
+        """查询侧设置不影响索引与文档更新；默认与 lowercase 一致"""
+        bm25 = BM25(query_lowercase=True)
+        bm25.fit(["Python 编程"])
+        assert bm25.search("python") == []
+        assert bm25.update_document(0, "python 编程")
+        assert bm25.search("PYTHON")[0][0] == 0
+
+        lowered = BM25(lowercase=True)
+        lowered.fit(["Python 编程"])
+        assert lowered.search("PYTHON")[0][0] == 0
+        strict = BM25(lowercase=True, query_lowercase=False)
+        strict.fit(["Python 编程"])
+        assert strict.search("PYTHON") == []
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        assert BM25.load(str(path)).search("PYTHON") == bm25.search("PYTHON")
+
+
+
+class TestBM25CharFallback:
+    """未登录查询词的单字兜底测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["西 林 医院", "阿 莫 诊所", "其他 文档"]
+
+    def test_oov_word_matches_characters(self, documents: list[str]):
+        """不在词表中的多字词拆分为单字，按 OR 召回包含这些字的文档"""
+        plain = BM25()
+        plain.fit(documents)
+        assert plain.search("阿莫西林") == []
+
+        bm25 = BM25(char_fallback=True)
+        bm25.fit(documents)
+        assert bm25.analyze("阿莫西林", query=True) == ["阿", "莫", "西", "林"]
+        assert {doc_id for doc_id, _ in bm25.search("阿莫西林")} == {0, 1}
+        assert bm25.search("阿莫西林") == bm25.search("阿 莫 西 林")
+
+    def test_known_words_unchanged(self, documents: list[str], tmp_path):
+        """词表中已有的词不拆分；设置随索引保存"""
+        bm25 = BM25(char_fallback=True)
+        bm25.fit(documents + ["阿莫西林 胶囊"])
+        assert bm25.analyze("阿莫西林", query=True) == ["阿莫西林"]
+        assert [doc_id for doc_id, _ in bm25.search("阿莫西林")] == [3]
+        assert bm25.analyze("莫西林", query=True) == ["莫", "西", "林"]
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("莫西林") == bm25.search("莫西林")
+
+
+class TestBM25LengthPrior:
+    """文档长度先验测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        # b=0 时文档长度不影响 BM25，两个文档对苹果的分数相同
+        model = BM25(b=0.0)
+        model.fit(["苹果 香蕉", "苹果 西瓜 葡萄 草莓", "榴莲"])
+        return model
+
+    def test_longer_document_outranks(self, bm25: BM25):
+        """分数相同时，开启长度先验后较长的文档排在前面"""
+        plain = bm25.search("苹果")
+        assert [doc_id for doc_id, _ in plain] == [0, 1]
+        assert plain[0][1] == plain[1][1]
+
+        results = bm25.search("苹果", length_prior=0.1)
+        assert [doc_id for doc_id, _ in results] == [1, 0]
+        assert results[0][1] == pytest.approx(plain[0][1] + 0.1 * math.log(5))
+        assert results[1][1] == pytest.approx(plain[0][1] + 0.1 * math.log(3))
+
+    def test_multi_term_and_validation(self, bm25: BM25):
+        """多词查询同样生效；未命中的文档不会因先验进入结果"""
+        results = bm25.search("苹果 香蕉", length_prior=1.0)
+        assert {doc_id for doc_id, _ in results} == {0, 1}
+        assert bm25.search("苹果", length_prior=0.0) == bm25.search("苹果")
+        with pytest.raises(ValueError, match="length_prior"):
+            bm25.search("苹果", length_prior=float("nan"))
+
+
+class TestBM25BlockPrefetch:
+    """Block 预取测试"""
+
+    QUERIES = ["苹果", "苹果 香蕉", "葡萄 西瓜 草莓", "榴莲 芒果"]
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "榴莲", "芒果"]
+        model = BM25()
+        # 每个词的倒排列表跨越多个 Block
+        model.fit([" ".join(words[i % 7 : i % 7 + i % 3 + 1]) for i in range(3000)])
+        return model
+
+    def test_results_unchanged(self, bm25: BM25):
+        """开启预取后搜索结果 (含合取查询与统计) 完全不变"""
+        expected = [
+            (bm25.search(q, top_k=20), bm25.search(q, require_all_terms=True), bm25.search_with_stats(q, top_k=20))
+            for q in self.QUERIES
+        ]
+        assert get_block_prefetch() is False
+        try:
+            set_block_prefetch(True)
+            assert get_block_prefetch() is True
+            actual = [
+                (bm25.search(q, top_k=20), bm25.search(q, require_all_terms=True), bm25.search_with_stats(q, top_k=20))
+                for q in self.QUERIES
+            ]
+        finally:
+            set_block_prefetch(False)
+        for (results, conj, (stat_results, stats)), (exp_results, exp_conj, (exp_stat_results, exp_stats)) in zip(
+            actual, expected
+        ):
+            assert results == exp_results
+            assert conj == exp_conj
+            assert stat_results == exp_stat_results
+            assert stats.blocks_scanned == exp_stats.blocks_scanned
+
+
+class TestBM25IdfOverrides:
+    """外部 idf 注入测试"""
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        return ["苹果 香蕉", "苹果 葡萄", "西瓜 香蕉 草莓", "芒果"]
+
+    def test_override_replaces_idf(self, documents: list[str]):
+        """被覆盖的词按注入的 idf 打分，未覆盖的词不受影响"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        before_apple = bm25.get_scores("苹果")
+        before_banana = bm25.get_scores("香蕉")
+        df = 2
+        local_idf = math.log((4 - df + 0.5) / (df + 0.5) + 1.0)
+
+        bm25.set_idf_overrides({"苹果": 3.0})
+        after_apple = bm25.get_scores("苹果")
+        for before, after in zip(before_apple, after_apple):
+            assert after == pytest.approx(before * 3.0 / local_idf)
+        assert bm25.get_scores("香蕉") == before_banana
+
+        # 剪枝上界同步更新，Top-K 结果与全量打分一致
+        results = bm25.search("苹果 香蕉", top_k=2)
+        scores = bm25.get_scores("苹果 香蕉")
+        expected = sorted(range(4), key=lambda i: -scores[i])[:2]
+        assert [doc_id for doc_id, _ in results] == expected
+        assert results[0][1] == pytest.approx(scores[expected[0]])
+
+        bm25.set_idf_overrides({})
+        assert bm25.get_scores("苹果") == pytest.approx(before_apple)
+
+    def test_persist_and_validate(self, documents: list[str], tmp_path):
+        """外部 idf 随索引保存；非法值报错"""
+        bm25 = BM25()
+        bm25.fit(documents)
+        bm25.set_idf_overrides({"芒果": 0.5})
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.get_scores("芒果") == pytest.approx(bm25.get_scores("芒果"))
+        assert loaded.approx_eq(bm25)
+
+        with pytest.raises(ValueError, match="idf overrides"):
+            bm25.set_idf_overrides({"芒果": -1.0})
+        with pytest.raises(ValueError, match="idf overrides"):
+            bm25.set_idf_overrides({"芒果": float("nan")})
+
+
+class TestBM25Reader:
+    """只读检索结构测试"""
+
+    QUERIES = ["苹果", "苹果 榴莲", "香蕉 葡萄 西瓜", "芒果 草莓", "菠萝"]
+
+    @pytest.fixture
+    def documents(self) -> list[str]:
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "榴莲", "芒果"]
+        return [" ".join(words[i % 7 : i % 7 + 1 + i % 3] * (1 + i % 4)) for i in range(2000)]
+
+    @pytest.mark.parametrize("store_positions", [False, True])
+    def test_matches_bm25(self, documents: list[str], store_positions: bool):
+        """检索结果与转换前的 BM25 完全一致"""
+        bm25 = BM25(store_positions=store_positions)
+        bm25.fit(documents, ids=[i * 10 for i in range(2000)])
+        expected = [
+            (bm25.search(q, top_k=20), bm25.search(q, require_all_terms=True), bm25.get_scores(q))
+            for q in self.QUERIES
+        ]
+        reader = bm25.into_reader()
+        assert isinstance(reader, BM25Reader)
+        assert len(reader) == 2000
+        for q, (results, conj, scores) in zip(self.QUERIES, expected):
+            assert reader.search(q, top_k=20) == results
+            assert reader.search(q, require_all_terms=True) == conj
+            assert reader.get_scores(q) == scores
+
+    def test_uses_less_memory(self, documents: list[str]):
+        """释放预留容量并丢弃元数据后占用更少内存；原实例变为空索引"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(documents, metadata=[{"source": f"doc-{i}"} for i in range(2000)])
+        before = bm25.memory_usage()
+        reader = bm25.into_reader()
+        assert reader.memory_usage() < before * 0.9
+
+        plain = BM25()
+        plain.fit(documents)
+        plain_before = plain.memory_usage()
+        assert plain.into_reader().memory_usage() < plain_before
+
+        # 无元数据时丢弃词位置仍明显减少内存
+        positional = BM25(store_positions=True)
+        positional.fit(documents)
+        positional_before = positional.memory_usage()
+        assert positional.into_reader().memory_usage() < positional_before * 0.8
+
+        assert bm25.search("苹果") == []
+        bm25.fit(documents)
+        assert bm25.search("苹果 榴莲") == reader.search("苹果 榴莲")
+
+
+class TestBM25TermBoosts:
+    """词级权重测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        # 苹果与香蕉的文档频率、词频与文档长度完全对称
+        model.fit(["苹果 西瓜", "香蕉 西瓜", "苹果 葡萄", "香蕉 葡萄", "草莓"])
+        return model
+
+    def test_boosted_term_contributes_more(self, bm25: BM25):
+        """加权词在不同查询中的贡献都按乘数放大，同频的未加权词不变"""
+        apple = bm25.get_scores("苹果")
+        banana = bm25.get_scores("香蕉")
+        assert max(apple) == pytest.approx(max(banana))
+
+        bm25.set_term_boosts({"苹果": 2.0})
+        assert bm25.get_scores("苹果") == pytest.approx([s * 2.0 for s in apple])
+        assert bm25.get_scores("香蕉") == banana
+        for query in ["苹果 香蕉", "香蕉 苹果 西瓜", "葡萄 苹果 香蕉"]:
+            results = bm25.search(query, top_k=2)
+            assert results[0][0] in {0, 2}
+            scores = bm25.get_scores(query)
+            assert [doc_id for doc_id, _ in results] == sorted(range(5), key=lambda i: (-scores[i], i))[:2]
+
+        bm25.set_term_boosts({})
+        assert bm25.get_scores("苹果") == pytest.approx(apple)
+
+    def test_persist_and_validate(self, bm25: BM25, tmp_path):
+        """词级权重随索引保存；非法值报错"""
+        bm25.set_term_boosts({"香蕉": 3.0})
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("苹果 香蕉") == bm25.search("苹果 香蕉")
+        assert loaded.approx_eq(bm25)
+
+        with pytest.raises(ValueError, match="term boosts"):
+            bm25.set_term_boosts({"香蕉": -2.0})
+
+
+class TestBM25SearchBoosted:
+    """查询时按词加权测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        # 苹果与香蕉的文档频率、词频与文档长度完全对称
+        model.fit(["苹果 西瓜", "香蕉 西瓜", "苹果 葡萄", "香蕉 葡萄", "草莓"])
+        return model
+
+    def test_weights_scale_contributions(self, bm25: BM25):
+        """权重乘以各词的分量；全为 1 时与 search 一致"""
+        assert bm25.search_boosted([("苹果", 1.0), ("香蕉", 1.0)], top_k=4) == bm25.search("苹果 香蕉", top_k=4)
+
+        apple = dict(bm25.search("苹果", top_k=5))
+        banana = dict(bm25.search("香蕉", top_k=5))
+        results = bm25.search_boosted([("苹果", 3.0), ("香蕉", 0.5)], top_k=4)
+        assert [doc_id for doc_id, _ in results[:2]] == [0, 2]
+        for doc_id, score in results:
+            expected = apple.get(doc_id, 0.0) * 3.0 + banana.get(doc_id, 0.0) * 0.5
+            assert score == pytest.approx(expected)
+
+        single = bm25.search_boosted([("苹果", 2.0)])
+        assert single == [(doc_id, pytest.approx(score * 2.0)) for doc_id, score in bm25.search("苹果")]
+
+    def test_last_weight_wins_and_persistence(self, bm25: BM25):
+        """重复的词使用最后的权重；查询权重不影响后续搜索"""
+        once = bm25.search_boosted([("苹果", 2.0)], top_k=2)
+        assert bm25.search_boosted([("苹果", 5.0), ("苹果", 2.0)], top_k=2) == once
+        assert bm25.search("苹果", top_k=2)[0][1] == pytest.approx(once[0][1] / 2.0)
+
+    def test_invalid_weights(self, bm25: BM25):
+        """负数或非有限的权重报错"""
+        for weight in [-1.0, math.nan, math.inf]:
+            with pytest.raises(ValueError, match="term weights"):
+                bm25.search_boosted([("苹果", weight)])
+
+
+class TestBM25SearchTokens:
+    """预分词查询测试"""
+
+    def test_tokens_bypass_segmentation(self):
+        """给出的词直接查索引，不会被 jieba 再次切分"""
+        bm25 = BM25()
+        bm25.fit_weighted(
+            [
+                [("红色连衣裙", 1.0), ("夏季", 1.0)],
+                [("红色", 1.0), ("连衣裙", 1.0)],
+                [("夏季", 1.0), ("外套", 1.0)],
+            ]
+        )
+        assert [doc_id for doc_id, _ in bm25.search("红色连衣裙")] == [1]
+        assert [doc_id for doc_id, _ in bm25.search_tokens(["红色连衣裙"])] == [0]
+        assert [doc_id for doc_id, _ in bm25.search_tokens(["红色连衣裙", "外套"])] == [0, 2]
+        assert bm25.search_tokens(["榴莲"]) == []
+        assert bm25.search_tokens([]) == []
+
+    def test_matches_search(self):
+        """与 search 分出相同的词时结果一致，查询侧小写设置同样生效"""
+        bm25 = BM25(query_lowercase=True)
+        bm25.fit(["python 编程 入门", "rust 系统 编程", "java 企业 开发", "python 数据 分析"])
+        assert bm25.search_tokens(["Python", "编程"]) == bm25.search("python 编程")
+        assert bm25.search_tokens(["编程", "编程"], top_k=1) == bm25.search("编程", top_k=1)
+
+
+class TestBM25Analyze:
+    """分词调试接口测试"""
+
+    def test_matches_index_terms(self):
+        """分词结果与 fit 写入索引的词一致，包含小写化与停用词过滤"""
+        bm25 = BM25(lowercase=True, stopwords=["的"])
+        text = "Python 的 机器学习 Rust"
+        tokens = bm25.analyze(text)
+        assert "python" in tokens and "rust" in tokens
+        assert "的" not in tokens
+        assert all(not t.isspace() for t in tokens)
+
+        bm25.fit([text])
+        assert set(bm25.export_vocab()) == set(tokens)
+
+    def test_zero_weight_keeps_stopwords(self):
+        """ZeroWeight 模式下停用词仍出现在分词结果中"""
+        bm25 = BM25(stopwords=["的"], stopword_mode=StopwordMode.ZeroWeight)
+        assert bm25.analyze("苹果 的 手机") == ["苹果", "的", "手机"]
+
+    def test_query_side(self):
+        """query=True 时按查询侧设置分词"""
+        bm25 = BM25(lowercase=False, query_lowercase=True)
+        assert bm25.analyze("Apple 苹果") == ["Apple", "苹果"]
+        assert bm25.analyze("Apple 苹果", query=True) == ["apple", "苹果"]
@@ -0,0 +1,139 @@
+"""
+自定义词典测试
+"""
+
+import json
+import subprocess
+import sys
+import textwrap
+
+import pytest
+from bm25_jieba import BM25, configure_global_dict
+
+
+def run_isolated(code: str) -> subprocess.CompletedProcess:
+    """在新进程中运行，保证全局 Jieba 尚未初始化"""
+    return subprocess.run(
+        [sys.executable, "-c", textwrap.dedent(code)],
+        capture_output=True,
+        text=True,
+    )
+
+
+class TestGlobalDict:
+    """全局词典配置测试"""
+
+    @pytest.fixture
+    def dict_path(self, tmp_path) -> str:
+        path = tmp_path / "user_dict.txt"
+        path.write_text("云原生数据库 100000 n\n", encoding="utf-8")
+        return str(path)
+
+    def test_configure_before_first_use(self, dict_path: str):
+        """首次分词前配置的词典生效"""
+        result = run_isolated(f"""
+            from bm25_jieba import BM25, configure_global_dict
+            configure_global_dict({dict_path!r})
+            bm25 = BM25()
+            bm25.fit(["云原生数据库很好用", "传统数据库"])
+            hits = bm25.top_docs_per_term(["云原生数据库"])["云原生数据库"]
+            assert [doc_id for doc_id, _ in hits] == [0], hits
+        """)
+        assert result.returncode == 0, result.stderr
+
+    def test_default_dict_splits_word(self):
+        """默认词典不会把自定义词作为整体"""
+        bm25 = BM25()
+        bm25.fit(["云原生数据库很好用", "传统数据库"])
+        assert bm25.top_docs_per_term(["云原生数据库"])["云原生数据库"] == []
+
+    def test_configure_after_use_errors(self, dict_path: str):
+        """分词后再配置会明确报错"""
+        bm25 = BM25()
+        bm25.fit(["初始化全局分词器"])
+        with pytest.raises(RuntimeError, match="already initialized"):
+            configure_global_dict(dict_path)
+
+    def test_configure_twice_errors(self, dict_path: str):
+        """重复配置报错"""
+        result = run_isolated(f"""
+            from bm25_jieba import configure_global_dict
+            configure_global_dict({dict_path!r})
+            try:
+                configure_global_dict({dict_path!r})
+            except RuntimeError as e:
+                assert "already initialized" in str(e)
+            else:
+                raise AssertionError("expected RuntimeError")
+        """)
+        assert result.returncode == 0, result.stderr
+
+
+class TestBM25UserDict:
+    """实例级自定义词典测试"""
+
+    @pytest.fixture
+    def dict_path(self, tmp_path) -> str:
+        path = tmp_path / "medical_dict.txt"
+        path.write_text("阿莫西林胶囊 100000 n\n", encoding="utf-8")
+        return str(path)
+
+    def test_fit_and_search_use_user_dict(self, dict_path: str):
+        """fit 与 search 使用同一实例级词典，不影响其他实例"""
+        bm25 = BM25()
+        bm25.load_user_dict(dict_path)
+        assert bm25.analyze("阿莫西林胶囊说明书") == ["阿莫西林胶囊", "说明书"]
+        assert BM25().analyze("阿莫西林胶囊") == ["阿莫西林", "胶囊"]
+
+        bm25.fit(["阿莫西林胶囊说明书", "阿莫西林 颗粒", "胶囊 咖啡"])
+        assert [doc_id for doc_id, _ in bm25.search("阿莫西林胶囊")] == [0]
+
+    def test_user_dict_save_load(self, dict_path: str, tmp_path):
+        """词典随索引保存，加载后分词一致；多次加载的词典累加"""
+        extra = tmp_path / "extra_dict.txt"
+        extra.write_text("布洛芬缓释片 100000 n", encoding="utf-8")
+        bm25 = BM25()
+        bm25.load_user_dict(dict_path)
+        bm25.load_user_dict(str(extra))
+        bm25.fit(["阿莫西林胶囊", "布洛芬缓释片"])
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.analyze("阿莫西林胶囊和布洛芬缓释片") == ["阿莫西林胶囊", "和", "布洛芬缓释片"]
+        assert loaded.search("布洛芬缓释片") == bm25.search("布洛芬缓释片")
+
+    def test_corrupted_saved_dict_errors(self, dict_path: str, tmp_path):
+        """索引文件中保存的词典损坏时加载报错，而不是静默退回默认分词"""
+        bm25 = BM25()
+        bm25.load_user_dict(dict_path)
+        bm25.fit(["阿莫西林胶囊说明书", "阿莫西林 颗粒"])
+
+        data = bm25.to_bytes()
+        assert data.count("100000".encode()) == 1
+        corrupted = data.replace("100000".encode(), "abcdef".encode())
+        with pytest.raises(ValueError, match="user_dict"):
+            BM25.from_bytes(corrupted)
+        path = tmp_path / "bm25.bin"
+        path.write_bytes(corrupted)
+        with pytest.raises(ValueError, match="user_dict"):
+            BM25.load(str(path))
+
+        json_path = tmp_path / "bm25.json"
+        bm25.save_json(str(json_path))
+        payload = json.loads(json_path.read_text(encoding="utf-8"))
+        payload["user_dict"] = "阿莫西林胶囊 not-a-number\n"
+        json_path.write_text(json.dumps(payload, ensure_ascii=False), encoding="utf-8")
+        with pytest.raises(ValueError, match="user_dict"):
+            BM25.load_json(str(json_path))
+
+    def test_invalid_dict_errors(self, tmp_path):
+        """词典文件不存在或格式错误时报错，原有分词不变"""
+        bm25 = BM25()
+        with pytest.raises(OSError):
+            bm25.load_user_dict(str(tmp_path / "missing.txt"))
+        bad = tmp_path / "bad_dict.txt"
+        bad.write_text("阿莫西林胶囊 not-a-number\n", encoding="utf-8")
+        with pytest.raises(ValueError):
+            bm25.load_user_dict(str(bad))
+        assert bm25.analyze("阿莫西林胶囊") == ["阿莫西林", "胶囊"]
@@ -41,3 +41,39 @@ class TestBM25IDs:
         assert len(results) > 0
         doc_id, _ = results[0]
         assert doc_id == 0  # Should be index 0
+
+
+class TestBM25SearchFiltered:
+    """限定外部 ID 集合的搜索测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            [f"Python 文档 {'教程' if i % 3 == 0 else '笔记'}" for i in range(300)],
+            ids=[1000 + i for i in range(300)],
+        )
+        return model
+
+    def test_only_allowed_ids(self, bm25: BM25):
+        """只返回集合内的文档，分数与普通搜索一致"""
+        allowed = [1000, 1003, 1007, 1250, 1299]
+        results = bm25.search_filtered("Python 教程", allowed, top_k=10)
+        assert {doc_id for doc_id, _ in results} == set(allowed)
+        full = dict(bm25.search("Python 教程", top_k=300))
+        for doc_id, score in results:
+            assert score == full[doc_id]
+        assert [doc_id for doc_id, _ in results[:2]] == [1000, 1003]
+        assert bm25.search_filtered("Python 教程", allowed, top_k=2) == results[:2]
+
+    def test_unknown_and_empty(self, bm25: BM25):
+        """不在索引中的 ID 被忽略；空集合或无命中时返回空列表"""
+        assert bm25.search_filtered("Python", [9999, 1001]) == bm25.search_filtered("Python", [1001])
+        assert bm25.search_filtered("Python", []) == []
+        assert bm25.search_filtered("Rust", [1001]) == []
+
+    def test_chunked_index(self):
+        """分块索引中同一外部 ID 的各分块都参与检索"""
+        bm25 = BM25()
+        bm25.fit(["苹果 香蕉 西瓜 葡萄", "苹果 草莓"], ids=[7, 8], chunk_size=2)
+        assert [doc_id for doc_id, _ in bm25.search_filtered("西瓜", [7, 8])] == [7]
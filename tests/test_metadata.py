@@ -0,0 +1,181 @@
+"""
+文档元数据测试
+"""
+
+import pytest
+from bm25_jieba import BM25, SecondaryMode
+
+
+class TestBM25Metadata:
+    """文档元数据存储与检索测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["Python 编程语言", "Java 编程语言", "Python 数据分析"],
+            ids=[11, 12, 13],
+            metadata=[
+                {"category": "lang", "source": "wiki"},
+                {"category": "lang"},
+                {"category": "data", "source": "blog"},
+            ],
+        )
+        return model
+
+    def test_get_metadata(self, bm25: BM25):
+        """按外部 ID 获取元数据"""
+        assert bm25.get_metadata(11) == {"category": "lang", "source": "wiki"}
+        assert bm25.get_metadata(13)["category"] == "data"
+        assert bm25.get_metadata(999) is None
+
+    def test_search_with_metadata(self, bm25: BM25):
+        """搜索结果附带元数据"""
+        results = bm25.search_with_metadata("Python")
+        assert {doc_id for doc_id, _, _ in results} == {11, 13}
+        for doc_id, score, metadata in results:
+            assert score > 0
+            assert metadata == bm25.get_metadata(doc_id)
+
+        plain = bm25.search("Python")
+        assert [(d, s) for d, s, _ in results] == plain
+
+    def test_without_metadata(self):
+        """未提供元数据时返回 None"""
+        bm25 = BM25()
+        bm25.fit(["Python 编程语言"])
+        assert bm25.get_metadata(0) is None
+        assert bm25.search_with_metadata("Python")[0][2] is None
+
+    def test_metadata_length_mismatch(self):
+        """元数据长度不一致时报错"""
+        bm25 = BM25()
+        with pytest.raises(ValueError, match="documents and metadata must have the same length"):
+            bm25.fit(["A", "B"], metadata=[{}])
+
+    def test_metadata_save_load(self, bm25: BM25, tmp_path):
+        """元数据随索引持久化"""
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.get_metadata(12) == {"category": "lang"}
+
+
+class TestBM25MetadataFilter:
+    """元数据过滤回调测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            ["Python 入门", "Python 进阶", "Python 实战", "Java 入门"],
+            ids=[1, 2, 3, 4],
+            metadata=[
+                {"category": "book"},
+                {"category": "video"},
+                {"category": "book"},
+                {"category": "book"},
+            ],
+        )
+        return model
+
+    def test_filter_single_category(self, bm25: BM25):
+        """只保留指定类别的文档"""
+        results = bm25.search_with_filter("Python", lambda m: m.get("category") == "book")
+        assert {doc_id for doc_id, _ in results} == {1, 3}
+
+        unfiltered = dict(bm25.search("Python"))
+        for doc_id, score in results:
+            assert score == unfiltered[doc_id]
+
+    def test_filter_respects_top_k(self, bm25: BM25):
+        """过滤后仍能补足 Top-K"""
+        results = bm25.search_with_filter("Python", lambda m: m["category"] == "book", top_k=2)
+        assert len(results) == 2
+
+    def test_filter_error_propagates(self, bm25: BM25):
+        """回调异常应传递给调用方"""
+        def bad_filter(metadata):
+            raise RuntimeError("boom")
+
+        with pytest.raises(RuntimeError, match="boom"):
+            bm25.search_with_filter("Python", bad_filter)
+
+    def test_include_unmatched(self, bm25: BM25):
+        """过滤结果即全集: 未命中查询词的文档以 0 分排在最后"""
+        is_book = lambda m: m["category"] == "book"
+        results = bm25.search_with_filter("Python", is_book, include_unmatched=True)
+        assert [doc_id for doc_id, _ in results] == [1, 3, 4]
+        assert results[0][1] > 0 and results[1][1] > 0
+        assert results[2] == (4, 0.0)
+
+        # 完全不命中时按文档顺序返回全部过滤结果
+        results = bm25.search_with_filter("榴莲", is_book, include_unmatched=True)
+        assert results == [(1, 0.0), (3, 0.0), (4, 0.0)]
+        top2 = bm25.search_with_filter("Python", is_book, top_k=2, include_unmatched=True)
+        assert top2 == bm25.search_with_filter("Python", is_book)
+
+
+class TestBM25SecondaryRanking:
+    """次要排序键 (热度) 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(
+            [
+                "Python 编程 入门 教程",  # 略短，BM25 略高
+                "Python 编程 入门 教程 指南",
+                "Java 编程 入门",
+            ],
+            ids=[1, 2, 3],
+            metadata=[{"popularity": "10"}, {"popularity": "500"}, {"popularity": "1"}],
+        )
+        return model
+
+    def test_blend_orders_near_tie_by_popularity(self, bm25: BM25):
+        """BM25 接近的文档按热度排序"""
+        plain = [doc_id for doc_id, _ in bm25.search("Python 教程")]
+        assert plain[:2] == [1, 2]
+
+        results = bm25.search_with_secondary(
+            "Python 教程", secondary_key="popularity", mode=SecondaryMode.Blend, weight=0.001
+        )
+        assert [doc_id for doc_id, _ in results] == [2, 1]
+
+    def test_tie_break_uses_secondary_list(self):
+        """分数完全相同时按次要值降序"""
+        model = BM25()
+        model.fit(["机器学习 入门", "机器学习 教程", "深度学习 入门"], ids=[7, 8, 9])
+
+        results = model.search_with_secondary("机器学习", secondary=[1.0, 2.0, 3.0])
+        assert [doc_id for doc_id, _ in results[:2]] == [8, 7]
+        assert results[0][1] == pytest.approx(results[1][1])
+
+    def test_secondary_after_delete(self):
+        """删除文档后 secondary 仍按槽位排列 (长度为 num_slots)，与 get_scores 一致"""
+        model = BM25()
+        model.fit(["机器学习 入门", "机器学习 教程", "深度学习 入门"], ids=[7, 8, 9])
+        model.delete_document(9)
+        assert (model.corpus_size, model.num_slots) == (2, 3)
+        assert len(model.get_scores("机器学习")) == model.num_slots
+
+        with pytest.raises(ValueError, match="num_slots"):
+            model.search_with_secondary("机器学习", secondary=[1.0, 2.0])
+        results = model.search_with_secondary("机器学习", secondary=[1.0, 2.0, 0.0])
+        assert [doc_id for doc_id, _ in results] == [8, 7]
+
+        model.compact()
+        assert model.num_slots == 2
+        assert model.search_with_secondary("机器学习", secondary=[1.0, 2.0]) == results
+
+    def test_invalid_arguments(self, bm25: BM25):
+        """参数校验"""
+        with pytest.raises(ValueError, match="exactly one"):
+            bm25.search_with_secondary("Python")
+        with pytest.raises(ValueError, match="same length"):
+            bm25.search_with_secondary("Python", secondary=[1.0])
+        with pytest.raises(ValueError, match="not a number"):
+            bad = BM25()
+            bad.fit(["Python"], metadata=[{"popularity": "hot"}])
+            bad.search_with_secondary("Python", secondary_key="popularity")
@@ -0,0 +1,262 @@
+"""
+多索引联合检索测试
+"""
+
+import pytest
+from bm25_jieba import BM25, MultiBM25
+
+
+class TestMultiBM25:
+    """MultiBM25 联合检索测试"""
+
+    @pytest.fixture
+    def corpus(self) -> list[str]:
+        return [
+            "Python是一种广泛使用的高级编程语言",
+            "机器学习是人工智能的一个分支",
+            "深度学习是机器学习的子领域",
+            "自然语言处理是人工智能的重要方向",
+            "Python在机器学习领域非常流行",
+            "数据分析常用Python和R语言",
+            "计算机视觉和深度学习密切相关",
+            "编程语言有很多种类",
+            "人工智能正在改变世界",
+            "机器学习算法需要大量数据",
+        ]
+
+    def build_shards(self, corpus: list[str]) -> list[BM25]:
+        bounds = [(0, 3), (3, 7), (7, 10)]
+        shards = []
+        for start, end in bounds:
+            shard = BM25()
+            shard.fit(corpus[start:end], ids=list(range(start, end)))
+            shards.append(shard)
+        return shards
+
+    def test_matches_merged_index(self, corpus: list[str]):
+        """注入全局统计后，联合检索结果与合并索引一致"""
+        merged = BM25()
+        merged.fit(corpus)
+        multi = MultiBM25(self.build_shards(corpus))
+        assert len(multi) == 3
+
+        for query in ["机器学习", "Python 编程", "人工智能 深度学习", "不存在的词"]:
+            expected = merged.search(query, top_k=5)
+            actual = multi.search(query, top_k=5)
+            assert [doc_id for doc_id, _ in actual] == [doc_id for doc_id, _ in expected]
+            for (_, a), (_, e) in zip(actual, expected):
+                assert a == pytest.approx(e)
+
+    def test_without_global_stats(self, corpus: list[str]):
+        """不注入全局统计时各索引按自身统计打分"""
+        shards = self.build_shards(corpus)
+        local = [shard.search("机器学习", top_k=10) for shard in shards]
+        multi = MultiBM25(shards, global_stats=False)
+
+        expected = sorted((r for rs in local for r in rs), key=lambda r: -r[1])[:5]
+        assert multi.search("机器学习", top_k=5) == expected
+
+    def test_set_global_stats_validation(self):
+        """全局统计参数校验与清除"""
+        bm25 = BM25()
+        bm25.fit(["机器学习", "深度学习"])
+        before = bm25.get_scores("机器学习")
+        with pytest.raises(ValueError, match="avgdl"):
+            bm25.set_global_stats(10, 0.0, {})
+
+        bm25.set_global_stats(100, 2.0, {"机器": 1})
+        assert bm25.get_scores("机器学习") != before
+        bm25.clear_global_stats()
+        assert bm25.get_scores("机器学习") == before
+
+
+class TestBM25Merge:
+    """索引合并测试"""
+
+    @pytest.fixture
+    def corpus(self) -> list[str]:
+        return [
+            "机器 学习 算法",
+            "深度 学习 框架",
+            "机器 视觉 应用",
+            "学习 资料 分享",
+            "机器 学习 入门 教程",
+            "自然 语言 处理",
+            "深度 学习 机器 翻译",
+        ]
+
+    def test_matches_fit_on_union(self, corpus: list[str]):
+        """合并结果与对全部文档 fit 一致 (含 Block 重新分块与 Block max_score)"""
+        ids = [10 * i for i in range(len(corpus))]
+        expected = BM25(store_positions=True, block_size=2)
+        expected.fit(corpus, ids=ids)
+
+        left = BM25(store_positions=True, block_size=2)
+        left.fit(corpus[:4], ids=ids[:4])
+        right = BM25(store_positions=True, block_size=2)
+        right.fit(corpus[4:], ids=ids[4:])
+        left.merge(right)
+
+        assert left.corpus_size == len(corpus)
+        assert left.approx_eq(expected)
+        for query in ["机器 学习", "深度 学习 框架", "自然 语言"]:
+            assert left.search(query, top_k=5) == expected.search(query, top_k=5)
+        assert left.search_phrase("机器 翻译") == expected.search_phrase("机器 翻译")
+
+        # block_size 不同的索引无法按本索引的分块合并
+        coarse = BM25(store_positions=True, block_size=64)
+        coarse.fit([f"苹果 {i}" for i in range(40)], ids=list(range(1000, 1040)))
+        with pytest.raises(ValueError, match="block_size"):
+            left.merge(coarse)
+        assert left.approx_eq(expected)
+
+    def test_metadata_and_deleted(self, corpus: list[str]):
+        """元数据只有一侧提供时另一侧补空；已删除文档保持删除"""
+        left = BM25()
+        left.fit(corpus[:3], ids=[1, 2, 3], metadata=[{"src": "a"}] * 3)
+        right = BM25()
+        right.fit(corpus[3:], ids=[4, 5, 6, 7])
+        right.delete_document(5)
+        left.merge(right)
+
+        assert left.corpus_size == 6
+        assert 5 not in {doc_id for doc_id, _ in left.search("机器 学习", top_k=10)}
+        results = dict((doc_id, meta) for doc_id, _, meta in left.search_with_metadata("学习", top_k=10))
+        assert results[1] == {"src": "a"}
+        assert results[4] == {}
+
+    def test_incompatible(self, corpus: list[str]):
+        """参数不同或外部 ID 重复时报错"""
+        left = BM25()
+        left.fit(corpus[:3])
+        other = BM25(k1=1.2)
+        other.fit(corpus[3:], ids=[3, 4, 5, 6])
+        with pytest.raises(ValueError, match="same k1"):
+            left.merge(other)
+        for options in [{"use_idf": False}, {"f32_scores": True}, {"unk_df_threshold": 2}]:
+            other = BM25(**options)
+            other.fit(corpus[3:], ids=[3, 4, 5, 6])
+            with pytest.raises(ValueError, match="scoring"):
+                left.merge(other)
+
+        duplicate = BM25()
+        duplicate.fit(corpus[3:])
+        with pytest.raises(ValueError, match="already exists"):
+            left.merge(duplicate)
+        assert left.corpus_size == 3
+
+    def test_unk_collapsed_terms(self):
+        """在一侧低频、另一侧高频的单字已被合并到 <UNK> 时拒绝合并"""
+        common = BM25(unk_df_threshold=2)
+        common.fit(["猫 狗", "猫 鱼", "猫 狗 鱼"], ids=[1, 2, 3])
+        rare = BM25(unk_df_threshold=2)
+        rare.fit(["猫 狗 鱼", "狗 鱼", "狗 鱼 鸟"], ids=[4, 5, 6])
+        assert rare.search("猫") != []  # 低频的 "猫" 已映射到 <UNK>
+        with pytest.raises(ValueError, match="<UNK>"):
+            common.merge(rare)
+        assert common.corpus_size == 3
+        assert [doc_id for doc_id, _ in common.search("猫", top_k=5)] == [1, 2, 3]
+
+        # 两侧都没有低频词被合并时可以合并，结果与对全部文档 fit 一致
+        left = BM25(unk_df_threshold=2)
+        left.fit(["猫 狗", "猫 狗 鱼", "鱼 猫"], ids=[1, 2, 3])
+        right = BM25(unk_df_threshold=2)
+        right.fit(["猫 鱼", "狗 猫", "狗 鱼"], ids=[4, 5, 6])
+        expected = BM25(unk_df_threshold=2)
+        expected.fit(["猫 狗", "猫 狗 鱼", "鱼 猫", "猫 鱼", "狗 猫", "狗 鱼"], ids=[1, 2, 3, 4, 5, 6])
+        left.merge(right)
+        assert left.approx_eq(expected)
+
+
+class TestBM25VocabExport:
+    """词表统计导出与导入测试"""
+
+    @pytest.fixture
+    def corpus(self) -> list[str]:
+        return ["苹果 香蕉 苹果", "香蕉 葡萄", "西瓜 苹果", "草莓 芒果 苹果", "葡萄 葡萄 榴莲"]
+
+    def test_counts_match_postings(self, corpus: list[str]):
+        """导出的文档频率与集合频率与倒排列表一致"""
+        bm25 = BM25()
+        bm25.fit(corpus)
+        vocab = bm25.export_vocab()
+        assert vocab["苹果"] == (3, 4)
+        assert vocab["葡萄"] == (2, 3)
+        for term, (doc_count, collection_freq) in vocab.items():
+            exported_count, blocks = bm25.export_term(term)
+            postings = [posting for _, _, block in blocks for posting in block]
+            assert doc_count == exported_count == len(postings)
+            assert collection_freq == sum(freq for _, freq, _ in postings)
+        assert bm25.corpus_stats() == (5, pytest.approx(13 / 5))
+
+    def test_reimport_as_global_stats(self, corpus: list[str]):
+        """各分片的词表统计相加后注入，分数与合并索引一致"""
+        merged = BM25()
+        merged.fit(corpus)
+        shards = [BM25(), BM25()]
+        shards[0].fit(corpus[:2], ids=[0, 1])
+        shards[1].fit(corpus[2:], ids=[2, 3, 4])
+
+        vocab: dict[str, tuple[int, int]] = {}
+        corpus_size, total_length = 0, 0.0
+        for shard in shards:
+            for term, (df, cf) in shard.export_vocab().items():
+                old_df, old_cf = vocab.get(term, (0, 0))
+                vocab[term] = (old_df + df, old_cf + cf)
+            size, avgdl = shard.corpus_stats()
+            corpus_size += size
+            total_length += size * avgdl
+        assert vocab == merged.export_vocab()
+
+        for shard in shards:
+            shard.import_vocab(corpus_size, total_length / corpus_size, vocab)
+        expected = dict(merged.search("苹果 葡萄"))
+        for shard in shards:
+            for doc_id, score in shard.search("苹果 葡萄"):
+                assert score == pytest.approx(expected[doc_id])
+
+    def test_corpus_properties(self, corpus: list[str], tmp_path):
+        """只读统计属性与导出结果一致，加载后保持不变"""
+        bm25 = BM25(lowercase=True)
+        bm25.fit(corpus + ["Apple 苹果"])
+        assert bm25.corpus_size == 6
+        assert bm25.avgdl == pytest.approx(15 / 6)
+        assert bm25.vocab_size == len(bm25.export_vocab()) == 8
+        assert bm25.doc_count("苹果") == 4
+        assert bm25.doc_count("APPLE") == 1
+        assert bm25.doc_count("榴莲") == 1
+        assert bm25.doc_count("菠萝") == 0
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert (loaded.corpus_size, loaded.avgdl, loaded.vocab_size) == (6, bm25.avgdl, 8)
+
+        bm25.delete_document(5)
+        assert bm25.corpus_size == 5
+        with pytest.raises(AttributeError):
+            bm25.corpus_size = 10
+
+    def test_vocabulary_and_term_stats(self, corpus: list[str]):
+        """词表与文档频率按词排序返回，与 export_vocab 一致"""
+        bm25 = BM25()
+        bm25.fit(corpus)
+        vocab = bm25.export_vocab()
+        assert bm25.vocabulary() == sorted(vocab)
+        assert bm25.term_stats() == sorted((term, df) for term, (df, _) in vocab.items())
+        assert dict(bm25.term_stats())["苹果"] == 3
+
+        bm25.delete_document(0)
+        assert dict(bm25.term_stats())["苹果"] == 2
+        assert BM25().vocabulary() == []
+
+    def test_top_frequent_terms(self, corpus: list[str]):
+        """按文档频率降序返回前 n 个词，idf 与打分时一致"""
+        bm25 = BM25()
+        bm25.fit(corpus)
+        top = bm25.top_frequent_terms(3)
+        assert [(term, df) for term, df, _ in top] == [("苹果", 3), ("葡萄", 2), ("香蕉", 2)]
+        for term, _, idf in top:
+            assert idf == pytest.approx(bm25.explain(term, 2 if term == "苹果" else 1)[0][1])
+        assert len(bm25.top_frequent_terms(100)) == bm25.vocab_size
+        assert bm25.top_frequent_terms(0) == []
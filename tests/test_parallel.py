@@ -0,0 +1,175 @@
+"""
+并行方法与线程数配置测试
+"""
+
+import threading
+import time
+
+import pytest
+from bm25_jieba import BM25, get_num_threads, set_num_threads
+
+
+class TestBM25Parallelism:
+    """线程池配置与批量搜索测试"""
+
+    QUERIES = ["苹果", "香蕉 葡萄", "西瓜 草莓 苹果", "榴莲", "芒果 香蕉"] * 20
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "芒果"]
+        model = BM25()
+        model.fit([" ".join(words[i % 6 : i % 6 + i % 4 + 1]) for i in range(500)])
+        return model
+
+    def test_num_threads(self):
+        """线程数可设置为固定值，None 恢复全局线程池"""
+        try:
+            set_num_threads(1)
+            assert get_num_threads() == 1
+            set_num_threads(3)
+            assert get_num_threads() == 3
+            with pytest.raises(ValueError, match="num_threads"):
+                set_num_threads(0)
+        finally:
+            set_num_threads(None)
+        assert get_num_threads() >= 1
+
+    @pytest.mark.parametrize("num_threads", [1, 2, 4])
+    def test_batch_matches_sequential(self, bm25: BM25, num_threads: int):
+        """不同线程数下批量搜索结果与逐条搜索完全一致"""
+        expected = [bm25.search(query, top_k=5) for query in self.QUERIES]
+        try:
+            set_num_threads(num_threads)
+            assert bm25.search_batch(self.QUERIES, top_k=5) == expected
+        finally:
+            set_num_threads(None)
+        assert bm25.search_batch([]) == []
+
+    @pytest.mark.parametrize(
+        "options",
+        [{}, {"store_positions": True}, {"unk_df_threshold": 2}, {"chunk_size": 3}],
+    )
+    def test_parallel_fit_matches_serial(self, options: dict):
+        """并行 fit 与单线程 fit 构建的索引完全一致 (跨越多个分词批次)"""
+        options = dict(options)
+        chunk_size = options.pop("chunk_size", None)
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓", "芒果", "榴莲", "柠檬"]
+        docs = [
+            " ".join(words[(i * 7 + j * 3) % 8] for j in range(1 + i % 9)) + f" 第{i % 97}篇"
+            for i in range(9000)
+        ]
+
+        def build(num_threads: int) -> BM25:
+            model = BM25(**options)
+            try:
+                set_num_threads(num_threads)
+                model.fit(docs, chunk_size=chunk_size)
+            finally:
+                set_num_threads(None)
+            return model
+
+        serial = build(1)
+        parallel = build(4)
+        assert parallel.approx_eq(serial, 0.0)
+        assert parallel.corpus_stats() == serial.corpus_stats()
+        for term in serial.export_vocab():
+            assert parallel.export_term(term) == serial.export_term(term)
+
+
+    @pytest.mark.parametrize("num_threads", [1, 2, 4])
+    def test_parallel_get_scores_exact(self, num_threads: int):
+        """按文档区间并行累加的分数与逐词串行累加逐位一致 (跨越多个区间)"""
+        words = ["苹果", "香蕉", "葡萄", "西瓜", "草莓"]
+        docs = [
+            [(words[(i * j + i // 7) % 5], 1.0 + (i * j) % 3) for j in range(1 + i % 6)]
+            for i in range(40000)
+        ]
+        bm25 = BM25()
+        bm25.fit_weighted(docs)
+        query = "苹果 香蕉 葡萄 西瓜 苹果"
+
+        expected = [0.0] * len(docs)
+        for doc_id, score in bm25.get_nonzero_scores(query):
+            expected[doc_id] = score
+        try:
+            set_num_threads(num_threads)
+            assert bm25.get_scores(query) == expected
+        finally:
+            set_num_threads(None)
+
+
+def longest_stall(call) -> tuple[float, float]:
+    """执行 call，返回 (另一个 Python 线程最长停顿时间, call 耗时)
+
+    call 持有 GIL 时该线程在整个调用期间都无法运行，停顿接近 call 耗时
+    """
+    ticks = []
+    running = threading.Event()
+    done = threading.Event()
+
+    def ticker():
+        running.set()
+        while not done.is_set():
+            ticks.append(time.perf_counter())
+
+    thread = threading.Thread(target=ticker)
+    thread.start()
+    running.wait()
+    start = time.perf_counter()
+    call()
+    end = time.perf_counter()
+    done.set()
+    thread.join()
+    inside = [start] + [t for t in ticks if start < t < end] + [end]
+    return max(b - a for a, b in zip(inside, inside[1:])), end - start
+
+
+class TestBM25GilRelease:
+    """长时间运行的方法释放 GIL 测试"""
+
+    DOCS = [f"文档 测试 苹果 第{i}篇 {'香蕉 ' * (i % 7)}" for i in range(20000)]
+    FIT_DOCS = DOCS[:5000]
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25()
+        model.fit(self.DOCS)
+        return model
+
+    def test_search_batch_releases_gil(self, bm25: BM25):
+        """批量搜索执行期间其他 Python 线程仍能运行"""
+        queries = ["文档 测试", "苹果 香蕉", "测试 香蕉 苹果"] * 20
+        try:
+            set_num_threads(1)
+            stall, elapsed = longest_stall(lambda: bm25.search_batch(queries, top_k=10))
+        finally:
+            set_num_threads(None)
+        assert stall < elapsed / 2
+
+    def test_fit_releases_gil(self):
+        """fit 分词与建索引期间其他 Python 线程仍能运行"""
+        bm25 = BM25()
+        stall, elapsed = longest_stall(lambda: bm25.fit(self.FIT_DOCS))
+        assert stall < elapsed / 2
+        assert len(bm25.search("苹果", top_k=len(self.FIT_DOCS))) == len(self.FIT_DOCS)
+
+    def test_search_releases_gil(self, bm25: BM25):
+        """单次搜索期间其他 Python 线程仍能运行"""
+        stall, elapsed = longest_stall(
+            lambda: bm25.search("文档 测试 苹果 香蕉", top_k=len(self.DOCS))
+        )
+        assert stall < elapsed / 2
+
+    def test_reader_releases_gil(self, bm25: BM25):
+        """只读检索结构的搜索与全量打分期间其他 Python 线程仍能运行"""
+        reader = bm25.into_reader()
+        stall, elapsed = longest_stall(
+            lambda: reader.search("文档 测试 苹果 香蕉", top_k=len(self.DOCS))
+        )
+        assert stall < elapsed / 2
+        try:
+            set_num_threads(1)
+            stall, elapsed = longest_stall(lambda: reader.get_scores("文档 测试 苹果 香蕉"))
+        finally:
+            set_num_threads(None)
+        assert stall < elapsed / 2
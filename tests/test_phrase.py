@@ -0,0 +1,165 @@
+"""
+短语匹配测试
+"""
+
+import pytest
+from bm25_jieba import BM25
+
+
+class TestBM25PhraseBoost:
+    """完整短语加权测试"""
+
+    @pytest.fixture
+    def docs(self) -> list[str]:
+        return [
+            "深度 学习 框架 很多",
+            "学习 框架 深度 讨论",
+            "深度 学习 框架",
+        ]
+
+    def test_phrase_outranks_scattered(self):
+        """包含完整短语的文档排在词语分散的文档之前"""
+        bm25 = BM25(store_positions=True)
+        docs = ["红色 长款 的 连衣裙", "连衣裙 的 红色 长款"]
+        bm25.fit(docs)
+
+        # 纯词袋: 两个文档分数相同
+        plain = bm25.search("红色 长款")
+        assert plain[0][1] == plain[1][1]
+
+        boosted = bm25.search("红色 长款", phrase_boost=1.0)
+        assert len(boosted) == 2
+        # 两文档中 "红色 长款" 都连续出现，加分相同
+        assert boosted[0][1] == pytest.approx(plain[0][1] + 1.0)
+
+        boosted = bm25.search("长款 的 连衣裙", phrase_boost=1.0)
+        assert boosted[0][0] == 0
+        assert boosted[0][1] > boosted[1][1]
+
+    def test_phrase_with_require_all_terms(self):
+        """合取查询同样支持短语加权"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(["北京 天气 晴朗", "天气 预报 北京", "上海 天气"])
+        results = bm25.search("北京 天气", require_all_terms=True, phrase_boost=2.0)
+        assert [doc_id for doc_id, _ in results] == [0, 1]
+        assert results[0][1] - results[1][1] > 1.0
+
+    def test_zero_boost_matches_plain(self, docs: list[str]):
+        """phrase_boost=0 时与普通搜索一致"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(docs)
+        assert bm25.search("深度 学习 框架", phrase_boost=0.0) == bm25.search("深度 学习 框架")
+
+    def test_requires_positions(self, docs: list[str]):
+        """未存储位置时使用 phrase_boost 报错"""
+        bm25 = BM25()
+        bm25.fit(docs)
+        with pytest.raises(ValueError, match="store_positions"):
+            bm25.search("深度 学习", phrase_boost=1.0)
+
+    def test_positions_save_load(self, docs: list[str], tmp_path):
+        """位置信息随索引持久化"""
+        bm25 = BM25(store_positions=True)
+        bm25.fit(docs)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        query = "深度 学习 框架"
+        assert loaded.search(query, phrase_boost=1.5) == bm25.search(query, phrase_boost=1.5)
+
+
+class TestBM25SearchNear:
+    """邻近查询 (slop 窗口) 测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(store_positions=True)
+        model.fit(
+            [
+                "苹果 香蕉 西瓜",  # 相邻
+                "苹果 西瓜 香蕉",  # 间隔 1
+                "香蕉 西瓜 草莓 苹果",  # 逆序，间隔 2
+                "苹果 西瓜 草莓 葡萄 香蕉",  # 间隔 3
+            ]
+        )
+        return model
+
+    @staticmethod
+    def ids(results) -> set[int]:
+        return {doc_id for doc_id, _ in results}
+
+    @pytest.mark.parametrize(
+        "slop, expected",
+        [(0, {0}), (1, {0, 1}), (2, {0, 1}), (3, {0, 1, 3})],
+    )
+    def test_ordered(self, bm25: BM25, slop: int, expected: set[int]):
+        """有序匹配: 逆序的文档始终不命中"""
+        results = bm25.search_near(["苹果", "香蕉"], slop=slop, ordered=True)
+        assert self.ids(results) == expected
+
+    @pytest.mark.parametrize(
+        "slop, expected",
+        [(0, {0}), (1, {0, 1}), (2, {0, 1, 2}), (3, {0, 1, 2, 3})],
+    )
+    def test_unordered(self, bm25: BM25, slop: int, expected: set[int]):
+        """无序匹配: 逆序的文档在窗口足够大时命中"""
+        results = bm25.search_near(["苹果", "香蕉"], slop=slop, ordered=False)
+        assert self.ids(results) == expected
+
+    def test_three_terms(self, bm25: BM25):
+        """多个词的窗口跨度"""
+        terms = ["西瓜", "苹果", "香蕉"]
+        assert self.ids(bm25.search_near(terms, slop=0, ordered=False)) == {0, 1}
+        assert self.ids(bm25.search_near(terms, slop=1, ordered=False)) == {0, 1, 2}
+        assert self.ids(bm25.search_near(terms, slop=2, ordered=False)) == {0, 1, 2, 3}
+        assert self.ids(bm25.search_near(["苹果", "西瓜", "香蕉"], slop=0)) == {1}
+
+    def test_scores_and_errors(self, bm25: BM25):
+        """分数为各词 BM25 之和；缺失词无结果；未存储位置时报错"""
+        results = bm25.search_near(["苹果", "香蕉"], slop=0)
+        assert results[0][1] == pytest.approx(bm25.get_scores("苹果 香蕉")[0])
+        assert bm25.search_near(["苹果", "榴莲"], slop=5) == []
+
+        plain = BM25()
+        plain.fit(["苹果 香蕉"])
+        with pytest.raises(ValueError, match="store_positions"):
+            plain.search_near(["苹果", "香蕉"], slop=1)
+
+
+class TestBM25SearchPhrase:
+    """短语查询测试"""
+
+    @pytest.fixture
+    def bm25(self) -> BM25:
+        model = BM25(store_positions=True, stopwords=["的"])
+        model.fit(
+            [
+                "红色 长款 连衣裙",
+                "长款 红色 连衣裙",  # 逆序
+                "红色 的 长款 外套",  # 停用词被移除，不占位置
+                "红色 短款 长款 裙子",  # 中间隔了一个词
+            ],
+            ids=[10, 20, 30, 40],
+        )
+        return model
+
+    def test_only_consecutive_matches(self, bm25: BM25):
+        """只返回各词按顺序连续出现的文档，分数为各词 BM25 之和"""
+        results = bm25.search_phrase("红色 长款")
+        assert {doc_id for doc_id, _ in results} == {10, 30}
+        scores = dict(bm25.search("红色 长款", top_k=10))
+        for doc_id, score in results:
+            assert score == pytest.approx(scores[doc_id])
+
+        assert [doc_id for doc_id, _ in bm25.search_phrase("长款 红色 连衣裙")] == [20]
+        assert bm25.search_phrase("红色 长款", top_k=1) == results[:1]
+
+    def test_missing_terms_and_errors(self, bm25: BM25):
+        """短语中有词不在索引中时无结果；未存储位置时报错"""
+        assert bm25.search_phrase("红色 榴莲") == []
+        assert bm25.search_phrase("") == []
+
+        plain = BM25()
+        plain.fit(["红色 长款"])
+        with pytest.raises(ValueError, match="store_positions"):
+            plain.search_phrase("红色 长款")
@@ -0,0 +1,75 @@
+"""
+停用词测试
+"""
+
+import pytest
+from bm25_jieba import BM25, StopwordMode
+
+
+class TestBM25StopwordMode:
+    """停用词处理方式测试"""
+
+    @pytest.fixture
+    def docs(self) -> list[str]:
+        return ["苹果 的 手机", "苹果 手机 的 价格", "的 的 的"]
+
+    def test_remove_mode_drops_stopwords(self, docs: list[str]):
+        """Remove 模式下停用词不进入索引"""
+        bm25 = BM25(stopwords=["的"])
+        bm25.fit(docs)
+        assert bm25.search("的") == []
+        assert bm25.get_scores("的") == [0.0, 0.0, 0.0]
+
+    def test_zero_weight_contributes_nothing(self, docs: list[str]):
+        """ZeroWeight 模式下停用词不贡献分数"""
+        bm25 = BM25(stopwords=["的"], stopword_mode=StopwordMode.ZeroWeight)
+        bm25.fit(docs)
+        assert bm25.search("的") == []
+        assert bm25.get_scores("苹果 的") == bm25.get_scores("苹果")
+        assert bm25.search("苹果 的") == bm25.search("苹果")
+
+    def test_zero_weight_keeps_doc_len(self, docs: list[str]):
+        """ZeroWeight 模式下停用词仍计入文档长度"""
+        removed = BM25(stopwords=["的"])
+        removed.fit(docs)
+        kept = BM25(stopwords=["的"], stopword_mode=StopwordMode.ZeroWeight)
+        kept.fit(docs)
+        # 文档长度不同，分数随之不同
+        assert removed.get_scores("苹果") != kept.get_scores("苹果")
+
+    def test_zero_weight_phrase_spanning_stopword(self, docs: list[str]):
+        """ZeroWeight 模式下跨越停用词的短语仍然匹配"""
+        bm25 = BM25(
+            store_positions=True,
+            stopwords=["的"],
+            stopword_mode=StopwordMode.ZeroWeight,
+        )
+        bm25.fit(docs)
+        plain = dict(bm25.search("苹果 的 手机"))
+        boosted = dict(bm25.search("苹果 的 手机", phrase_boost=1.0))
+        # 文档 0 包含完整短语 "苹果 的 手机"，文档 1 不包含
+        assert boosted[0] == pytest.approx(plain[0] + 1.0)
+        assert boosted[1] == plain[1]
+
+    def test_stopword_mode_save_load(self, docs: list[str], tmp_path):
+        """停用词与模式随索引持久化"""
+        bm25 = BM25(stopwords=["的"], stopword_mode=StopwordMode.ZeroWeight)
+        bm25.fit(docs)
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.search("苹果 的") == bm25.search("苹果 的")
+        assert loaded.search("的") == []
+
+    def test_set_stopwords_persists_tokenization(self, tmp_path):
+        """set_stopwords 设置的停用词 (按 lowercase 转小写) 随索引保存，加载后分词一致"""
+        bm25 = BM25(lowercase=True)
+        bm25.set_stopwords(["的", "THE"])
+        bm25.fit(["The 苹果 的 手机"])
+        assert bm25.analyze("The 苹果 的 手机") == ["苹果", "手机"]
+
+        path = tmp_path / "bm25.bin"
+        bm25.save(str(path))
+        loaded = BM25.load(str(path))
+        assert loaded.analyze("the 苹果 的 手机") == bm25.analyze("the 苹果 的 手机")
+        assert loaded.search("the") == []